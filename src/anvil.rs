@@ -0,0 +1,849 @@
+#![cfg(feature = "anvil")]
+
+use crate::world::{WorldSyncer, Block, Chunk, WorldError, WorldMeta};
+use crate::biome::{self, BiomeId};
+use crate::Vector3I;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write, Read, Cursor};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use byteorder::{WriteBytesExt, ReadBytesExt, BigEndian};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use nbt::{Blob, Tag, NBTRead, NBTWrite};
+use crate::heightmap::{ChunkHeightmaps, ColumnHeightmaps, HeightmapKind};
+use crate::light::LightArray;
+
+/// Data version written into columns we create (Minecraft 1.17).
+const DATA_VERSION: i32 = 2724;
+const SECTOR_SIZE: usize = 4096;
+/// The location table and the timestamp table each take one sector.
+const HEADER_SECTORS: usize = 2;
+
+/// Sections in a vanilla 1.17 overworld column, which runs from Y 0 to 256. Taller dimensions
+/// have more; see `WorldSyncer::set_height`.
+const COLUMN_SECTIONS: i32 = 16;
+/// Columns whose section heightmaps are kept between saves.
+const CACHED_COLUMNS: usize = 256;
+
+const COMPRESSION_GZIP: u8 = 1;
+const COMPRESSION_ZLIB: u8 = 2;
+const COMPRESSION_NONE: u8 = 3;
+/// Set on the compression byte when the payload lives in a `c.<x>.<z>.mcc` file instead.
+const COMPRESSION_EXTERNAL: u8 = 128;
+
+/// Reads and writes a vanilla `world/region` directory.
+///
+/// Anvil stores whole 16-wide columns while Grimstone chunks are 16x16x16 cubes, so a Grimstone
+/// chunk at `(x, y, z)` is the section with `Y = y` inside the column at `(x, z)`. Blocks are
+/// matched on their name alone and blocks Grimstone doesn't know about are read as air, but
+/// saving keeps the stored state, properties and all, of every block that hasn't changed since.
+/// World-wide data like the seed lives in the `level.dat` next to the region directory.
+pub struct AnvilWorld {
+    dir: PathBuf,
+    regions: HashMap<(i32, i32), RegionFile>,
+    /// Metadata waiting to be written into `level.dat` on the next flush.
+    meta: Option<WorldMeta>,
+    /// Section Y of the bottom of a column.
+    min_section: i32,
+    /// How many sections tall a column is, which sizes its biome array.
+    sections: i32,
+    /// Opened with `open_read_only`; nothing in the directory is created or written.
+    read_only: bool,
+    /// The heightmaps of every section of recently saved columns, bottom up, so saving a section
+    /// doesn't have to decode the rest of its column again.
+    section_heightmaps: HashMap<(i32, i32), Vec<ChunkHeightmaps>>
+}
+
+struct RegionFile {
+    file: File,
+    /// `sector offset << 8 | sector count` for each of the 32x32 columns; 0 when absent.
+    locations: [u32; 1024],
+    /// Which sectors of the file are occupied by the header or by a column.
+    sectors: Vec<bool>
+}
+
+impl RegionFile {
+    fn open(path: &Path, read_only: bool) -> Result<RegionFile, WorldError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(!read_only)
+            .create(!read_only)
+            .truncate(false)
+            .open(path)?;
+
+        // a file cut short before its header was written has no columns yet
+        let len = file.metadata()?.len() as usize;
+        let mut locations = [0u32; 1024];
+        if len < HEADER_SECTORS * SECTOR_SIZE {
+            if !read_only {
+                file.set_len((HEADER_SECTORS * SECTOR_SIZE) as u64)?;
+            }
+        } else {
+            file.seek(SeekFrom::Start(0))?;
+            file.read_u32_into::<BigEndian>(&mut locations)?;
+        }
+
+        let mut sectors = vec![false; len.div_ceil(SECTOR_SIZE).max(HEADER_SECTORS)];
+        for s in sectors.iter_mut().take(HEADER_SECTORS) { *s = true; }
+        for (i, loc) in locations.iter().enumerate() {
+            let (offset, count) = ((loc >> 8) as usize, (loc & 0xFF) as usize);
+            if *loc == 0 { continue; }
+            if offset < HEADER_SECTORS || offset + count > sectors.len() {
+                log::warn!("Column {} of {} points outside the file", i, path.display());
+                continue;
+            }
+            for s in &mut sectors[offset..offset + count] { *s = true; }
+        }
+
+        Ok(RegionFile { file, locations, sectors })
+    }
+
+    /// Returns the uncompressed NBT of a column, or `None` if it was never written.
+    fn read(&mut self, index: usize, external: &Path) -> Result<Option<Vec<u8>>, WorldError> {
+        let loc = self.locations[index];
+        if loc == 0 { return Ok(None); }
+
+        let (offset, count) = ((loc >> 8) as usize, (loc & 0xFF) as usize);
+        self.file.seek(SeekFrom::Start((offset * SECTOR_SIZE) as u64))?;
+        let len = self.file.read_u32::<BigEndian>()? as usize;
+        if len == 0 || len + 4 > count * SECTOR_SIZE {
+            return Err(WorldError::Corrupt(format!("column {} has a bad length of {}", index, len)));
+        }
+        let compression = self.file.read_u8()?;
+
+        let data = if compression & COMPRESSION_EXTERNAL != 0 {
+            std::fs::read(external)?
+        } else {
+            let mut data = vec![0u8; len - 1];
+            self.file.read_exact(&mut data)?;
+            data
+        };
+
+        let mut out = vec![];
+        match compression & !COMPRESSION_EXTERNAL {
+            COMPRESSION_GZIP => { GzDecoder::new(data.as_slice()).read_to_end(&mut out)?; }
+            COMPRESSION_ZLIB => { ZlibDecoder::new(data.as_slice()).read_to_end(&mut out)?; }
+            COMPRESSION_NONE => out = data,
+            other => return Err(WorldError::Corrupt(format!("column {} uses unknown compression {}", index, other)))
+        }
+        Ok(Some(out))
+    }
+
+    fn write(&mut self, index: usize, nbt: &[u8], external: &Path) -> Result<(), WorldError> {
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(nbt)?;
+        let mut payload = encoder.finish()?;
+        let mut compression = COMPRESSION_ZLIB;
+
+        // Columns over 1 MiB don't fit in the 8-bit sector count and go to their own file.
+        if (payload.len() + 5).div_ceil(SECTOR_SIZE) > 255 {
+            std::fs::write(external, &payload)?;
+            payload.clear();
+            compression |= COMPRESSION_EXTERNAL;
+        } else if external.exists() {
+            std::fs::remove_file(external)?;
+        }
+
+        let needed = (payload.len() + 5).div_ceil(SECTOR_SIZE);
+        let offset = self.allocate(index, needed);
+
+        self.file.seek(SeekFrom::Start((offset * SECTOR_SIZE) as u64))?;
+        self.file.write_u32::<BigEndian>(payload.len() as u32 + 1)?;
+        self.file.write_u8(compression)?;
+        self.file.write_all(&payload)?;
+        let padding = needed * SECTOR_SIZE - payload.len() - 5;
+        self.file.write_all(&vec![0u8; padding])?;
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        self.locations[index] = (offset as u32) << 8 | needed as u32;
+        self.file.seek(SeekFrom::Start((index * 4) as u64))?;
+        self.file.write_u32::<BigEndian>(self.locations[index])?;
+        self.file.seek(SeekFrom::Start((SECTOR_SIZE + index * 4) as u64))?;
+        self.file.write_u32::<BigEndian>(timestamp as u32)?;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    /// Finds room for `needed` sectors, reusing the column's current sectors when they're big
+    /// enough and otherwise the first free run, growing the file as a last resort.
+    fn allocate(&mut self, index: usize, needed: usize) -> usize {
+        let loc = self.locations[index];
+        let (old_offset, old_count) = ((loc >> 8) as usize, (loc & 0xFF) as usize);
+        if loc != 0 && old_offset + old_count <= self.sectors.len() {
+            for s in &mut self.sectors[old_offset..old_offset + old_count] { *s = false; }
+            if old_count >= needed {
+                for s in &mut self.sectors[old_offset..old_offset + needed] { *s = true; }
+                return old_offset;
+            }
+        }
+
+        let mut run = 0;
+        let mut offset = self.sectors.len();
+        for (i, used) in self.sectors.iter().enumerate() {
+            if *used {
+                run = 0;
+            } else {
+                run += 1;
+                if run == needed {
+                    offset = i + 1 - needed;
+                    break;
+                }
+            }
+        }
+
+        if offset + needed > self.sectors.len() {
+            self.sectors.resize(offset + needed, false);
+        }
+        for s in &mut self.sectors[offset..offset + needed] { *s = true; }
+        offset
+    }
+}
+
+impl AnvilWorld {
+    fn region(&mut self, x: i32, z: i32, create: bool) -> Result<Option<&mut RegionFile>, WorldError> {
+        let key = (x >> 5, z >> 5);
+        if !self.regions.contains_key(&key) {
+            let path = self.dir.join(format!("r.{}.{}.mca", key.0, key.1));
+            if !create && !path.exists() {
+                return Ok(None);
+            }
+            self.regions.insert(key, RegionFile::open(&path, self.read_only)?);
+        }
+        Ok(self.regions.get_mut(&key))
+    }
+
+    fn level_dat(&self) -> PathBuf {
+        self.dir.parent().unwrap_or_else(|| Path::new(".")).join("level.dat")
+    }
+
+    fn read_level(&self) -> Result<Option<Blob>, WorldError> {
+        let path = self.level_dat();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let mut bytes = vec![];
+        GzDecoder::new(File::open(&path)?).read_to_end(&mut bytes)?;
+        Blob::read(&mut Cursor::new(bytes))
+            .map(Some)
+            .map_err(|e| WorldError::Corrupt(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Puts the seed into `level.dat`, keeping whatever else is there. The old file is kept as
+    /// `level.dat_old` like vanilla does.
+    fn write_level(&self, meta: &WorldMeta) -> Result<(), WorldError> {
+        let mut level = self.read_level()?.unwrap_or_else(Blob::new);
+        let data = match level.elements.entry("Data".to_string())
+            .or_insert_with(|| Tag::Compound(HashMap::new())) {
+            Tag::Compound(data) => data,
+            _ => return Err(WorldError::Corrupt("level.dat Data is not a compound".to_string()))
+        };
+        data.entry("DataVersion".to_string()).or_insert(Tag::Int(DATA_VERSION));
+        if let Some(seed) = meta.seed {
+            match data.entry("WorldGenSettings".to_string())
+                .or_insert_with(|| Tag::Compound(HashMap::new())) {
+                Tag::Compound(settings) => { settings.insert("seed".to_string(), Tag::Long(seed)); }
+                _ => return Err(WorldError::Corrupt("level.dat WorldGenSettings is not a compound".to_string()))
+            }
+        }
+
+        let path = self.level_dat();
+        let bytes = level.bytes().map_err(|e| WorldError::Corrupt(format!("{}: {}", path.display(), e)))?;
+        let new = path.with_file_name("level.dat_new");
+        let mut encoder = GzEncoder::new(File::create(&new)?, Compression::default());
+        encoder.write_all(&bytes)?;
+        encoder.finish()?.sync_all()?;
+        if path.exists() {
+            std::fs::rename(&path, path.with_file_name("level.dat_old"))?;
+        }
+        std::fs::rename(&new, &path)?;
+        Ok(())
+    }
+
+    fn external_path(&self, x: i32, z: i32) -> PathBuf {
+        self.dir.join(format!("c.{}.{}.mcc", x, z))
+    }
+
+    fn read_column(&mut self, x: i32, z: i32) -> Result<Option<Blob>, WorldError> {
+        let external = self.external_path(x, z);
+        let bytes = match self.region(x, z, false)? {
+            Some(region) => region.read(column_index(x, z), &external)?,
+            None => None
+        };
+
+        match bytes {
+            Some(bytes) => Blob::read(&mut Cursor::new(bytes))
+                .map(Some)
+                .map_err(|e| WorldError::Corrupt(format!("column [{},{}]: {}", x, z, e))),
+            None => Ok(None)
+        }
+    }
+
+    fn write_column(&mut self, x: i32, z: i32, column: &Blob) -> Result<(), WorldError> {
+        if self.read_only {
+            return Err(WorldError::ReadOnly);
+        }
+        let external = self.external_path(x, z);
+        let bytes = column.bytes()
+            .map_err(|e| WorldError::Corrupt(format!("column [{},{}]: {}", x, z, e)))?;
+        self.region(x, z, true)?
+            .expect("region is created on demand")
+            .write(column_index(x, z), &bytes, &external)
+    }
+}
+
+fn column_index(x: i32, z: i32) -> usize {
+    ((x & 31) + (z & 31) * 32) as usize
+}
+
+fn new_column(x: i32, z: i32) -> Blob {
+    let mut level = HashMap::new();
+    level.insert("xPos".to_string(), Tag::Int(x));
+    level.insert("zPos".to_string(), Tag::Int(z));
+    level.insert("Status".to_string(), Tag::String("full".to_string()));
+    level.insert("LastUpdate".to_string(), Tag::Long(0));
+    level.insert("InhabitedTime".to_string(), Tag::Long(0));
+    level.insert("Sections".to_string(), Tag::List(vec![]));
+    level.insert("TileEntities".to_string(), Tag::List(vec![]));
+    level.insert("Entities".to_string(), Tag::List(vec![]));
+
+    let mut blob = Blob::new();
+    blob.insert("DataVersion", DATA_VERSION);
+    blob.insert("Level", Tag::Compound(level));
+    blob
+}
+
+/// Biomes are kept for the whole column, in 4x4x4 cells from the bottom of the world up, by their
+/// registry ids, which are vanilla's for vanilla biomes. `offset` is the first cell of the chunk.
+fn decode_biomes(chunk: &mut Chunk, biomes: &[i32], offset: usize) {
+    for (i, id) in biomes[offset..offset + 64].iter().enumerate() {
+        chunk.biomes[i] = if *id >= 0 && biome::name_of(*id as BiomeId).is_some() {
+            *id as BiomeId
+        } else {
+            biome::default_id()
+        };
+    }
+}
+
+fn encode_biomes(chunk: &Chunk, biomes: &mut [i32], offset: usize) {
+    for (i, id) in chunk.biomes.iter().enumerate() {
+        biomes[offset + i] = *id as i32;
+    }
+}
+
+impl AnvilWorld {
+    fn biome_cells(&self) -> usize {
+        self.sections as usize * 64
+    }
+
+    fn has_section(&self, y: i32) -> bool {
+        (self.min_section..self.min_section + self.sections).contains(&y)
+    }
+
+    /// Vanilla's column `Heightmaps` for a column with `sections`, where `chunk` is one of them.
+    /// The other sections are only decoded the first time their column is saved.
+    fn column_heightmaps(&mut self, chunk: &Chunk, sections: &[Tag]) -> Tag {
+        let key = (chunk.x, chunk.z);
+        if !self.section_heightmaps.contains_key(&key) {
+            if self.section_heightmaps.len() >= CACHED_COLUMNS {
+                self.section_heightmaps.clear();
+            }
+            let mut maps = vec![];
+            for y in self.min_section..self.min_section + self.sections {
+                let mut section = Chunk::new_empty(chunk.x, y, chunk.z);
+                if let Some(Tag::Compound(s)) = sections.iter().find(|s| y != chunk.y && section_y(s) == Some(y as i8)) {
+                    // a section that can't be read is left out, like loading would
+                    if decode_section(&mut section, s).is_err() {
+                        section = Chunk::new_empty(chunk.x, y, chunk.z);
+                    }
+                }
+                maps.push(section.heightmaps().clone());
+            }
+            self.section_heightmaps.insert(key, maps);
+        }
+
+        let index = self.has_section(chunk.y).then(|| (chunk.y - self.min_section) as usize);
+        let height = self.sections as i64 * 16;
+        let maps = self.section_heightmaps.get_mut(&key).unwrap();
+        if let Some(index) = index {
+            maps[index] = chunk.heightmaps().clone();
+        }
+        let column = ColumnHeightmaps::combine(&maps.iter().collect::<Vec<_>>(), height);
+
+        let mut heightmaps = HashMap::new();
+        for kind in HeightmapKind::ALL {
+            heightmaps.insert(kind.name().to_string(), Tag::LongArray(column.get(kind).longs().to_vec()));
+        }
+        Tag::Compound(heightmaps)
+    }
+}
+
+fn level_of(column: &mut Blob, x: i32, z: i32) -> Result<&mut HashMap<String, Tag>, WorldError> {
+    match column.elements.get_mut("Level") {
+        Some(Tag::Compound(level)) => Ok(level),
+        _ => Err(WorldError::Corrupt(format!("column [{},{}] has no Level", x, z)))
+    }
+}
+
+fn section_y(section: &Tag) -> Option<i8> {
+    match section {
+        Tag::Compound(s) => match s.get("Y") {
+            Some(Tag::Byte(y)) => Some(*y),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// Bits per block state, as vanilla picks them for a section palette of `len` entries.
+fn bits_for(len: usize) -> usize {
+    let bits = (usize::BITS - len.saturating_sub(1).leading_zeros()) as usize;
+    bits.max(4)
+}
+
+fn decode_section(chunk: &mut Chunk, section: &HashMap<String, Tag>) -> Result<(), WorldError> {
+    let (x, y, z) = (chunk.x, chunk.y, chunk.z);
+    let corrupt = |why: &str| WorldError::Corrupt(format!("section [{},{},{}] {}", x, y, z, why));
+
+    if let (Some(Tag::ByteArray(sky)), Some(Tag::ByteArray(block))) = (section.get("SkyLight"), section.get("BlockLight")) {
+        let light = |bytes: &Vec<i8>| LightArray::from_bytes(&bytes.iter().map(|b| *b as u8).collect::<Vec<_>>());
+        if let (Some(sky), Some(block)) = (light(sky), light(block)) {
+            chunk.sky_light = sky;
+            chunk.block_light = block;
+            chunk.lit = true;
+        }
+    }
+
+    let (palette, indices) = match read_states(section).map_err(&corrupt)? {
+        Some(states) => states,
+        None => return Ok(()) // light-only section; all air
+    };
+    let blocks = palette_blocks(palette).map_err(corrupt)?;
+    let unknown = blocks.iter().filter(|b| b.is_none()).count();
+    if unknown > 0 {
+        log::warn!("Section [{},{},{}] has {} unknown block types; reading them as air", x, y, z, unknown);
+    }
+
+    for (i, index) in indices.into_iter().enumerate() {
+        chunk.blocks[i] = blocks[index].unwrap_or(Block::Air);
+    }
+    chunk.recompute_heightmaps();
+    Ok(())
+}
+
+/// A section's palette and which entry of it each block is.
+type SectionStates<'a> = (&'a [Tag], Vec<usize>);
+
+/// The states of a section's blocks, or nothing for a section that has no blocks stored.
+fn read_states(section: &HashMap<String, Tag>) -> Result<Option<SectionStates<'_>>, &'static str> {
+    let palette = match section.get("Palette") {
+        Some(Tag::List(entries)) if !entries.is_empty() => entries,
+        Some(Tag::List(_)) => return Err("has an empty palette"),
+        _ => return Ok(None)
+    };
+    if palette.len() == 1 {
+        return Ok(Some((palette, vec![0; 16 * 16 * 16])));
+    }
+
+    let states = match section.get("BlockStates") {
+        Some(Tag::LongArray(states)) => states,
+        _ => return Err("has a palette but no block states")
+    };
+    let bits = bits_for(palette.len());
+    let per_long = 64 / bits;
+    if states.len() * per_long < 16 * 16 * 16 {
+        return Err("has too few block states");
+    }
+
+    let mask = (1u64 << bits) - 1;
+    let mut indices = Vec::with_capacity(16 * 16 * 16);
+    for i in 0..16 * 16 * 16 {
+        let value = ((states[i / per_long] as u64 >> ((i % per_long) * bits)) & mask) as usize;
+        if value >= palette.len() {
+            return Err("refers past its palette");
+        }
+        indices.push(value);
+    }
+    Ok(Some((palette, indices)))
+}
+
+/// The block each palette entry is, or None for blocks Grimstone doesn't know about.
+fn palette_blocks(palette: &[Tag]) -> Result<Vec<Option<Block>>, &'static str> {
+    palette.iter().map(|entry| match entry {
+        Tag::Compound(e) => match e.get("Name") {
+            Some(Tag::String(name)) => Ok(Block::from_name(name)),
+            _ => Err("has a palette entry without a name")
+        },
+        _ => Err("has a malformed palette")
+    }).collect()
+}
+
+/// The section as vanilla stores it. Blocks that are still what `stored`, the section as it was
+/// saved before, had there keep their stored palette entry, so their properties and blocks
+/// Grimstone reads as air aren't lost.
+fn encode_section(chunk: &Chunk, stored: Option<HashMap<String, Tag>>) -> Tag {
+    /// Where a palette entry comes from.
+    #[derive(PartialEq)]
+    enum Entry {
+        Stored(usize),
+        New(Block)
+    }
+
+    let mut stored_palette = stored.and_then(|mut section| {
+        let (blocks, indices) = match read_states(&section) {
+            Ok(Some((palette, indices))) => (palette_blocks(palette).ok()?, indices),
+            _ => return None
+        };
+        match section.remove("Palette") {
+            Some(Tag::List(entries)) => Some((entries.into_iter().map(Some).collect::<Vec<_>>(), blocks, indices)),
+            _ => None
+        }
+    });
+
+    let mut palette: Vec<Entry> = vec![];
+    let mut indices = Vec::with_capacity(16 * 16 * 16);
+    for (i, block) in chunk.blocks.iter().enumerate() {
+        let entry = match &stored_palette {
+            Some((_, blocks, stored)) if blocks[stored[i]].unwrap_or(Block::Air) == *block => Entry::Stored(stored[i]),
+            _ => Entry::New(*block)
+        };
+        let i = match palette.iter().position(|e| *e == entry) {
+            Some(i) => i,
+            None => {
+                palette.push(entry);
+                palette.len() - 1
+            }
+        };
+        indices.push(i as u64);
+    }
+    let bits = bits_for(palette.len());
+    let per_long = 64 / bits;
+    let mut states = vec![0i64; (16 * 16 * 16usize).div_ceil(per_long)];
+    for (i, value) in indices.into_iter().enumerate() {
+        states[i / per_long] |= (value << ((i % per_long) * bits)) as i64;
+    }
+
+    let palette = palette.into_iter().map(|entry| match entry {
+        Entry::Stored(i) => stored_palette.as_mut().and_then(|(entries, _, _)| entries[i].take())
+            .expect("each stored entry is only used once"),
+        Entry::New(block) => {
+            let mut entry = HashMap::new();
+            entry.insert("Name".to_string(), Tag::String(block.name().to_string()));
+            Tag::Compound(entry)
+        }
+    }).collect();
+
+    let mut section = HashMap::new();
+    section.insert("Y".to_string(), Tag::Byte(chunk.y as i8));
+    section.insert("Palette".to_string(), Tag::List(palette));
+    section.insert("BlockStates".to_string(), Tag::LongArray(states));
+    if chunk.lit {
+        section.insert("SkyLight".to_string(), Tag::ByteArray(chunk.sky_light.bytes().iter().map(|b| *b as i8).collect()));
+        section.insert("BlockLight".to_string(), Tag::ByteArray(chunk.block_light.bytes().iter().map(|b| *b as i8).collect()));
+    }
+    Tag::Compound(section)
+}
+
+impl WorldSyncer for AnvilWorld {
+    fn new(path: &str) -> Result<Self, WorldError> where Self: Sized {
+        std::fs::create_dir_all(path)?;
+        Ok(AnvilWorld {
+            dir: PathBuf::from(path),
+            regions: HashMap::new(),
+            meta: None,
+            min_section: 0,
+            sections: COLUMN_SECTIONS,
+            read_only: false,
+            section_heightmaps: HashMap::new()
+        })
+    }
+
+    /// The region directory has to exist already.
+    fn open_read_only(path: &str) -> Result<Self, WorldError> where Self: Sized {
+        if !Path::new(path).is_dir() {
+            return Err(WorldError::Io(std::io::Error::new(std::io::ErrorKind::NotFound,
+                format!("{} is not a directory", path))));
+        }
+        Ok(AnvilWorld {
+            dir: PathBuf::from(path),
+            regions: HashMap::new(),
+            meta: None,
+            min_section: 0,
+            sections: COLUMN_SECTIONS,
+            read_only: true,
+            section_heightmaps: HashMap::new()
+        })
+    }
+
+    fn save(&mut self, chunk: &mut Chunk) -> Result<(), WorldError> {
+        if chunk.y < i8::MIN as i32 || chunk.y > i8::MAX as i32 {
+            return Err(WorldError::Corrupt(format!("chunk [{},{},{}] is outside what Anvil can store", chunk.x, chunk.y, chunk.z)));
+        }
+
+        let mut column = self.read_column(chunk.x, chunk.z)?
+            .unwrap_or_else(|| new_column(chunk.x, chunk.z));
+        let level = level_of(&mut column, chunk.x, chunk.z)?;
+
+        let mut sections = match level.remove("Sections") {
+            Some(Tag::List(sections)) => sections,
+            _ => vec![]
+        };
+        let stored = sections.iter().position(|s| section_y(s) == Some(chunk.y as i8)).map(|i| sections.remove(i));
+        let section = match stored {
+            Some(Tag::Compound(stored)) => encode_section(chunk, Some(stored)),
+            _ => encode_section(chunk, None)
+        };
+        sections.retain(|s| section_y(s) != Some(chunk.y as i8));
+        sections.push(section);
+        let mut entities = match level.remove("TileEntities") {
+            Some(Tag::List(entities)) => entities,
+            _ => vec![]
+        };
+        entities.retain(|e| match e {
+            Tag::Compound(e) => !matches!(e.get("y"), Some(Tag::Int(y)) if y.div_euclid(16) == chunk.y),
+            _ => false
+        });
+        for ((x, y, z), entity) in chunk.block_entities() {
+            let (x, y, z) = chunk.world_pos(*x, *y, *z);
+            entities.push(Tag::Compound(entity.to_nbt(x, y, z)));
+        }
+        level.insert("TileEntities".to_string(), Tag::List(entities));
+        let heightmaps = self.column_heightmaps(chunk, &sections);
+        level.insert("Sections".to_string(), Tag::List(sections));
+        level.insert("Heightmaps".to_string(), heightmaps);
+        if self.has_section(chunk.y) {
+            let cells = self.biome_cells();
+            let mut biomes = match level.remove("Biomes") {
+                Some(Tag::IntArray(biomes)) if biomes.len() == cells => biomes,
+                _ => vec![biome::default_id() as i32; cells]
+            };
+            encode_biomes(chunk, &mut biomes, (chunk.y - self.min_section) as usize * 64);
+            level.insert("Biomes".to_string(), Tag::IntArray(biomes));
+        }
+        // light is stored per section and may not agree with the rest of the column yet; have
+        // vanilla recompute it
+        level.insert("isLightOn".to_string(), Tag::Byte(0));
+
+        let written = self.write_column(chunk.x, chunk.z, &column);
+        if written.is_err() {
+            // what's cached has to match what's on disk
+            self.section_heightmaps.remove(&(chunk.x, chunk.z));
+        }
+        written
+    }
+
+    fn find_all(&mut self) -> Result<Vec<Vector3I>, WorldError> {
+        let mut regions = vec![];
+        for entry in std::fs::read_dir(&self.dir)? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            let parts: Vec<&str> = name.split('.').collect();
+            if let ["r", rx, rz, "mca"] = parts.as_slice() {
+                if let (Ok(rx), Ok(rz)) = (rx.parse::<i32>(), rz.parse::<i32>()) {
+                    regions.push((rx, rz));
+                }
+            }
+        }
+
+        // only the location tables are read; sections a stored column leaves out are air, and
+        // loading works out whether the column is finished
+        let mut poses = vec![];
+        for (rx, rz) in regions {
+            for i in 0..1024 {
+                let (x, z) = (rx * 32 + (i % 32), rz * 32 + (i / 32));
+                let present = self.region(x, z, false)?.is_some_and(|r| r.locations[i as usize] != 0);
+                if present {
+                    let sections = self.min_section..self.min_section + self.sections;
+                    poses.extend(sections.map(|y| Vector3I(x as i64, y as i64, z as i64)));
+                }
+            }
+        }
+        Ok(poses)
+    }
+
+    fn load(&mut self, x: i32, y: i32, z: i32) -> Result<Chunk, WorldError> {
+        let pos = Vector3I(x as i64, y as i64, z as i64);
+        let mut column = match self.read_column(x, z)? {
+            Some(column) => column,
+            None => return Err(WorldError::MissingChunk(pos))
+        };
+        let level = level_of(&mut column, x, z)?;
+
+        // partially generated columns are left for the generator to fill in
+        if let Some(Tag::String(status)) = level.get("Status") {
+            if status != "full" {
+                return Err(WorldError::MissingChunk(pos));
+            }
+        }
+
+        let mut chunk = Chunk::new_empty(x, y, z);
+        if y < i8::MIN as i32 || y > i8::MAX as i32 {
+            return Ok(chunk);
+        }
+        if let Some(Tag::List(sections)) = level.get("Sections") {
+            if let Some(Tag::Compound(section)) = sections.iter().find(|s| section_y(s) == Some(y as i8)) {
+                decode_section(&mut chunk, section)?;
+            }
+        }
+        if let Some(Tag::IntArray(biomes)) = level.get("Biomes") {
+            if biomes.len() == self.biome_cells() && self.has_section(y) {
+                decode_biomes(&mut chunk, biomes, (y - self.min_section) as usize * 64);
+            }
+        }
+        if let Some(Tag::List(entities)) = level.remove("TileEntities") {
+            for entity in entities {
+                if let Tag::Compound(entity) = entity {
+                    if matches!(entity.get("y"), Some(Tag::Int(ey)) if ey.div_euclid(16) == y) {
+                        chunk.load_block_entity(entity);
+                    }
+                }
+            }
+        }
+        chunk.repair_block_entities();
+        Ok(chunk)
+    }
+
+    fn load_meta(&mut self) -> Result<WorldMeta, WorldError> {
+        if let Some(meta) = &self.meta {
+            return Ok(meta.clone());
+        }
+        let level = match self.read_level()? {
+            Some(level) => level,
+            None => return Ok(WorldMeta::default())
+        };
+
+        let data = match level.elements.get("Data") {
+            Some(Tag::Compound(data)) => data,
+            _ => return Ok(WorldMeta::default())
+        };
+        // 1.16 moved the seed from Data.RandomSeed into the world generation settings
+        let seed = match data.get("WorldGenSettings") {
+            Some(Tag::Compound(settings)) => match settings.get("seed") {
+                Some(Tag::Long(seed)) => Some(*seed),
+                _ => None
+            },
+            _ => match data.get("RandomSeed") {
+                Some(Tag::Long(seed)) => Some(*seed),
+                _ => None
+            }
+        };
+        Ok(WorldMeta { seed })
+    }
+
+    fn save_meta(&mut self, meta: &WorldMeta) -> Result<(), WorldError> {
+        if self.read_only {
+            return Err(WorldError::ReadOnly);
+        }
+        self.meta = Some(meta.clone());
+        Ok(())
+    }
+
+    /// Region files are written through, so only `level.dat` is left to write.
+    fn flush(&mut self) -> Result<(), WorldError> {
+        if let Some(meta) = self.meta.take() {
+            if let Err(e) = self.write_level(&meta) {
+                self.meta = Some(meta);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn set_height(&mut self, min_section: i32, sections: i32) {
+        self.min_section = min_section;
+        self.sections = sections;
+        self.section_heightmaps.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::BlockContainer;
+
+    fn entry(name: &str, properties: Option<(&str, &str)>) -> Tag {
+        let mut entry = HashMap::new();
+        entry.insert("Name".to_string(), Tag::String(name.to_string()));
+        if let Some((key, value)) = properties {
+            let mut props = HashMap::new();
+            props.insert(key.to_string(), Tag::String(value.to_string()));
+            entry.insert("Properties".to_string(), Tag::Compound(props));
+        }
+        Tag::Compound(entry)
+    }
+
+    fn palette_of(section: &Tag) -> (&Vec<Tag>, Vec<usize>) {
+        match section {
+            Tag::Compound(s) => match (s.get("Palette"), read_states(s)) {
+                (Some(Tag::List(palette)), Ok(Some((_, indices)))) => (palette, indices),
+                _ => panic!("section has no blocks")
+            },
+            _ => panic!("section is not a compound")
+        }
+    }
+
+    #[test]
+    fn saving_keeps_unknown_blocks_and_properties() {
+        // stone, a log along X and a block Grimstone doesn't know, alternating
+        let mut stored = HashMap::new();
+        stored.insert("Y".to_string(), Tag::Byte(0));
+        stored.insert("Palette".to_string(), Tag::List(vec![
+            entry("minecraft:stone", None),
+            entry("minecraft:oak_log", Some(("axis", "x"))),
+            entry("minecraft:amethyst_block", None)
+        ]));
+        let mut states = vec![0i64; 256];
+        for i in 0..16 * 16 * 16 {
+            states[i / 16] |= ((i % 3) as i64) << ((i % 16) * 4);
+        }
+        stored.insert("BlockStates".to_string(), Tag::LongArray(states));
+
+        let mut chunk = Chunk::new_empty(0, 0, 0);
+        decode_section(&mut chunk, &stored).unwrap();
+        assert_eq!((chunk.blocks[0], chunk.blocks[1], chunk.blocks[2]), (Block::Stone, Block::OakLog, Block::Air));
+        chunk.blocks[3] = Block::Glass;
+        chunk.blocks[4] = Block::Dirt;
+
+        let section = encode_section(&chunk, Some(stored));
+        let (palette, indices) = palette_of(&section);
+        assert_eq!(palette[indices[1]], entry("minecraft:oak_log", Some(("axis", "x"))));
+        assert_eq!(palette[indices[2]], entry("minecraft:amethyst_block", None));
+        assert_eq!(palette[indices[3]], entry("minecraft:glass", None));
+        assert_eq!(palette[indices[4]], entry("minecraft:dirt", None));
+        assert_eq!(palette[indices[5]], entry("minecraft:amethyst_block", None));
+    }
+
+    #[test]
+    fn saved_heightmaps_match_the_whole_column() {
+        let dir = std::env::temp_dir().join(format!("grimstone-anvil-heightmaps-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.to_str().unwrap();
+        let heightmaps = |world: &mut AnvilWorld| {
+            let mut column = world.read_column(0, 0).unwrap().unwrap();
+            level_of(&mut column, 0, 0).unwrap().remove("Heightmaps").unwrap()
+        };
+
+        // a tower over (1, 1) that the top section no longer covers, and glass on top
+        let mut world = AnvilWorld::new(path).unwrap();
+        for y in [2, 0, 5, 1] {
+            let mut chunk = Chunk::new_empty(0, y, 0);
+            for by in 0..16 {
+                chunk.put(1, by, 1, Block::Stone);
+            }
+            chunk.put(4, 15, 4, Block::Glass);
+            world.save(&mut chunk).unwrap();
+        }
+        let mut chunk = Chunk::new_empty(0, 5, 0);
+        chunk.put(4, 3, 4, Block::Glass);
+        world.save(&mut chunk).unwrap();
+        let cached = heightmaps(&mut world);
+
+        // a fresh syncer has to decode the whole column
+        let mut world = AnvilWorld::new(path).unwrap();
+        world.save(&mut chunk).unwrap();
+        assert!(heightmaps(&mut world) == cached);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
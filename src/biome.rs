@@ -0,0 +1,186 @@
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::sync::OnceLock;
+use crate::registry::Registry;
+
+/// Index of a biome in the biome registry, as stored in chunks and sent to clients.
+pub type BiomeId = u16;
+
+/// What chunks are filled with before anything else is picked.
+pub const DEFAULT_BIOME: &str = "minecraft:plains";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BiomeMoodSound {
+    pub sound: String,
+    pub tick_delay: i32,
+    pub offset: f64,
+    pub block_search_extent: i32
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BiomeAdditionsSound {
+    pub sound: String,
+    pub tick_chance: f64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BiomeMusic {
+    pub replace_current_music: bool,
+    pub sound: String,
+    pub max_delay: i32,
+    pub min_delay: i32
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BiomeParticleOptions {
+    #[serde(rename = "type")]
+    pub particle_type: String
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BiomeParticles {
+    pub probability: f32,
+    pub options: BiomeParticleOptions
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BiomeEffects {
+    pub sky_color: i32,
+    pub water_fog_color: i32,
+    pub fog_color: i32,
+    pub water_color: i32,
+    pub foliage_color: Option<i32>,
+    pub grass_color: Option<i32>,
+    pub grass_color_modifier: Option<String>,
+    pub music: Option<BiomeMusic>,
+    pub ambient_sound: Option<String>,
+    pub additions_sound: Option<BiomeAdditionsSound>,
+    pub mood_sound: Option<BiomeMoodSound>,
+    pub particle: Option<BiomeParticles>
+}
+
+/// The part of a biome clients are told about. Anything else in a biome definition, like how
+/// vanilla generates it, is ignored.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Biome {
+    /// `none`, `rain` or `snow`.
+    pub precipitation: String,
+    pub depth: f32,
+    pub temperature: f32,
+    pub scale: f32,
+    pub downfall: f32,
+    pub category: String,
+    pub temperature_modifier: Option<String>,
+    pub effects: BiomeEffects
+}
+
+impl Biome {
+    /// Checks the values the client only accepts a few of.
+    pub fn validate(&self) -> Result<(), String> {
+        if !matches!(self.precipitation.as_str(), "none" | "rain" | "snow") {
+            return Err(format!("precipitation {} has to be none, rain or snow", self.precipitation));
+        }
+        if let Some(modifier) = &self.temperature_modifier {
+            if !matches!(modifier.as_str(), "none" | "frozen") {
+                return Err(format!("temperature_modifier {} has to be none or frozen", modifier));
+            }
+        }
+        if let Some(modifier) = &self.effects.grass_color_modifier {
+            if !matches!(modifier.as_str(), "none" | "dark_forest" | "swamp") {
+                return Err(format!("grass_color_modifier {} has to be none, dark_forest or swamp", modifier));
+            }
+        }
+        if !CATEGORIES.contains(&self.category.as_str()) {
+            return Err(format!("unknown category {}", self.category));
+        }
+        if let Some(particle) = &self.effects.particle {
+            if !(0.0..=1.0).contains(&particle.probability) {
+                return Err(format!("particle probability {} has to be from 0 to 1", particle.probability));
+            }
+        }
+        Ok(())
+    }
+}
+
+const CATEGORIES: &[&str] = &[
+    "none", "taiga", "extreme_hills", "jungle", "mesa", "plains", "savanna", "icy", "the_end", "beach",
+    "forest", "ocean", "desert", "river", "swamp", "mushroom", "nether", "underground"
+];
+
+#[derive(Deserialize)]
+struct VanillaBiome {
+    name: String,
+    id: i32,
+    element: Biome
+}
+
+static BIOMES: OnceLock<Registry<'static, Biome>> = OnceLock::new();
+
+/// Every biome of vanilla 1.17, under vanilla's ids.
+pub fn vanilla() -> Registry<'static, Biome> {
+    let biomes: Vec<VanillaBiome> = serde_json::from_str(include_str!("data/biomes.json"))
+        .expect("Built-in biome data is broken");
+
+    let mut registry = Registry::new("minecraft:worldgen/biome");
+    for b in biomes {
+        registry.register_with_id(&b.name, b.id, b.element);
+    }
+    registry
+}
+
+/// Reads custom biomes from a TOML or JSON file (picked by its extension) mapping names to
+/// biomes, e.g. `["grimstone:ashlands"]` tables in TOML.
+pub fn load_custom(path: &str) -> Result<Vec<(String, Biome)>, String> {
+    let text = read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let biomes: BTreeMap<String, Biome> = if path.ends_with(".json") {
+        serde_json::from_str(&text).map_err(|e| format!("{}: {}", path, e))?
+    } else {
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path, e))?
+    };
+
+    for (name, biome) in biomes.iter() {
+        if !is_valid_name(name) {
+            return Err(format!("{}: {} is not a valid namespaced biome name", path, name));
+        }
+        biome.validate().map_err(|e| format!("{}: {}: {}", path, name, e))?;
+    }
+    Ok(biomes.into_iter().collect())
+}
+
+/// Whether `name` is a namespaced id like `grimstone:ashlands`.
+pub fn is_valid_name(name: &str) -> bool {
+    match name.split_once(':') {
+        Some((ns, path)) => !ns.is_empty() && !path.is_empty()
+            && ns.chars().all(|c| matches!(c, 'a'..='z' | '0'..='9' | '_' | '-' | '.'))
+            && path.chars().all(|c| matches!(c, 'a'..='z' | '0'..='9' | '_' | '-' | '.' | '/')),
+        None => false
+    }
+}
+
+/// Sets up the biome registry with the vanilla biomes and `custom` on top of them; a custom biome
+/// with a vanilla name replaces it. Has to happen before anything looks a biome up, since
+/// lookups settle on vanilla's biomes otherwise.
+pub fn init(custom: Vec<(String, Biome)>) -> Result<(), String> {
+    let mut registry = vanilla();
+    for (name, biome) in custom {
+        registry.replace(&name, biome);
+    }
+    BIOMES.set(registry).map_err(|_| "the biome registry is already set up".to_string())
+}
+
+pub fn registry() -> &'static Registry<'static, Biome> {
+    BIOMES.get_or_init(vanilla)
+}
+
+pub fn id_of(name: &str) -> Option<BiomeId> {
+    registry().get(name).map(|e| e.id as BiomeId)
+}
+
+pub fn name_of(id: BiomeId) -> Option<&'static str> {
+    registry().by_id(id as i32).map(|e| e.name.as_str())
+}
+
+pub fn default_id() -> BiomeId {
+    id_of(DEFAULT_BIOME).expect("The default biome is missing from the registry")
+}
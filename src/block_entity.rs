@@ -0,0 +1,417 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use nbt::Tag;
+use crate::world::Block;
+use crate::inventory::{self, Slot, WindowType, FURNACE_FUEL, FURNACE_INPUT, FURNACE_OUTPUT};
+use crate::recipe;
+use crate::item;
+
+/// Extra data some blocks carry, stored and sent as NBT with vanilla's field names.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlockEntity {
+    Chest(ChestData),
+    Furnace(FurnaceData),
+    Sign(SignData),
+    Banner(BannerData),
+    Spawner(SpawnerData)
+}
+
+/// An item kept in a container, by slot.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct StoredItem {
+    #[serde(rename = "Slot")]
+    pub slot: i8,
+    pub id: String,
+    #[serde(rename = "Count")]
+    pub count: i8,
+    /// Anything else about the item, like enchantments or a custom name. It can be any NBT, which
+    /// serde can't carry, so `take_item_tags` and `put_item_tags` move it around the rest.
+    #[serde(skip)]
+    pub tag: Option<HashMap<String, Tag>>
+}
+
+impl Clone for StoredItem {
+    fn clone(&self) -> Self {
+        StoredItem {
+            slot: self.slot,
+            id: self.id.clone(),
+            count: self.count,
+            tag: self.tag.as_ref().map(item::clone_compound)
+        }
+    }
+}
+
+/// Takes the `tag` out of each item of a saved item list, in order, so the rest can be decoded
+/// into `StoredItem`s. Give them back with `set_tags`.
+pub(crate) fn take_item_tags(items: &mut [Tag]) -> Vec<Option<HashMap<String, Tag>>> {
+    items.iter_mut()
+        .map(|item| match item {
+            Tag::Compound(item) => match item.remove("tag") {
+                Some(Tag::Compound(tag)) => Some(tag),
+                _ => None
+            },
+            _ => None
+        })
+        .collect()
+}
+
+/// Gives decoded items the tags `take_item_tags` took out of them.
+pub(crate) fn set_tags(items: &mut [StoredItem], tags: Vec<Option<HashMap<String, Tag>>>) {
+    for (item, tag) in items.iter_mut().zip(tags) {
+        item.tag = tag;
+    }
+}
+
+/// Adds the tags of `stored` to the item list they were encoded to.
+pub(crate) fn put_item_tags(items: &mut [Tag], stored: &[StoredItem]) {
+    for (item, stored) in items.iter_mut().zip(stored) {
+        if let (Tag::Compound(item), Some(tag)) = (item, &stored.tag) {
+            item.insert("tag".to_string(), Tag::Compound(item::clone_compound(tag)));
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChestData {
+    #[serde(rename = "Items", default)]
+    pub items: Vec<StoredItem>,
+    #[serde(rename = "CustomName", default, skip_serializing_if = "Option::is_none")]
+    pub custom_name: Option<String>
+}
+
+/// Times are in ticks.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FurnaceData {
+    #[serde(rename = "Items", default)]
+    pub items: Vec<StoredItem>,
+    /// How much longer the fuel that's burning lasts.
+    #[serde(rename = "BurnTime", default)]
+    pub burn_time: i16,
+    #[serde(rename = "CookTime", default)]
+    pub cook_time: i16,
+    #[serde(rename = "CookTimeTotal", default)]
+    pub cook_time_total: i16,
+    /// How long the fuel that's burning lasted in all. Vanilla doesn't save it either, and works
+    /// it out again from the fuel slot.
+    #[serde(skip)]
+    pub fuel_time: i16,
+    #[serde(rename = "CustomName", default, skip_serializing_if = "Option::is_none")]
+    pub custom_name: Option<String>
+}
+
+/// The four lines of a sign are chat component JSON.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SignData {
+    #[serde(rename = "Text1")]
+    pub text1: String,
+    #[serde(rename = "Text2")]
+    pub text2: String,
+    #[serde(rename = "Text3")]
+    pub text3: String,
+    #[serde(rename = "Text4")]
+    pub text4: String,
+    #[serde(rename = "Color", default = "default_sign_color")]
+    pub color: String,
+    #[serde(rename = "GlowingText", default)]
+    pub glowing_text: bool
+}
+
+fn default_sign_color() -> String {
+    "black".to_string()
+}
+
+impl Default for SignData {
+    fn default() -> SignData {
+        let empty = r#"{"text":""}"#.to_string();
+        SignData {
+            text1: empty.clone(),
+            text2: empty.clone(),
+            text3: empty.clone(),
+            text4: empty,
+            color: default_sign_color(),
+            glowing_text: false
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BannerPattern {
+    /// The pattern's short code, like `bs` or `cr`.
+    #[serde(rename = "Pattern")]
+    pub pattern: String,
+    /// Dye color id, 0 for white to 15 for black.
+    #[serde(rename = "Color")]
+    pub color: i32
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BannerData {
+    #[serde(rename = "Patterns", default)]
+    pub patterns: Vec<BannerPattern>,
+    #[serde(rename = "CustomName", default, skip_serializing_if = "Option::is_none")]
+    pub custom_name: Option<String>
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpawnEntity {
+    pub id: String
+}
+
+/// Delays are in ticks and ranges in blocks.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpawnerData {
+    #[serde(rename = "SpawnData")]
+    pub spawn_data: SpawnEntity,
+    #[serde(rename = "Delay")]
+    pub delay: i16,
+    #[serde(rename = "MinSpawnDelay")]
+    pub min_spawn_delay: i16,
+    #[serde(rename = "MaxSpawnDelay")]
+    pub max_spawn_delay: i16,
+    #[serde(rename = "SpawnCount")]
+    pub spawn_count: i16,
+    #[serde(rename = "MaxNearbyEntities")]
+    pub max_nearby_entities: i16,
+    #[serde(rename = "RequiredPlayerRange")]
+    pub required_player_range: i16,
+    #[serde(rename = "SpawnRange")]
+    pub spawn_range: i16
+}
+
+impl Default for SpawnerData {
+    fn default() -> SpawnerData {
+        SpawnerData {
+            spawn_data: SpawnEntity { id: "minecraft:pig".to_string() },
+            delay: 20,
+            min_spawn_delay: 200,
+            max_spawn_delay: 800,
+            spawn_count: 4,
+            max_nearby_entities: 6,
+            required_player_range: 16,
+            spawn_range: 4
+        }
+    }
+}
+
+impl BlockEntity {
+    /// A fresh block entity for `block`, if it's a block that has one.
+    pub fn for_block(block: Block) -> Option<BlockEntity> {
+        match block {
+            Block::Chest => Some(BlockEntity::Chest(ChestData::default())),
+            Block::Furnace => Some(BlockEntity::Furnace(FurnaceData::default())),
+            Block::OakSign => Some(BlockEntity::Sign(SignData::default())),
+            Block::WhiteBanner => Some(BlockEntity::Banner(BannerData::default())),
+            Block::Spawner => Some(BlockEntity::Spawner(SpawnerData::default())),
+            _ => None
+        }
+    }
+
+    /// Whether this is the kind of block entity `block` has.
+    pub fn fits(&self, block: Block) -> bool {
+        matches!((self, block),
+            (BlockEntity::Chest(_), Block::Chest)
+            | (BlockEntity::Furnace(_), Block::Furnace)
+            | (BlockEntity::Sign(_), Block::OakSign)
+            | (BlockEntity::Banner(_), Block::WhiteBanner)
+            | (BlockEntity::Spawner(_), Block::Spawner))
+    }
+
+    /// Vanilla's id for the kind of block entity.
+    pub fn id(&self) -> &'static str {
+        match self {
+            BlockEntity::Chest(_) => "minecraft:chest",
+            BlockEntity::Furnace(_) => "minecraft:furnace",
+            BlockEntity::Sign(_) => "minecraft:sign",
+            BlockEntity::Banner(_) => "minecraft:banner",
+            BlockEntity::Spawner(_) => "minecraft:mob_spawner"
+        }
+    }
+
+    /// What a Block Entity Data packet tells the client to do with the data, or `None` if the
+    /// client has no use for updates of this kind.
+    pub fn update_action(&self) -> Option<u8> {
+        match self {
+            BlockEntity::Spawner(_) => Some(1),
+            BlockEntity::Banner(_) => Some(6),
+            BlockEntity::Sign(_) => Some(9),
+            BlockEntity::Chest(_) | BlockEntity::Furnace(_) => None
+        }
+    }
+
+    /// The window players get when they use the block, for containers.
+    pub fn window_type(&self) -> Option<WindowType> {
+        match self {
+            BlockEntity::Chest(_) => Some(WindowType::Chest),
+            BlockEntity::Furnace(_) => Some(WindowType::Furnace),
+            _ => None
+        }
+    }
+
+    /// What's in the container, by slot of its window, or nothing for block entities that
+    /// aren't containers.
+    pub fn slots(&self) -> Option<Vec<Slot>> {
+        let kind = self.window_type()?;
+        Some(inventory::slots_from_stored(self.items()?, kind.player_start()))
+    }
+
+    /// The items saved in the container, if it is one.
+    fn items(&self) -> Option<&[StoredItem]> {
+        match self {
+            BlockEntity::Chest(data) => Some(&data.items),
+            BlockEntity::Furnace(data) => Some(&data.items),
+            _ => None
+        }
+    }
+
+    /// Replaces what's in the container. A furnace starts cooking over if what's in its input
+    /// changes to something else.
+    pub fn set_slots(&mut self, slots: &[Slot]) {
+        match self {
+            BlockEntity::Chest(data) => data.items = inventory::stored_from_slots(slots),
+            BlockEntity::Furnace(data) => {
+                let input = |items: &[StoredItem]| items.iter().find(|i| i.slot == FURNACE_INPUT as i8).map(|i| i.id.clone());
+                let items = inventory::stored_from_slots(slots);
+                if input(&items) != input(&data.items) {
+                    data.cook_time = 0;
+                }
+                data.items = items;
+            }
+            _ => {}
+        }
+    }
+
+    /// The custom name the container was given, as chat component JSON.
+    pub fn custom_name(&self) -> Option<&str> {
+        match self {
+            BlockEntity::Chest(data) => data.custom_name.as_deref(),
+            BlockEntity::Furnace(data) => data.custom_name.as_deref(),
+            BlockEntity::Banner(data) => data.custom_name.as_deref(),
+            _ => None
+        }
+    }
+
+    /// The block entity as NBT with its `id` and world position, like vanilla saves it.
+    pub fn to_nbt(&self, x: i64, y: i64, z: i64) -> HashMap<String, Tag> {
+        let data = match self {
+            BlockEntity::Chest(data) => nbt::encode_tag(data),
+            BlockEntity::Furnace(data) => nbt::encode_tag(data),
+            BlockEntity::Sign(data) => nbt::encode_tag(data),
+            BlockEntity::Banner(data) => nbt::encode_tag(data),
+            BlockEntity::Spawner(data) => nbt::encode_tag(data)
+        };
+        let mut compound = match data {
+            Ok(Some(Tag::Compound(compound))) => compound,
+            _ => unreachable!("block entity data always encodes to a compound")
+        };
+        if let (Some(Tag::List(items)), Some(stored)) = (compound.get_mut("Items"), self.items()) {
+            put_item_tags(items, stored);
+        }
+        compound.insert("id".to_string(), Tag::String(self.id().to_string()));
+        compound.insert("x".to_string(), Tag::Int(x as i32));
+        compound.insert("y".to_string(), Tag::Int(y as i32));
+        compound.insert("z".to_string(), Tag::Int(z as i32));
+        compound
+    }
+
+    /// Reads what `to_nbt` wrote, giving back the world position too.
+    pub fn from_nbt(mut compound: HashMap<String, Tag>) -> Result<((i64, i64, i64), BlockEntity), String> {
+        let mut coord = |name: &str| match compound.remove(name) {
+            Some(Tag::Int(v)) => Ok(v as i64),
+            _ => Err(format!("block entity has no {}", name))
+        };
+        let pos = (coord("x")?, coord("y")?, coord("z")?);
+        let id = match compound.remove("id") {
+            Some(Tag::String(id)) => id,
+            _ => return Err("block entity has no id".to_string())
+        };
+
+        let tags = match compound.get_mut("Items") {
+            Some(Tag::List(items)) => take_item_tags(items),
+            _ => vec![]
+        };
+        let data = Tag::Compound(compound);
+        let mut entity = match id.as_str() {
+            "minecraft:chest" => nbt::decode_tag(data).map(BlockEntity::Chest),
+            "minecraft:furnace" => nbt::decode_tag(data).map(BlockEntity::Furnace),
+            "minecraft:sign" => nbt::decode_tag(data).map(BlockEntity::Sign),
+            "minecraft:banner" => nbt::decode_tag(data).map(BlockEntity::Banner),
+            "minecraft:mob_spawner" => nbt::decode_tag(data).map(BlockEntity::Spawner),
+            _ => return Err(format!("unknown block entity {}", id))
+        };
+        if let Ok(BlockEntity::Chest(ChestData { items, .. }) | BlockEntity::Furnace(FurnaceData { items, .. })) = &mut entity {
+            set_tags(items, tags);
+        }
+        entity.map(|e| (pos, e)).map_err(|e| format!("bad {} at {:?}: {}", id, pos, e))
+    }
+}
+
+impl FurnaceData {
+    /// Burns fuel and cooks for a tick, like vanilla's furnace. Gives back whether anything
+    /// changed.
+    pub fn tick(&mut self) -> bool {
+        let before = (self.burn_time, self.fuel_time, self.cook_time, self.cook_time_total);
+        let mut slots = inventory::slots_from_stored(&self.items, WindowType::Furnace.player_start());
+        let mut changed = false;
+        if self.burn_time > 0 {
+            self.burn_time -= 1;
+        }
+
+        let smelted = slots[FURNACE_INPUT].as_ref().and_then(recipe::smelt);
+        let fits = match (&smelted, &slots[FURNACE_OUTPUT]) {
+            (Some(_), None) => true,
+            (Some((result, _)), Some(output)) => output.stacks_with(result) && output.count + result.count <= output.max_stack_size(),
+            (None, _) => false
+        };
+        if self.burn_time == 0 && fits {
+            if let Some(fuel) = slots[FURNACE_FUEL].as_mut() {
+                let item = fuel.item;
+                self.burn_time = recipe::burn_time(item);
+                self.fuel_time = self.burn_time;
+                fuel.count -= 1;
+                if fuel.count <= 0 {
+                    slots[FURNACE_FUEL] = recipe::remainder(item);
+                }
+                changed = true;
+            }
+        }
+        if self.fuel_time == 0 && self.burn_time > 0 {
+            // loaded while burning
+            self.fuel_time = slots[FURNACE_FUEL].as_ref().map_or(0, |fuel| recipe::burn_time(fuel.item)).max(self.burn_time);
+        }
+
+        match smelted {
+            Some((result, total)) if self.burn_time > 0 && fits => {
+                self.cook_time_total = total;
+                self.cook_time += 1;
+                if self.cook_time >= total {
+                    self.cook_time = 0;
+                    match &mut slots[FURNACE_OUTPUT] {
+                        Some(output) => output.count += result.count,
+                        output => *output = Some(result)
+                    }
+                    if let Some(input) = slots[FURNACE_INPUT].as_mut() {
+                        input.count -= 1;
+                        if input.count <= 0 {
+                            slots[FURNACE_INPUT] = None;
+                        }
+                    }
+                    changed = true;
+                }
+            }
+            _ if self.burn_time > 0 => self.cook_time = 0,
+            // cooking goes back down while the fire's out
+            _ => self.cook_time = (self.cook_time - 2).clamp(0, self.cook_time_total.max(0))
+        }
+
+        if changed {
+            self.items = inventory::stored_from_slots(&slots);
+        }
+        changed || before != (self.burn_time, self.fuel_time, self.cook_time, self.cook_time_total)
+    }
+
+    /// The furnace's window properties by number: fuel left, how long the fuel lasted, how far
+    /// the cooking is and how long it takes.
+    pub fn properties(&self) -> [(i16, i16); 4] {
+        [(0, self.burn_time), (1, self.fuel_time), (2, self.cook_time), (3, self.cook_time_total)]
+    }
+}
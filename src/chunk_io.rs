@@ -0,0 +1,264 @@
+#![cfg(feature = "world_syncers")]
+
+use crate::world::{WorldSyncer, Chunk, WorldError};
+use crate::Vector3I;
+use crate::generator::ChunkGenerator;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, Condvar, MutexGuard};
+use std::sync::mpsc::{channel, Sender, Receiver, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+/// How many times an I/O error is retried before a chunk is refused.
+const IO_RETRIES: u32 = 3;
+
+/// A finished request, handed back to the world on its own thread.
+pub(crate) enum Completed {
+    Loaded(Vector3I, Result<Chunk, WorldError>),
+    /// A failed save gives the chunk back so its changes aren't lost.
+    Saved(Vector3I, Result<(), (WorldError, Chunk)>)
+}
+
+struct Load {
+    pos: Vector3I,
+    /// Someone is blocked on this chunk, so it goes before everything else.
+    urgent: bool
+}
+
+#[derive(Default)]
+struct Queue {
+    loads: Vec<Load>,
+    saves: VecDeque<Vector3I>,
+    /// Latest copy of every chunk that's queued or being saved; loads are served from here so
+    /// they never read what's on disk from before the save.
+    saving: HashMap<Vector3I, (Chunk, u64)>,
+    /// Positions a worker is saving right now; a second save of one waits until it's done.
+    busy: HashSet<Vector3I>,
+    next_seq: u64,
+    /// Chunk positions of the players; loads nearest to any of them go first.
+    players: Vec<Vector3I>,
+    shutdown: bool
+}
+
+enum Job {
+    Load(Vector3I),
+    Save(Vector3I, Box<Chunk>, u64)
+}
+
+impl Queue {
+    fn next_job(&mut self) -> Option<Job> {
+        // saves go first so memory is given back and later loads see the newest data
+        if let Some(i) = self.saves.iter().position(|p| !self.busy.contains(p)) {
+            let pos = self.saves.remove(i).unwrap();
+            return match self.saving.get(&pos) {
+                Some((chunk, seq)) => {
+                    self.busy.insert(pos);
+                    Some(Job::Save(pos, Box::new(chunk.clone()), *seq))
+                }
+                // an earlier entry already wrote the newest copy
+                None => self.next_job()
+            };
+        }
+
+        let players = &self.players;
+        let distance = |pos: &Vector3I| players.iter()
+            .map(|p| (p.0 - pos.0).pow(2) + (p.1 - pos.1).pow(2) + (p.2 - pos.2).pow(2))
+            .min()
+            .unwrap_or(0);
+        let i = self.loads.iter()
+            .enumerate()
+            .min_by_key(|(i, load)| (!load.urgent, distance(&load.pos), *i))
+            .map(|(i, _)| i)?;
+        Some(Job::Load(self.loads.remove(i).pos))
+    }
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    wake: Condvar,
+    sync: Mutex<Box<dyn WorldSyncer>>,
+    generator: Arc<dyn ChunkGenerator>,
+    /// The lowest block Y of the world and how many blocks tall it is, for the generator.
+    min_y: i64,
+    height: i64
+}
+
+/// Runs syncer reads and writes and chunk generation on a pool of worker threads so the
+/// thread that owns the world never waits on the disk.
+pub(crate) struct ChunkIo {
+    shared: Arc<Shared>,
+    completed: Receiver<Completed>,
+    workers: Vec<JoinHandle<()>>
+}
+
+impl ChunkIo {
+    pub(crate) fn new(sync: Box<dyn WorldSyncer>, generator: Arc<dyn ChunkGenerator>, min_y: i64, height: i64,
+                      threads: usize) -> ChunkIo {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            wake: Condvar::new(),
+            sync: Mutex::new(sync),
+            generator,
+            min_y,
+            height
+        });
+        let (tx, completed) = channel();
+
+        let workers = (0..threads.max(1)).map(|i| {
+            let shared = shared.clone();
+            let tx = tx.clone();
+            thread::Builder::new()
+                .name(format!("Chunk I/O #{}", i))
+                .spawn(move || work(shared, tx))
+                .expect("Could not start chunk I/O thread")
+        }).collect();
+
+        ChunkIo { shared, completed, workers }
+    }
+
+    /// Queues a chunk to be read (or generated). Asking again for a chunk that's already queued
+    /// doesn't queue it twice, but can make it urgent.
+    pub(crate) fn load(&self, pos: Vector3I, urgent: bool) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        match queue.loads.iter_mut().find(|l| l.pos == pos) {
+            Some(load) => load.urgent |= urgent,
+            None => queue.loads.push(Load { pos, urgent })
+        }
+        drop(queue);
+        self.shared.wake.notify_one();
+    }
+
+    /// Queues a chunk to be written and flushed.
+    pub(crate) fn save(&self, chunk: Chunk) {
+        let pos = Vector3I(chunk.x as i64, chunk.y as i64, chunk.z as i64);
+        let mut queue = self.shared.queue.lock().unwrap();
+        let seq = queue.next_seq;
+        queue.next_seq += 1;
+        queue.saving.insert(pos, (chunk, seq));
+        queue.saves.push_back(pos);
+        drop(queue);
+        self.shared.wake.notify_one();
+    }
+
+    pub(crate) fn set_players(&self, players: Vec<Vector3I>) {
+        self.shared.queue.lock().unwrap().players = players;
+    }
+
+    /// Waits for every queued save to be written, then has the syncer throw away everything saved
+    /// since it was opened. Gives back whether it could.
+    pub(crate) fn reset(&self) -> bool {
+        let mut queue = self.shared.queue.lock().unwrap();
+        while !queue.saves.is_empty() || !queue.busy.is_empty() {
+            queue = self.shared.wake.wait(queue).unwrap();
+        }
+        drop(queue);
+        self.shared.sync.lock().unwrap().reset()
+    }
+
+    /// Direct access to the syncer, for work that has to happen all at once like `save_all`.
+    pub(crate) fn syncer(&self) -> MutexGuard<'_, Box<dyn WorldSyncer>> {
+        self.shared.sync.lock().unwrap()
+    }
+
+    /// Direct access to the syncer for writing the chunks at `positions` all at once, like
+    /// `save_all` does. What's about to be written is newer than any save of them still queued,
+    /// so those are dropped, and ones a worker has already started are waited for so they can't
+    /// land on top of it.
+    pub(crate) fn syncer_for(&self, positions: &HashSet<Vector3I>) -> MutexGuard<'_, Box<dyn WorldSyncer>> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.saves.retain(|pos| !positions.contains(pos));
+        queue.saving.retain(|pos, _| !positions.contains(pos));
+        while queue.busy.iter().any(|pos| positions.contains(pos)) {
+            queue = self.shared.wake.wait(queue).unwrap();
+        }
+        drop(queue);
+        self.syncer()
+    }
+
+    pub(crate) fn try_recv(&self) -> Option<Completed> {
+        match self.completed.try_recv() {
+            Ok(done) => Some(done),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None
+        }
+    }
+
+    pub(crate) fn recv(&self) -> Completed {
+        self.completed.recv().expect("Chunk I/O threads stopped")
+    }
+}
+
+impl Drop for ChunkIo {
+    /// Lets the workers finish every queued save before they stop.
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().shutdown = true;
+        self.shared.wake.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn work(shared: Arc<Shared>, tx: Sender<Completed>) {
+    loop {
+        let job = {
+            let mut queue = shared.queue.lock().unwrap();
+            loop {
+                if let Some(job) = queue.next_job() { break job; }
+                if queue.shutdown && queue.saves.is_empty() { return; }
+                queue = shared.wake.wait(queue).unwrap();
+            }
+        };
+
+        // a failed send means the world has gone away, and nobody's left to tell
+        match job {
+            Job::Load(pos) => {
+                let _ = tx.send(Completed::Loaded(pos, load(&shared, pos)));
+            }
+            Job::Save(pos, mut chunk, seq) => {
+                let result = save(&shared, &mut chunk);
+                // sent before the position stops being busy, so `reset` never misses it
+                let _ = tx.send(Completed::Saved(pos, result.map_err(|e| (e, *chunk))));
+                let mut queue = shared.queue.lock().unwrap();
+                queue.busy.remove(&pos);
+                if queue.saving.get(&pos).is_some_and(|(_, s)| *s == seq) {
+                    queue.saving.remove(&pos);
+                }
+                drop(queue);
+                // another worker (or `reset`) may be waiting for this position to stop being busy
+                shared.wake.notify_all();
+            }
+        }
+    }
+}
+
+/// Reads a chunk from the syncer. Missing chunks are generated, I/O errors are retried,
+/// and corrupt or incompatible chunks are refused so the stored data is never overwritten.
+fn load(shared: &Shared, pos: Vector3I) -> Result<Chunk, WorldError> {
+    if let Some((chunk, _)) = shared.queue.lock().unwrap().saving.get(&pos) {
+        return Ok(chunk.clone());
+    }
+
+    let (x, y, z) = (pos.0 as i32, pos.1 as i32, pos.2 as i32);
+    let mut attempts = 0;
+    loop {
+        let result = shared.sync.lock().unwrap().load(x, y, z);
+        match result {
+            Ok(chunk) => return Ok(chunk),
+            // generate without holding the syncer so other threads can keep reading
+            Err(WorldError::MissingChunk(_)) => return Ok(shared.generator.generate(x, y, z, shared.min_y, shared.height)),
+            Err(WorldError::Io(e)) if attempts < IO_RETRIES => {
+                attempts += 1;
+                log::warn!("Could not read chunk [{},{},{}] (attempt {}): {}", x, y, z, attempts, e);
+            }
+            Err(e) => {
+                log::error!("Refusing to load chunk [{},{},{}]: {}", x, y, z, e);
+                return Err(e);
+            }
+        }
+    }
+}
+
+fn save(shared: &Shared, chunk: &mut Chunk) -> Result<(), WorldError> {
+    let mut sync = shared.sync.lock().unwrap();
+    sync.save(chunk)?;
+    sync.flush()
+}
@@ -1,7 +1,12 @@
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter, Debug};
 use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::net::{Shutdown, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, MutexGuard};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread;
+use std::time::Duration;
 
 use crate::client::PacketState::Handshake;
 use crate::traits::{Packet, Readable, Writable};
@@ -9,6 +14,25 @@ use crate::client::Error::{IoError, CannotReplace, InvalidPacketId};
 use crate::buffer::Buffer;
 use crate::config::ConcreteConfig;
 use uuid::Uuid;
+use crate::server::Server;
+use crate::packets::play::{RespawnPacket, ChatMessagePacket, ChunkDataPacket, UpdateLightPacket, UpdateViewPositionPacket, UnloadChunkPacket, PlayerPositionAndLookPacket,
+                            PlayerAbilitiesPacket, ChangeGameStatePacket, PlayerInfoPacket, WindowItemsPacket, SetSlotPacket,
+                            HeldItemChangePacket, OpenWindowPacket, CloseWindowPacket, WindowPropertyPacket,
+                            VIEW_DISTANCE};
+use crate::GameMode;
+use crate::world::{Block, ChunkContainer, World};
+use crate::entity::{PlayerEntity, Entity, EntityKind, EYE_HEIGHT};
+use crate::playerdata::{self, PlayerData};
+use crate::inventory::{self, PlayerInventory, Slot, Window, WindowType};
+use crate::block_entity::BlockEntity;
+use crate::item::ItemStack;
+
+/// How far from the middle of a block a player's feet can be to keep its window open, squared.
+const WINDOW_REACH_SQUARED: f64 = 8.0 * 8.0;
+/// Packets that can wait to be written to a client before it's taken to have stopped reading.
+const OUTBOX_PACKETS: usize = 1024;
+/// How long writing to a client can block before it's taken to be gone.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
 pub enum PacketState {
@@ -28,7 +52,85 @@ pub struct Client {
     pub state: PacketState,
     pub config: ConcreteConfig,
     pub username: Option<String>,
-    pub uuid: Option<Uuid>
+    pub uuid: Option<Uuid>,
+    /// Name of the world the player is in, once they're playing.
+    pub world: Option<String>,
+    pub connection: Arc<Connection>,
+    pub entity_id: i32,
+    pub player: PlayerEntity,
+    /// The id of the last teleport sent to the client.
+    pub teleport_id: i32,
+    /// A teleport the client hasn't confirmed yet; until it does, its movement is ignored.
+    pub awaiting_teleport: Option<i32>,
+    /// The block the player started digging and hasn't finished or cancelled, and the tick they
+    /// started on.
+    pub digging: Option<((i64, i64, i64), u64)>,
+    /// The column the player's view is around, once they've been sent one in their world.
+    view: Option<(i32, i32)>,
+    pub game_mode: GameMode,
+    pub flying: bool,
+    pub inventory: PlayerInventory,
+    /// The window open on top of the player's own, if any.
+    pub window: Option<Window>,
+    /// The id of the last window opened, which vanilla counts from 1 to 100 and round again.
+    window_id: u8,
+    /// `Server::player_saves` when the player was last saved.
+    saves_seen: u64
+}
+
+/// The sending side of a client's stream, shared so other threads can send them packets too.
+/// Packets are queued whole and written in order by the connection's own thread, so sending
+/// never waits on the network, even with a world locked. A client that stops reading is
+/// disconnected once its queue fills up or a write times out.
+pub struct Connection {
+    outbox: SyncSender<Vec<u8>>,
+    stream: TcpStream
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Connection {
+        if let Err(e) = stream.set_write_timeout(Some(WRITE_TIMEOUT)) {
+            log::warn!("Could not set a write timeout: {}", e);
+        }
+        let (outbox, queued) = mpsc::sync_channel::<Vec<u8>>(OUTBOX_PACKETS);
+        let mut writer = stream.try_clone().expect("Could not share the client's stream");
+        let addr = stream.peer_addr().map_or_else(|_| "a client".to_string(), |a| a.to_string());
+        thread::spawn(move || {
+            for bytes in queued {
+                if let Err(e) = writer.write_all(&bytes) {
+                    log::info!("Could not write to {}: {}", addr, e);
+                    // wakes up the client's thread, which cleans up after them
+                    let _ = writer.shutdown(Shutdown::Both);
+                    break;
+                }
+            }
+        });
+        Connection { outbox, stream }
+    }
+
+    /// Queues `packet` with its length and id, giving back how many bytes that took.
+    pub fn send(&self, packet: &dyn Packet) -> Result<usize, Error> {
+        let mut buffer = Buffer::new();
+        buffer.write_var_int(packet.id() as i32)?;
+        packet.write(&mut buffer)?;
+
+        let mut framed = Buffer::new();
+        framed.write_var_int(buffer.bytes.len() as i32)?;
+        framed.write(&*buffer.bytes)?;
+
+        let len = framed.bytes.len();
+        match self.outbox.try_send(framed.bytes) {
+            Ok(()) => Ok(len),
+            Err(TrySendError::Full(_)) => {
+                if let Ok(addr) = self.stream.peer_addr() {
+                    log::info!("Disconnecting {}, which stopped reading packets", addr);
+                }
+                let _ = self.stream.shutdown(Shutdown::Both);
+                Err(Error::Disconnected)
+            }
+            Err(TrySendError::Disconnected(_)) => Err(Error::Disconnected)
+        }
+    }
 }
 
 pub struct RawPacket {
@@ -55,14 +157,29 @@ impl Display for Error {
 
 impl Client {
     pub fn new(stream: TcpStream, config: ConcreteConfig) -> Self {
+        let connection = Arc::new(Connection::new(stream.try_clone().expect("Could not share the client's stream")));
         Self {
             stream,
             is_valid: true,
             packets: HashMap::new(),
             state: Handshake,
+            game_mode: config.server_default_game_mode,
             config,
             username: None,
-            uuid: Some(Uuid::from_u128(rand::random()))
+            uuid: Some(Uuid::from_u128(rand::random())),
+            world: None,
+            connection,
+            entity_id: 0,
+            player: PlayerEntity::new((0.0, 0.0, 0.0)),
+            teleport_id: 0,
+            awaiting_teleport: None,
+            digging: None,
+            view: None,
+            flying: false,
+            inventory: PlayerInventory::new(),
+            window: None,
+            window_id: 0,
+            saves_seen: Server::global().player_saves()
         }
     }
 
@@ -85,14 +202,7 @@ impl Client {
     }
 
     pub fn write_packet(&mut self, packet: &dyn Packet) -> Result<usize, Error> {
-        let mut buffer = Buffer::new();
-        buffer.write_var_int(packet.id() as i32)?;
-        packet.write(&mut buffer)?;
-
-        let mut new_buf = Buffer::new();
-        let mut size = new_buf.write_var_int(buffer.bytes.len() as i32)?;
-        size += new_buf.write(&*buffer.bytes)?;
-        self.write(new_buf.bytes.as_slice());
+        let size = self.connection.send(packet)?;
 
         #[cfg(feature = "debug")]
         log::debug!("Wrote [S->C] {:?}", packet);
@@ -112,8 +222,403 @@ impl Client {
         }
     }
 
-    pub fn write_initial_play_packets(&mut self) {
+    /// The world the player is in, locked. Let go of it before calling anything that locks it
+    /// again.
+    pub fn current_world(&self) -> Option<MutexGuard<'static, World>> {
+        self.world.as_ref().and_then(|name| Server::global().world(name))
+    }
+
+    /// Sends the player's abilities and their inventory and puts them where they are in their
+    /// world, which sends them the columns around them.
+    pub fn write_initial_play_packets(&mut self) -> Result<(), Error> {
+        if self.world.is_none() {
+            return Ok(());
+        }
+        self.write_packet(&PlayerAbilitiesPacket::new(self.game_mode, self.flying))?;
+        self.send_inventory()?;
+        self.teleport(self.player.position)
+    }
+
+    /// Moves the player's view to the column they're in if that's changed. The client forgets
+    /// the columns that went out of view, and the ones that came into it are loaded in the
+    /// background and sent as each is ready, nearest first.
+    pub fn update_view(&mut self) -> Result<(), Error> {
+        let centre = ((self.player.position.0 / 16.0).floor() as i32, (self.player.position.2 / 16.0).floor() as i32);
+        if self.view == Some(centre) {
+            return Ok(());
+        }
+        let (uuid, mut world) = match (self.uuid, self.current_world()) {
+            (Some(uuid), Some(world)) => (uuid, world),
+            _ => return Ok(())
+        };
+        self.view = Some(centre);
+
+        // the new centre goes first, or the client throws away columns sent around it
+        self.write_packet(&UpdateViewPositionPacket { x: centre.0, z: centre.1 })?;
+        for (x, z) in world.tracked_columns(uuid) {
+            if !in_view(centre, x, z) {
+                world.untrack_column(uuid, x, z);
+                self.write_packet(&UnloadChunkPacket { x, z })?;
+            }
+        }
+        let mut columns: Vec<_> = (centre.0 - VIEW_DISTANCE..=centre.0 + VIEW_DISTANCE)
+            .flat_map(|x| (centre.1 - VIEW_DISTANCE..=centre.1 + VIEW_DISTANCE).map(move |z| (x, z)))
+            .collect();
+        columns.sort_by_key(|(x, z)| (x - centre.0).pow(2) + (z - centre.1).pow(2));
+        for (x, z) in columns {
+            if !world.trackers(x, z).contains(&uuid) {
+                send_column(&mut world, uuid, x, z);
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the player to `position`, keeping which way they're looking. Their movement is
+    /// ignored until the client confirms it.
+    pub fn teleport(&mut self, position: (f64, f64, f64)) -> Result<(), Error> {
+        self.teleport_id = self.teleport_id.wrapping_add(1);
+        self.awaiting_teleport = Some(self.teleport_id);
+        self.player.position = position;
+        self.update_entity();
+        self.update_view()?;
+        self.write_packet(&PlayerPositionAndLookPacket {
+            position,
+            yaw: self.player.yaw,
+            pitch: self.player.pitch,
+            teleport_id: self.teleport_id
+        })?;
+        Ok(())
+    }
+
+    /// Lets the player's world know where they are and which way they're looking, for others to
+    /// see.
+    pub fn update_entity(&self) {
+        if let Some(mut world) = self.current_world() {
+            let player = &self.player;
+            world.entities_mut().move_entity(self.entity_id, player.position, player.yaw, player.pitch, player.on_ground);
+        }
+    }
+
+    /// Puts the player in `game_mode`, telling them and the players who can see them.
+    pub fn set_game_mode(&mut self, game_mode: GameMode) -> Result<(), Error> {
+        self.game_mode = game_mode;
+        // like vanilla, spectators start off flying and everyone else on their feet
+        self.flying = game_mode == GameMode::Spectator;
+        self.digging = None;
+        if let Some(mut world) = self.current_world() {
+            world.entities_mut().set_game_mode(self.entity_id, game_mode);
+        }
+        self.write_packet(&ChangeGameStatePacket::game_mode(game_mode))?;
+        self.write_packet(&PlayerAbilitiesPacket::new(game_mode, self.flying))?;
+        if let Some(uuid) = self.uuid {
+            self.write_packet(&PlayerInfoPacket::UpdateGameMode { uuid, game_mode })?;
+        }
+        Ok(())
+    }
+
+    /// Sends everything in the player's own window, what's on their cursor and which slot they
+    /// hold.
+    pub fn send_inventory(&mut self) -> Result<(), Error> {
+        self.write_packet(&WindowItemsPacket { window_id: 0, slots: self.inventory.slots.clone() })?;
+        self.write_packet(&SetSlotPacket::cursor(self.inventory.cursor.clone()))?;
+        self.write_packet(&HeldItemChangePacket { slot: self.inventory.selected as u8 })?;
+        Ok(())
+    }
+
+    /// Sends what's in one slot of the player's own window.
+    pub fn send_slot(&mut self, slot: usize) -> Result<(), Error> {
+        let stack = self.inventory.slots[slot].clone();
+        self.write_packet(&SetSlotPacket { window_id: 0, slot: slot as i16, stack })?;
+        Ok(())
+    }
+
+    /// Throws `stack` out in front of the player into `world`, where it lies as an item.
+    pub fn drop_item(&self, world: &mut World, stack: ItemStack) {
+        let player = &self.player;
+        let (yaw, pitch) = ((player.yaw as f64).to_radians(), (player.pitch as f64).to_radians());
+        let position = (player.position.0, player.position.1 + EYE_HEIGHT - 0.3, player.position.2);
+        let velocity = (-yaw.sin() * pitch.cos() * 0.3, -pitch.sin() * 0.3 + 0.1, yaw.cos() * pitch.cos() * 0.3);
+        world.entities_mut().add(Entity::item(position, velocity, stack));
+    }
+
+    /// Opens a window of `kind` for the block at `x`, `y`, `z` of `world`, the player's world,
+    /// closing any other first. Chests and furnaces show what's in their block entity, which has
+    /// to be there.
+    pub fn open_window(&mut self, world: &mut World, kind: WindowType, x: i64, y: i64, z: i64) -> Result<(), Error> {
+        let (menu, title) = match kind.menu() {
+            Some(menu) => menu,
+            None => return Ok(())
+        };
+        self.close_window(world);
+        self.window_id = self.window_id % 100 + 1;
+        let mut window = Window { id: self.window_id, kind, position: (x, y, z), slots: vec![] };
+        let mut title = format!(r#"{{"translate":"{}"}}"#, title);
+        match self.uuid {
+            Some(uuid) if kind.crafting().is_none() => {
+                if let Ok(Some(name)) = world.block_entity(x, y, z).map(|e| e.and_then(BlockEntity::custom_name)) {
+                    title = name.to_string();
+                }
+                world.open_container(uuid, window.id, x, y, z);
+            }
+            _ => window.slots = vec![None; kind.player_start()]
+        }
+        self.write_packet(&OpenWindowPacket { window_id: window.id, menu, title })?;
+        self.window = Some(window);
+        self.send_window(world)
+    }
+
+    /// The open window with id `window_id` and what's in its own slots, as long as the block it's
+    /// for is still there in `world` and the player is near enough to it.
+    pub fn window_contents(&self, world: &mut World, window_id: u8) -> Option<(WindowType, Vec<Slot>)> {
+        let window = self.window.as_ref().filter(|w| w.id == window_id)?;
+        let (kind, (x, y, z)) = (window.kind, window.position);
+        let (dx, dy, dz) = (self.player.position.0 - (x as f64 + 0.5), self.player.position.1 - (y as f64 + 0.5),
+                            self.player.position.2 - (z as f64 + 0.5));
+        if dx * dx + dy * dy + dz * dz > WINDOW_REACH_SQUARED {
+            return None;
+        }
+        let slots = window.slots.clone();
+        if kind == WindowType::Crafting {
+            return (world.get(x, y, z).ok()? == Block::CraftingTable).then_some((kind, slots));
+        }
+        let entity = world.block_entity(x, y, z).ok()??;
+        (entity.window_type() == Some(kind)).then(|| entity.slots()).flatten().map(|slots| (kind, slots))
+    }
+
+    /// Replaces what's in the open window's own slots, and lets anyone else with the same
+    /// container open know what changed. `world` has to stay locked from `window_contents` until
+    /// this, or someone else's changes in between are lost or doubled.
+    pub fn set_window_contents(&mut self, world: &mut World, contents: Vec<Slot>) {
+        let (id, kind, (x, y, z)) = match &mut self.window {
+            Some(window) if window.kind.crafting().is_some() => {
+                window.slots = contents;
+                return;
+            }
+            Some(window) => (window.id, window.kind, window.position),
+            None => return
+        };
+        let mut entity = match world.block_entity(x, y, z) {
+            Ok(Some(entity)) if entity.window_type() == Some(kind) => entity.clone(),
+            _ => return
+        };
+        let before = entity.slots().unwrap_or_default();
+        entity.set_slots(&contents);
+        if let Err(e) = world.set_block_entity(x, y, z, entity) {
+            log::error!("Could not update the container at [{},{},{}] in {}: {}", x, y, z, world.name(), e);
+            return;
+        }
+        for &(player, window_id) in world.viewers(x, y, z) {
+            if Some(player) == self.uuid && window_id == id {
+                continue;
+            }
+            for (slot, stack) in contents.iter().enumerate().filter(|(i, stack)| before.get(*i) != Some(*stack)) {
+                let packet = SetSlotPacket { window_id: window_id as i8, slot: slot as i16, stack: stack.clone() };
+                Server::global().send_to(&[player], &packet);
+            }
+        }
+    }
+
+    /// Sends everything in the open window, or the player's own if there isn't one, and what's on
+    /// their cursor.
+    pub fn send_window(&mut self, world: &mut World) -> Result<(), Error> {
+        let (id, kind, contents) = match self.window.as_ref().map(|w| w.id) {
+            Some(id) => match self.window_contents(world, id) {
+                Some((kind, contents)) => (id, kind, contents),
+                None => return self.send_inventory()
+            },
+            None => return self.send_inventory()
+        };
+        let mut slots = contents;
+        slots.extend_from_slice(&self.inventory.slots[inventory::MAIN.start..inventory::HOTBAR.end]);
+        self.write_packet(&WindowItemsPacket { window_id: id, slots })?;
+        self.write_packet(&SetSlotPacket::cursor(self.inventory.cursor.clone()))?;
+        // the off hand isn't in the window, but the swap key still reaches it
+        self.send_slot(inventory::OFFHAND)?;
+        if kind == WindowType::Furnace {
+            self.send_furnace_properties(world)?;
+        }
+        Ok(())
+    }
+
+    /// Sends how far along the furnace the player has open is.
+    fn send_furnace_properties(&mut self, world: &mut World) -> Result<(), Error> {
+        let (id, (x, y, z)) = match &self.window {
+            Some(window) => (window.id, window.position),
+            None => return Ok(())
+        };
+        let properties = match world.block_entity(x, y, z) {
+            Ok(Some(BlockEntity::Furnace(furnace))) => furnace.properties(),
+            _ => return Ok(())
+        };
+        for (property, value) in properties {
+            self.write_packet(&WindowPropertyPacket { window_id: id, property, value })?;
+        }
+        Ok(())
+    }
+
+    /// Closes whatever window the player has open, putting what's on their cursor and in the
+    /// crafting grids back in their inventory and throwing out into `world` what doesn't fit.
+    pub fn close_window(&mut self, world: &mut World) {
+        let mut leftover = vec![];
+        match self.window.take() {
+            Some(window) if window.kind.crafting().is_some() => {
+                // the result slot goes too, since it's only what the grid would make
+                leftover.extend(window.slots.into_iter().skip(1).flatten().filter_map(|stack| self.inventory.add(stack)));
+            }
+            Some(_) => {
+                if let Some(uuid) = self.uuid {
+                    world.close_containers(uuid);
+                }
+            }
+            None => {}
+        }
+        leftover.extend(self.inventory.put_away());
+        for stack in leftover {
+            self.drop_item(world, stack);
+        }
+    }
+
+    /// Closes the window with id `window_id` for a player whose client still thinks it's open.
+    pub fn force_close_window(&mut self, world: &mut World, window_id: u8) -> Result<(), Error> {
+        self.close_window(world);
+        self.write_packet(&CloseWindowPacket { window_id })?;
+        self.send_inventory()
+    }
+
+    /// Reads what was saved of the player, if anything was.
+    pub fn load_player_data(&self) -> Option<PlayerData> {
+        let uuid = self.uuid?;
+        match PlayerData::load(Path::new(&self.config.server_playerdata), uuid) {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!("Could not load player data of {}: {}", uuid, e);
+                None
+            }
+        }
+    }
+
+    /// Puts the player back how they were saved. Call it once they're in the saved world.
+    pub fn apply_player_data(&mut self, data: &PlayerData) {
+        if let Some(position) = data.position() {
+            self.player.position = position;
+        }
+        let (yaw, pitch) = data.rotation();
+        self.player.yaw = yaw;
+        self.player.pitch = pitch;
+        self.player.on_ground = data.on_ground;
+        self.player.health = data.health;
+        self.player.food_level = data.food_level;
+        self.player.saturation = data.food_saturation_level;
+        self.player.xp_level = data.xp_level;
+        self.player.xp_progress = data.xp_progress;
+        self.player.xp_total = data.xp_total;
+        self.game_mode = data.game_mode();
+        self.flying = self.game_mode == GameMode::Spectator;
+        self.inventory = PlayerInventory::from_stored(&data.inventory, data.selected_item_slot.max(0) as usize);
+        self.update_entity();
+        if let Some(mut world) = self.current_world() {
+            world.entities_mut().set_game_mode(self.entity_id, self.game_mode);
+        }
+    }
 
+    /// The player as they're saved, if they're in a world.
+    pub fn player_data(&self) -> Option<PlayerData> {
+        let player = &self.player;
+        Some(PlayerData {
+            pos: vec![player.position.0, player.position.1, player.position.2],
+            rotation: vec![player.yaw, player.pitch],
+            on_ground: player.on_ground,
+            dimension: playerdata::dimension_id(self.world.as_ref()?),
+            game_type: self.game_mode.id() as i32,
+            health: player.health,
+            food_level: player.food_level,
+            food_saturation_level: player.saturation,
+            xp_level: player.xp_level,
+            xp_progress: player.xp_progress,
+            xp_total: player.xp_total,
+            inventory: self.inventory.stored(),
+            selected_item_slot: self.inventory.selected as i32
+        })
+    }
+
+    /// Writes the player's file, if they're in a world.
+    pub fn save(&mut self) {
+        self.saves_seen = Server::global().player_saves();
+        if let (Some(data), Some(uuid)) = (self.player_data(), self.uuid) {
+            if let Err(e) = data.save(Path::new(&self.config.server_playerdata), uuid) {
+                log::error!("Could not save player data of {}: {}", uuid, e);
+            }
+        }
+    }
+
+    /// Saves the player if they've been asked to since they last were.
+    pub fn autosave(&mut self) {
+        if self.saves_seen != Server::global().player_saves() {
+            self.save();
+        }
+    }
+
+    /// Sends a system message to the player's chat.
+    pub fn send_message(&mut self, text: &str) -> Result<(), Error> {
+        self.write_packet(&ChatMessagePacket::system(text))?;
+        Ok(())
+    }
+
+    /// Adds the player to the player list of the world called `name`.
+    pub fn enter_world(&mut self, name: &str) {
+        self.leave_world();
+        if let (Some(mut world), Some(uuid)) = (Server::global().world(name), self.uuid) {
+            world.add_player(uuid);
+            self.world = Some(name.to_string());
+            self.digging = None;
+            self.awaiting_teleport = None;
+            self.view = None;
+            match world.spawn_point() {
+                Ok(spawn) => {
+                    // only where they are starts over; their health and the rest go with them
+                    self.player.position = spawn;
+                    self.player.yaw = 0.0;
+                    self.player.pitch = 0.0;
+                    self.player.on_ground = false;
+                }
+                Err(e) => log::error!("Could not find the spawn point of {}: {}", name, e)
+            }
+            let kind = EntityKind::Player { name: self.username.clone().unwrap_or_default(), game_mode: self.game_mode };
+            world.entities_mut().add(Entity::new(self.entity_id, uuid, kind, self.player.position));
+            Server::global().connect(uuid, self.connection.clone());
+        }
+    }
+
+    /// Takes the player off their world's player list, like when they disconnect.
+    pub fn leave_world(&mut self) {
+        if let (Some(name), Some(uuid)) = (self.world.take(), self.uuid) {
+            if let Some(mut world) = Server::global().world(&name) {
+                world.remove_player(uuid);
+            }
+            Server::global().disconnect(uuid);
+        }
+    }
+
+    /// Moves the player into the world called `name` with a respawn. Returns false if there's no
+    /// such world.
+    pub fn change_world(&mut self, name: &str) -> Result<bool, Error> {
+        let packet = match Server::global().world(name) {
+            Some(world) => RespawnPacket {
+                dimension_type: world.dimension_type().to_string(),
+                world_name: world.name().to_string(),
+                hashed_seed: world.hashed_seed(),
+                game_mode: self.game_mode
+            },
+            None => return Ok(false)
+        };
+
+        if let Some(mut world) = self.current_world() {
+            self.close_window(&mut world);
+        }
+        self.write_packet(&packet)?;
+        self.enter_world(name);
+        self.write_initial_play_packets()?;
+        Ok(true)
     }
 }
 
@@ -135,3 +640,34 @@ impl Writable for Client {
         }
     }
 }
+
+/// Whether the column at `x`, `z` is close enough to `centre` for a player there to have it.
+fn in_view(centre: (i32, i32), x: i32, z: i32) -> bool {
+    (x - centre.0).abs() <= VIEW_DISTANCE && (z - centre.1).abs() <= VIEW_DISTANCE
+}
+
+/// Sends `player` the column at `x`, `z` once it's loaded, and from then on its changes. By the
+/// time it is they may have left the world, moved on or been sent it already, and then it isn't.
+fn send_column(world: &mut World, player: Uuid, x: i32, z: i32) {
+    world.request_column(x, z, Box::new(move |world, result| {
+        if let Err(e) = result {
+            log::error!("Could not load column [{},{}] of {}: {}", x, z, world.name(), e);
+            return;
+        }
+        let centre = match world.entities().by_uuid(player) {
+            Some(entity) => entity.column(),
+            None => return
+        };
+        if !in_view(centre, x, z) || world.trackers(x, z).contains(&player) {
+            return;
+        }
+        match UpdateLightPacket::new(world, x, z).and_then(|light| Ok((light, ChunkDataPacket::new(world, x, z)?))) {
+            Ok((light, chunk)) => {
+                Server::global().send_to(&[player], &light);
+                Server::global().send_to(&[player], &chunk);
+                world.track_column(player, x, z);
+            }
+            Err(e) => log::error!("Could not send column [{},{}] of {}: {}", x, z, world.name(), e)
+        }
+    }));
+}
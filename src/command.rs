@@ -0,0 +1,83 @@
+use crate::server::{self, Server};
+use crate::client::{Client, Error};
+use crate::GameMode;
+
+/// Runs a single console command line, e.g. `save-all`.
+pub fn execute(line: &str) {
+    let mut args = line.split_whitespace();
+
+    match args.next() {
+        Some("save-all") => {
+            for world in Server::global().worlds() {
+                let mut world = server::lock(world);
+                log::info!("Saving {}", world.name());
+                world.save_all();
+            }
+            Server::global().save_players();
+        }
+        Some("worlds") => {
+            for line in list_worlds() {
+                log::info!("{}", line);
+            }
+        }
+        Some("reset") => match args.next().map(|name| (name, Server::global().world(name))) {
+            Some((name, Some(world))) if !world.players().is_empty() =>
+                log::warn!("Move everyone out of {} before resetting it", name),
+            Some((name, Some(mut world))) => match world.reset() {
+                Ok(true) => log::info!("Reset {}", name),
+                Ok(false) => log::warn!("{} can't be reset; only read-only and memory worlds can", name),
+                Err(e) => log::error!("Could not reset {}: {}", name, e)
+            },
+            Some((name, None)) => log::warn!("There is no world called {}", name),
+            None => log::warn!("Usage: reset <world>")
+        },
+        Some(other) => log::warn!("Unknown command {}", other),
+        None => {}
+    }
+}
+
+/// Runs a command a player typed in chat, without the leading `/`.
+pub fn execute_as(client: &mut Client, line: &str) -> Result<(), Error> {
+    let mut args = line.split_whitespace();
+
+    match args.next() {
+        Some("world") => match args.next() {
+            Some(name) => {
+                if client.world.as_deref() == Some(name) {
+                    client.send_message(&format!("You are already in {}", name))?;
+                } else if !client.change_world(name)? {
+                    client.send_message(&format!("There is no world called {}", name))?;
+                }
+            }
+            None => {
+                let current = client.world.clone().unwrap_or_default();
+                client.send_message(&format!("You are in {}", current))?;
+            }
+        },
+        Some("worlds") => {
+            for line in list_worlds() {
+                client.send_message(&line)?;
+            }
+        }
+        Some("gamemode") => match args.next().map(|name| (name, GameMode::from_name(name))) {
+            Some((_, Some(game_mode))) => {
+                client.set_game_mode(game_mode)?;
+                client.send_message(&format!("Your game mode is now {}", game_mode.name()))?;
+            }
+            Some((name, None)) => client.send_message(&format!("There is no game mode called {}", name))?,
+            None => client.send_message(&format!("You are in {}", client.game_mode.name()))?
+        },
+        Some(other) => client.send_message(&format!("Unknown command {}", other))?,
+        None => {}
+    }
+    Ok(())
+}
+
+fn list_worlds() -> Vec<String> {
+    Server::global().worlds().into_iter()
+        .map(|w| {
+            let w = server::lock(w);
+            format!("{} ({}): {} players", w.name(), w.dimension_type(), w.players().len())
+        })
+        .collect()
+}
@@ -1,23 +1,58 @@
 use serde::{Serialize, Deserialize};
 use std::fs::read_to_string;
+use std::collections::BTreeMap;
+use crate::generator::FlatGenerator;
+use crate::dimension::OVERWORLD;
+use crate::GameMode;
 
 #[derive(Clone)]
 pub struct ConcreteConfig {
     pub server_port: u16,
     pub server_motd: String,
+    pub server_custom_biomes: Option<String>,
+    pub server_datapacks: String,
+    pub server_playerdata: String,
+    pub server_default_game_mode: GameMode,
     pub networking_enable_compression: bool,
-    pub networking_online_mode: bool
+    pub networking_online_mode: bool,
+    pub world_autosave_interval: u64,
+    pub world_io_threads: usize,
+    /// The main world from `[world]` first, then the ones from `[worlds]`.
+    pub worlds: Vec<ConcreteWorldConfig>
+}
+
+#[derive(Clone)]
+pub struct ConcreteWorldConfig {
+    pub name: String,
+    pub format: String,
+    pub path: String,
+    pub dimension_type: String,
+    pub generator_type: String,
+    pub generator_layers: String,
+    pub generator_biome: String,
+    pub generator_seed: Option<String>
 }
 
 #[derive(Deserialize, Serialize, Clone)]
 pub struct Config {
-    pub server: Option<ConfigServer>
+    pub server: Option<ConfigServer>,
+    pub world: Option<ConfigWorld>,
+    /// More worlds next to the main one, by name, like `[worlds."minecraft:the_nether"]`.
+    pub worlds: Option<BTreeMap<String, ConfigExtraWorld>>
 }
 
 #[derive(Deserialize, Serialize, Clone)]
 pub struct ConfigServer {
     pub port: Option<u16>,
     pub motd: Option<String>,
+    /// A TOML or JSON file of extra biomes to add to the vanilla ones.
+    pub custom_biomes: Option<String>,
+    /// Directory of data packs to load dimension types and biomes from.
+    pub datapacks: Option<String>,
+    /// Directory players' `<uuid>.dat` files are kept in.
+    pub playerdata: Option<String>,
+    /// What new players start in: `survival`, `creative`, `adventure` or `spectator`.
+    pub default_game_mode: Option<GameMode>,
     pub networking: Option<ConfigServerNetworking>
 }
 
@@ -27,6 +62,48 @@ pub struct ConfigServerNetworking {
     pub online_mode: Option<bool>
 }
 
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ConfigWorld {
+    /// What the main world is called; `minecraft:overworld` by default.
+    pub name: Option<String>,
+    /// `single` for a Grimstone `.sng` file, `anvil` for a vanilla `region` directory or `memory`
+    /// for a world that's gone once the server stops. `single-readonly` and `anvil-readonly` never
+    /// write to the files and keep changes in memory instead, until the `reset` console command
+    /// throws them away.
+    pub format: Option<String>,
+    pub path: Option<String>,
+    /// Registered name of the dimension type the world uses, like `minecraft:overworld`.
+    pub dimension_type: Option<String>,
+    /// Seconds between autosave passes; 0 disables autosaving.
+    pub autosave_interval: Option<u64>,
+    /// Threads that read, write and generate chunks in the background.
+    pub io_threads: Option<usize>,
+    pub generator: Option<ConfigWorldGenerator>
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ConfigExtraWorld {
+    /// Same as for `[world]`, and `single` by default.
+    pub format: Option<String>,
+    pub path: String,
+    /// Defaults to the world's name, which works for vanilla's dimensions.
+    pub dimension_type: Option<String>,
+    pub generator: Option<ConfigWorldGenerator>
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ConfigWorldGenerator {
+    /// `noise`, `flat` or `void`.
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    /// Superflat layers from the bottom up, e.g. `minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block`.
+    pub layers: Option<String>,
+    pub biome: Option<String>,
+    /// Seed for new worlds; numbers are used as they are and anything else is hashed like
+    /// vanilla does. A random one is picked when left out. Worlds keep the seed they were made with.
+    pub seed: Option<String>
+}
+
 impl Config {
     pub fn load() -> Config {
         toml::from_str(read_to_string("config.toml").unwrap().as_str()).unwrap()
@@ -38,15 +115,71 @@ impl From<Config> for ConcreteConfig {
         let mut c = Self {
             server_port: 25565,
             server_motd: String::from("Hello, World!"),
+            server_custom_biomes: None,
+            server_datapacks: String::from("datapacks"),
+            server_playerdata: String::from("playerdata"),
+            server_default_game_mode: GameMode::Survival,
             networking_enable_compression: true,
-            networking_online_mode: true
+            networking_online_mode: true,
+            world_autosave_interval: 300,
+            world_io_threads: 2,
+            worlds: vec![]
         };
 
         if let Some(server) = conf.server {
             if let Some(v) = server.port { c.server_port = v; }
             if let Some(v) = server.motd { c.server_motd = v; }
+            if let Some(v) = server.custom_biomes { c.server_custom_biomes = Some(v); }
+            if let Some(v) = server.datapacks { c.server_datapacks = v; }
+            if let Some(v) = server.playerdata { c.server_playerdata = v; }
+            if let Some(v) = server.default_game_mode { c.server_default_game_mode = v; }
+        }
+
+        let mut main = ConcreteWorldConfig::new(OVERWORLD, "world.sng");
+        if let Some(world) = conf.world {
+            if let Some(v) = world.autosave_interval { c.world_autosave_interval = v; }
+            if let Some(v) = world.io_threads { c.world_io_threads = v; }
+            if let Some(v) = world.name {
+                main.dimension_type = v.clone();
+                main.name = v;
+            }
+            if let Some(v) = world.format { main.format = v; }
+            if let Some(v) = world.path { main.path = v; }
+            if let Some(v) = world.dimension_type { main.dimension_type = v; }
+            if let Some(generator) = world.generator { main.apply_generator(generator); }
+        }
+        c.worlds.push(main);
+
+        for (name, world) in conf.worlds.unwrap_or_default() {
+            let mut extra = ConcreteWorldConfig::new(&name, &world.path);
+            if let Some(v) = world.format { extra.format = v; }
+            if let Some(v) = world.dimension_type { extra.dimension_type = v; }
+            if let Some(generator) = world.generator { extra.apply_generator(generator); }
+            c.worlds.push(extra);
         }
 
         c
     }
 }
+
+impl ConcreteWorldConfig {
+    fn new(name: &str, path: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            format: String::from("single"),
+            path: path.to_string(),
+            dimension_type: name.to_string(),
+            generator_type: String::from("void"),
+            generator_layers: String::from(FlatGenerator::DEFAULT_LAYERS),
+            generator_biome: String::from("minecraft:plains"),
+            generator_seed: None
+        }
+    }
+
+    fn apply_generator(&mut self, generator: ConfigWorldGenerator) {
+        if let Some(v) = generator.kind { self.generator_type = v; }
+        if let Some(v) = generator.layers { self.generator_layers = v; }
+        if let Some(v) = generator.biome { self.generator_biome = v; }
+        if let Some(v) = generator.seed { self.generator_seed = Some(v); }
+    }
+}
@@ -0,0 +1,96 @@
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
+use serde::de::DeserializeOwned;
+use crate::biome::{self, Biome};
+use crate::dimension::DimType;
+
+/// Everything the server takes from data packs, in the order the packs were loaded. Later packs
+/// override earlier ones with the same names.
+#[derive(Default)]
+pub struct Datapacks {
+    pub dimension_types: Vec<(String, DimType)>,
+    pub biomes: Vec<(String, Biome)>
+}
+
+/// Loads every data pack folder in `dir`, in name order. Packs need a `pack.mcmeta`; zipped packs
+/// aren't supported and are skipped. A missing `dir` just means there are no packs.
+pub fn load(dir: &Path) -> Result<Datapacks, String> {
+    let mut packs = Datapacks::default();
+    if !dir.is_dir() {
+        return Ok(packs);
+    }
+
+    for pack in sorted_entries(dir)? {
+        if !pack.is_dir() {
+            log::warn!("Skipping {}, only unzipped data packs are supported", pack.display());
+            continue;
+        }
+        if !pack.join("pack.mcmeta").is_file() {
+            log::warn!("Skipping {}, it has no pack.mcmeta", pack.display());
+            continue;
+        }
+
+        let data = pack.join("data");
+        if !data.is_dir() { continue; }
+        for ns in sorted_entries(&data)? {
+            let namespace = match ns.file_name().and_then(|n| n.to_str()) {
+                Some(n) if ns.is_dir() => n.to_string(),
+                _ => continue
+            };
+
+            for (name, file) in find_json(&ns.join("dimension_type"), &namespace)? {
+                let dim_type: DimType = parse(&file)?;
+                dim_type.validate().map_err(|e| format!("{}: {}", file.display(), e))?;
+                packs.dimension_types.push((name, dim_type));
+            }
+            for (name, file) in find_json(&ns.join("worldgen").join("biome"), &namespace)? {
+                let biome: Biome = parse(&file)?;
+                biome.validate().map_err(|e| format!("{}: {}", file.display(), e))?;
+                packs.biomes.push((name, biome));
+            }
+        }
+        log::info!("Loaded data pack {}", pack.display());
+    }
+    Ok(packs)
+}
+
+fn sorted_entries(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut entries = read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{}: {}", dir.display(), e))?;
+    entries.sort();
+    Ok(entries)
+}
+
+/// Finds the JSON files under `dir` and names them like vanilla: `namespace:sub/dir/file`.
+fn find_json(dir: &Path, namespace: &str) -> Result<Vec<(String, PathBuf)>, String> {
+    let mut found = vec![];
+    if !dir.is_dir() {
+        return Ok(found);
+    }
+
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for path in sorted_entries(&current)? {
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|e| e == "json") {
+                let relative = path.strip_prefix(dir).unwrap().with_extension("");
+                let parts: Vec<_> = relative.iter().map(|p| p.to_string_lossy()).collect();
+                let name = format!("{}:{}", namespace, parts.join("/"));
+                if !biome::is_valid_name(&name) {
+                    return Err(format!("{}: {} is not a valid namespaced name", path.display(), name));
+                }
+                found.push((name, path));
+            }
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+fn parse<T: DeserializeOwned>(file: &Path) -> Result<T, String> {
+    let text = read_to_string(file).map_err(|e| format!("{}: {}", file.display(), e))?;
+    serde_json::from_str(&text).map_err(|e| format!("{}: {}", file.display(), e))
+}
@@ -0,0 +1,91 @@
+use serde::{Serialize, Deserialize};
+use std::sync::OnceLock;
+use crate::registry::Registry;
+
+pub const OVERWORLD: &str = "minecraft:overworld";
+
+/// How a dimension looks and behaves on the client, as in vanilla's `dimension_type` files.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DimType {
+    pub piglin_safe: bool,
+    pub natural: bool,
+    pub ambient_light: f32,
+    /// Locks the time of day, e.g. 18000 in the nether.
+    pub fixed_time: Option<i64>,
+    pub infiniburn: String,
+    pub respawn_anchor_works: bool,
+    pub has_skylight: bool,
+    pub bed_works: bool,
+    pub effects: String,
+    pub has_raids: bool,
+    pub min_y: i32,
+    pub height: i32,
+    pub logical_height: i32,
+    pub coordinate_scale: f64,
+    pub ultrawarm: bool,
+    pub has_ceiling: bool
+}
+
+impl DimType {
+    /// Checks the limits the 1.17 client puts on dimension types.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.height < 16 || self.height > 4064 || self.height % 16 != 0 {
+            return Err(format!("height {} has to be a multiple of 16 from 16 to 4064", self.height));
+        }
+        if self.min_y < -2032 || self.min_y > 2031 || self.min_y % 16 != 0 {
+            return Err(format!("min_y {} has to be a multiple of 16 from -2032 to 2031", self.min_y));
+        }
+        if self.min_y + self.height > 2032 {
+            return Err(format!("min_y {} plus height {} goes above 2032", self.min_y, self.height));
+        }
+        if self.logical_height < 0 || self.logical_height > self.height {
+            return Err(format!("logical_height {} has to be from 0 to the height", self.logical_height));
+        }
+        if !(0.0..=1.0).contains(&self.ambient_light) {
+            return Err(format!("ambient_light {} has to be from 0 to 1", self.ambient_light));
+        }
+        if !(0.00001..=30000000.0).contains(&self.coordinate_scale) {
+            return Err(format!("coordinate_scale {} is out of range", self.coordinate_scale));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct VanillaDimType {
+    name: String,
+    id: i32,
+    element: DimType
+}
+
+static DIMENSION_TYPES: OnceLock<Registry<'static, DimType>> = OnceLock::new();
+
+/// Vanilla 1.17's dimension types, under vanilla's ids.
+pub fn vanilla() -> Registry<'static, DimType> {
+    let types: Vec<VanillaDimType> = serde_json::from_str(include_str!("data/dimension_types.json"))
+        .expect("Built-in dimension type data is broken");
+
+    let mut registry = Registry::new("minecraft:dimension_type");
+    for t in types {
+        registry.register_with_id(&t.name, t.id, t.element);
+    }
+    registry
+}
+
+/// Sets up the dimension type registry with the vanilla types and `extra` on top of them; one
+/// with a vanilla name replaces it.
+pub fn init(extra: Vec<(String, DimType)>) -> Result<(), String> {
+    let mut registry = vanilla();
+    for (name, dim_type) in extra {
+        registry.replace(&name, dim_type);
+    }
+    DIMENSION_TYPES.set(registry).map_err(|_| "the dimension type registry is already set up".to_string())
+}
+
+pub fn registry() -> &'static Registry<'static, DimType> {
+    DIMENSION_TYPES.get_or_init(vanilla)
+}
+
+pub fn get(name: &str) -> Option<&'static DimType> {
+    registry().get(name).map(|e| &e.element)
+}
@@ -0,0 +1,463 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicI32, Ordering};
+use uuid::Uuid;
+use crate::world::{BoundingBox, ChunkContainer, World, WorldError};
+use crate::GameMode;
+use crate::inventory::Slot;
+use crate::item::ItemStack;
+
+pub const PLAYER_WIDTH: f64 = 0.6;
+pub const PLAYER_HEIGHT: f64 = 1.8;
+/// How far above their feet a standing player's eyes are.
+pub const EYE_HEIGHT: f64 = 1.62;
+
+/// How far in from its sides a box is checked for collisions, so one resting exactly on or
+/// against a block doesn't count as inside it.
+const COLLISION_MARGIN: f64 = 1.0e-7;
+
+/// Where a player is, which way they're looking and how they're doing, as far as the server is
+/// concerned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerEntity {
+    /// Where the player's feet are.
+    pub position: (f64, f64, f64),
+    /// Degrees clockwise from facing south.
+    pub yaw: f32,
+    /// Degrees down from looking straight ahead.
+    pub pitch: f32,
+    pub on_ground: bool,
+    /// Half-hearts, up to 20.
+    pub health: f32,
+    /// Up to 20.
+    pub food_level: i32,
+    pub saturation: f32,
+    pub xp_level: i32,
+    /// How far the player is towards the next level, from 0 to 1.
+    pub xp_progress: f32,
+    pub xp_total: i32
+}
+
+impl PlayerEntity {
+    pub fn new(position: (f64, f64, f64)) -> PlayerEntity {
+        PlayerEntity {
+            position,
+            yaw: 0.0,
+            pitch: 0.0,
+            on_ground: false,
+            health: 20.0,
+            food_level: 20,
+            saturation: 5.0,
+            xp_level: 0,
+            xp_progress: 0.0,
+            xp_total: 0
+        }
+    }
+
+    /// The low and high corners of the box a player standing at `position` takes up.
+    pub fn bounding_box(position: (f64, f64, f64)) -> BoundingBox {
+        let half = PLAYER_WIDTH / 2.0;
+        ((position.0 - half, position.1, position.2 - half),
+            (position.0 + half, position.1 + PLAYER_HEIGHT, position.2 + half))
+    }
+
+    /// The blocks a player standing at `position` would be inside of, going by their collision
+    /// boxes.
+    pub fn colliding_blocks(world: &mut World, position: (f64, f64, f64)) -> Result<Vec<(i64, i64, i64)>, WorldError> {
+        let (low, high) = PlayerEntity::bounding_box(position);
+        let (low, high) = ((low.0 + COLLISION_MARGIN, low.1 + COLLISION_MARGIN, low.2 + COLLISION_MARGIN),
+            (high.0 - COLLISION_MARGIN, high.1 - COLLISION_MARGIN, high.2 - COLLISION_MARGIN));
+
+        let mut blocks = Vec::new();
+        for x in low.0.floor() as i64..=high.0.floor() as i64 {
+            for y in low.1.floor() as i64..=high.1.floor() as i64 {
+                if !world.contains_y(y) {
+                    continue;
+                }
+                for z in low.2.floor() as i64..=high.2.floor() as i64 {
+                    let (from, to) = match world.get(x, y, z)?.collision_box() {
+                        Some(shape) => shape,
+                        None => continue
+                    };
+                    let (bx, by, bz) = (x as f64, y as f64, z as f64);
+                    if low.0 < bx + to.0 && high.0 > bx + from.0
+                        && low.1 < by + to.1 && high.1 > by + from.1
+                        && low.2 < bz + to.2 && high.2 > bz + from.2 {
+                        blocks.push((x, y, z));
+                    }
+                }
+            }
+        }
+        Ok(blocks)
+    }
+}
+
+static NEXT_ID: AtomicI32 = AtomicI32::new(1);
+
+/// A new entity id, different from every other one handed out while the server runs.
+pub fn next_id() -> i32 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Bits of the flags every entity has as metadata.
+pub const FLAGS_INDEX: u8 = 0;
+pub const FLAG_ON_FIRE: i8 = 0x01;
+pub const FLAG_CROUCHING: i8 = 0x02;
+pub const FLAG_SPRINTING: i8 = 0x08;
+pub const FLAG_SWIMMING: i8 = 0x10;
+pub const FLAG_INVISIBLE: i8 = 0x20;
+pub const FLAG_GLOWING: i8 = 0x40;
+pub const POSE_INDEX: u8 = 6;
+/// The stack an item entity is of.
+pub const ITEM_INDEX: u8 = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntityKind {
+    Player {
+        name: String,
+        game_mode: GameMode
+    },
+    Item
+}
+
+impl EntityKind {
+    /// The vanilla 1.17 id of the entity type, as sent to clients.
+    pub fn type_id(&self) -> i32 {
+        match self {
+            EntityKind::Player { .. } => 111,
+            EntityKind::Item => 41
+        }
+    }
+
+    /// How many columns away players can see entities of this kind from, like vanilla's
+    /// `clientTrackingRange`.
+    pub fn tracking_range(&self) -> i32 {
+        match self {
+            EntityKind::Player { .. } => 32,
+            EntityKind::Item => 6
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Pose {
+    Standing,
+    FallFlying,
+    Sleeping,
+    Swimming,
+    SpinAttack,
+    Crouching
+}
+
+/// A value in an entity's metadata; the client knows what each index means for each kind.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataValue {
+    Byte(i8),
+    VarInt(i32),
+    Float(f32),
+    String(String),
+    Boolean(bool),
+    Slot(Slot),
+    Pose(Pose)
+}
+
+/// Metadata values by index.
+pub type Metadata = Vec<(u8, MetadataValue)>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub kind: EntityKind,
+    pub position: (f64, f64, f64),
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool,
+    /// Blocks per tick the entity was given when it spawned, which clients carry on with.
+    pub velocity: (f64, f64, f64),
+    pub metadata: BTreeMap<u8, MetadataValue>
+}
+
+impl Entity {
+    pub fn new(id: i32, uuid: Uuid, kind: EntityKind, position: (f64, f64, f64)) -> Entity {
+        let mut metadata = BTreeMap::new();
+        metadata.insert(FLAGS_INDEX, MetadataValue::Byte(0));
+        metadata.insert(POSE_INDEX, MetadataValue::Pose(Pose::Standing));
+        Entity { id, uuid, kind, position, yaw: 0.0, pitch: 0.0, on_ground: false, velocity: (0.0, 0.0, 0.0), metadata }
+    }
+
+    /// A new item entity of `stack`, thrown off at `velocity`.
+    pub fn item(position: (f64, f64, f64), velocity: (f64, f64, f64), stack: ItemStack) -> Entity {
+        let mut item = Entity::new(next_id(), Uuid::from_u128(rand::random()), EntityKind::Item, position);
+        item.velocity = velocity;
+        item.metadata.insert(ITEM_INDEX, MetadataValue::Slot(Some(stack)));
+        item
+    }
+
+    /// The column the entity is in.
+    pub fn column(&self) -> (i32, i32) {
+        ((self.position.0 / 16.0).floor() as i32, (self.position.2 / 16.0).floor() as i32)
+    }
+
+    pub fn flags(&self) -> i8 {
+        match self.metadata.get(&FLAGS_INDEX) {
+            Some(MetadataValue::Byte(flags)) => *flags,
+            _ => 0
+        }
+    }
+}
+
+/// An entity that moved or turned since players watching it were last told.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movement {
+    pub id: i32,
+    /// Where the players watching it think it is.
+    pub from: (f64, f64, f64),
+    pub to: (f64, f64, f64),
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool,
+    pub moved: bool,
+    pub turned: bool
+}
+
+/// What players need to be told about the entities around them, from `EntityStore::take_changes`.
+#[derive(Debug, Default)]
+pub struct EntityChanges {
+    /// Entities that came into view, with the player who should now be shown them.
+    pub spawned: Vec<(Uuid, Entity)>,
+    /// Ids of entities that went out of view or away, with the player who should stop seeing them.
+    pub destroyed: Vec<(Uuid, i32)>,
+    /// Players that left the world, with a player who had been shown them.
+    pub departed: Vec<(Uuid, Uuid)>,
+    /// Entities that moved or turned, with the players already watching them.
+    pub moved: Vec<(Vec<Uuid>, Movement)>,
+    /// Metadata that changed, with the players already watching the entity.
+    pub metadata: Vec<(Vec<Uuid>, i32, Metadata)>,
+    /// Players whose game mode changed, with the players already watching them.
+    pub game_modes: Vec<(Vec<Uuid>, Uuid, GameMode)>
+}
+
+/// Where the players watching an entity were last told it was.
+#[derive(Debug, Clone, Copy)]
+struct Sent {
+    position: (f64, f64, f64),
+    yaw: f32,
+    pitch: f32
+}
+
+/// The entities in a world, indexed by the column they're in, and which of them each player in
+/// the world has been shown.
+#[derive(Debug, Default)]
+pub struct EntityStore {
+    entities: HashMap<i32, Entity>,
+    by_uuid: HashMap<Uuid, i32>,
+    columns: HashMap<(i32, i32), HashSet<i32>>,
+    sent: HashMap<i32, Sent>,
+    /// Metadata indices changed since players were last told, by entity.
+    changed_metadata: HashMap<i32, HashSet<u8>>,
+    /// Players whose game mode changed since players were last told.
+    changed_game_modes: HashSet<i32>,
+    /// Players removed since the last `take_changes`, by entity id.
+    departed: HashMap<i32, Uuid>,
+    /// The entities each player has been shown, by player.
+    shown: HashMap<Uuid, HashSet<i32>>
+}
+
+impl EntityStore {
+    pub fn new() -> EntityStore {
+        EntityStore::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn get(&self, id: i32) -> Option<&Entity> {
+        self.entities.get(&id)
+    }
+
+    pub fn by_uuid(&self, uuid: Uuid) -> Option<&Entity> {
+        self.by_uuid.get(&uuid).and_then(|id| self.entities.get(id))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Entity> {
+        self.entities.values()
+    }
+
+    /// The entities in the column at `x`, `z`.
+    pub fn in_column(&self, x: i32, z: i32) -> impl Iterator<Item = &Entity> {
+        self.columns.get(&(x, z)).into_iter().flatten().filter_map(move |id| self.entities.get(id))
+    }
+
+    /// Adds `entity`, replacing one with the same id.
+    pub fn add(&mut self, entity: Entity) {
+        self.remove(entity.id);
+        self.columns.entry(entity.column()).or_default().insert(entity.id);
+        self.by_uuid.insert(entity.uuid, entity.id);
+        self.sent.insert(entity.id, Sent { position: entity.position, yaw: entity.yaw, pitch: entity.pitch });
+        self.departed.remove(&entity.id);
+        self.entities.insert(entity.id, entity);
+    }
+
+    pub fn remove(&mut self, id: i32) -> Option<Entity> {
+        let entity = self.entities.remove(&id)?;
+        let column = entity.column();
+        if let Some(ids) = self.columns.get_mut(&column) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.columns.remove(&column);
+            }
+        }
+        self.by_uuid.remove(&entity.uuid);
+        self.sent.remove(&id);
+        self.changed_metadata.remove(&id);
+        self.changed_game_modes.remove(&id);
+        if let EntityKind::Player { .. } = entity.kind {
+            self.departed.insert(id, entity.uuid);
+        }
+        Some(entity)
+    }
+
+    /// Puts the entity `id` at `position` facing `yaw` and `pitch`. Gives back false if there's
+    /// no such entity.
+    pub fn move_entity(&mut self, id: i32, position: (f64, f64, f64), yaw: f32, pitch: f32, on_ground: bool) -> bool {
+        let entity = match self.entities.get_mut(&id) {
+            Some(entity) => entity,
+            None => return false
+        };
+        let from = entity.column();
+        entity.position = position;
+        entity.yaw = yaw;
+        entity.pitch = pitch;
+        entity.on_ground = on_ground;
+        let to = entity.column();
+        if from != to {
+            if let Some(ids) = self.columns.get_mut(&from) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.columns.remove(&from);
+                }
+            }
+            self.columns.entry(to).or_default().insert(id);
+        }
+        true
+    }
+
+    /// Sets one of the entity's metadata values, which players watching it are then told about.
+    pub fn set_metadata(&mut self, id: i32, index: u8, value: MetadataValue) -> bool {
+        let entity = match self.entities.get_mut(&id) {
+            Some(entity) => entity,
+            None => return false
+        };
+        if entity.metadata.get(&index) != Some(&value) {
+            entity.metadata.insert(index, value);
+            self.changed_metadata.entry(id).or_default().insert(index);
+        }
+        true
+    }
+
+    /// Sets the game mode of the player entity `id`, which players watching it are then told about.
+    /// Gives back false if there's no such player.
+    pub fn set_game_mode(&mut self, id: i32, mode: GameMode) -> bool {
+        match self.entities.get_mut(&id).map(|entity| &mut entity.kind) {
+            Some(EntityKind::Player { game_mode, .. }) => {
+                if *game_mode != mode {
+                    *game_mode = mode;
+                    self.changed_game_modes.insert(id);
+                }
+                true
+            }
+            _ => false
+        }
+    }
+
+    /// Forgets what `player` has been shown, like when they leave the world.
+    pub fn remove_viewer(&mut self, player: Uuid) {
+        self.shown.remove(&player);
+    }
+
+    /// Works out what each player in `tracked` needs to be told since the last call: an entity is
+    /// in view while its column is one the player has, within its kind's tracking range of the
+    /// player's own entity. Players are never shown themselves.
+    pub fn take_changes(&mut self, tracked: &HashMap<Uuid, HashSet<(i32, i32)>>) -> EntityChanges {
+        let mut changes = EntityChanges::default();
+        let mut watchers: HashMap<i32, Vec<Uuid>> = HashMap::new();
+
+        for (player, columns) in tracked {
+            let (own, (px, pz)) = match self.by_uuid(*player) {
+                Some(entity) => (entity.id, entity.column()),
+                None => continue
+            };
+            let visible: HashSet<i32> = self.entities.values()
+                .filter(|e| e.id != own)
+                .filter(|e| {
+                    let (x, z) = e.column();
+                    let range = e.kind.tracking_range();
+                    columns.contains(&(x, z)) && (x - px).abs() <= range && (z - pz).abs() <= range
+                })
+                .map(|e| e.id)
+                .collect();
+
+            let shown = self.shown.entry(*player).or_default();
+            for id in shown.iter() {
+                if visible.contains(id) {
+                    watchers.entry(*id).or_default().push(*player);
+                } else {
+                    changes.destroyed.push((*player, *id));
+                    if let Some(uuid) = self.departed.get(id) {
+                        changes.departed.push((*player, *uuid));
+                    }
+                }
+            }
+            for id in visible.difference(shown) {
+                changes.spawned.push((*player, self.entities[id].clone()));
+            }
+            *shown = visible;
+        }
+        self.shown.retain(|player, _| tracked.contains_key(player));
+        self.departed.clear();
+
+        for (id, sent) in self.sent.iter_mut() {
+            let entity = &self.entities[id];
+            let moved = entity.position != sent.position;
+            let turned = entity.yaw != sent.yaw || entity.pitch != sent.pitch;
+            if moved || turned {
+                if let Some(players) = watchers.get(id) {
+                    changes.moved.push((players.clone(), Movement {
+                        id: *id,
+                        from: sent.position,
+                        to: entity.position,
+                        yaw: entity.yaw,
+                        pitch: entity.pitch,
+                        on_ground: entity.on_ground,
+                        moved,
+                        turned
+                    }));
+                }
+                *sent = Sent { position: entity.position, yaw: entity.yaw, pitch: entity.pitch };
+            }
+        }
+
+        for (id, indices) in self.changed_metadata.drain() {
+            if let (Some(players), Some(entity)) = (watchers.get(&id), self.entities.get(&id)) {
+                let values = indices.into_iter()
+                    .filter_map(|i| entity.metadata.get(&i).map(|v| (i, v.clone())))
+                    .collect();
+                changes.metadata.push((players.clone(), id, values));
+            }
+        }
+
+        for id in self.changed_game_modes.drain() {
+            if let (Some(players), Some(entity)) = (watchers.get(&id), self.entities.get(&id)) {
+                if let EntityKind::Player { game_mode, .. } = entity.kind {
+                    changes.game_modes.push((players.clone(), entity.uuid, game_mode));
+                }
+            }
+        }
+        changes
+    }
+}
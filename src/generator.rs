@@ -0,0 +1,282 @@
+use crate::world::{Chunk, Block, BlockContainer};
+use crate::noise::OctaveNoise;
+use crate::biome::{self, BiomeId};
+
+/// Fills in chunks that have never been saved. Generators run on the chunk I/O threads, so they
+/// can be slow but must be shareable.
+pub trait ChunkGenerator: Send + Sync {
+    /// Builds the chunk at chunk coordinates `x`, `y`, `z` of a world whose blocks go from
+    /// `min_y` up for `height`. It should come back dirty so it gets saved.
+    fn generate(&self, x: i32, y: i32, z: i32, min_y: i64, height: i64) -> Chunk;
+}
+
+/// Generates nothing but air.
+pub struct VoidGenerator;
+
+impl ChunkGenerator for VoidGenerator {
+    fn generate(&self, x: i32, y: i32, z: i32, _min_y: i64, _height: i64) -> Chunk {
+        Chunk::generate_new(x, y, z)
+    }
+}
+
+/// Stacks the same layers everywhere, from the bottom of the world upwards, like vanilla's
+/// superflat.
+pub struct FlatGenerator {
+    layers: Vec<Block>,
+    biome: BiomeId
+}
+
+impl FlatGenerator {
+    /// The layers vanilla uses when nothing else is picked.
+    pub const DEFAULT_LAYERS: &'static str = "minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block";
+
+    /// Parses a vanilla layer string, bottom layer first: `minecraft:bedrock,2*minecraft:dirt`.
+    /// The namespace can be left out.
+    pub fn new(layers: &str, biome: &str) -> Result<FlatGenerator, String> {
+        let mut blocks = vec![];
+        for layer in layers.split(',').map(str::trim).filter(|l| !l.is_empty()) {
+            let (count, name) = match layer.split_once('*') {
+                Some((count, name)) => (
+                    count.trim().parse::<usize>().map_err(|_| format!("bad layer count in {}", layer))?,
+                    name.trim()
+                ),
+                None => (1, layer)
+            };
+            let name = if name.contains(':') { name.to_string() } else { format!("minecraft:{}", name) };
+            let block = Block::from_name(&name).ok_or_else(|| format!("unknown block {}", name))?;
+            blocks.extend(std::iter::repeat_n(block, count));
+        }
+
+        let biome = biome::id_of(biome).ok_or_else(|| format!("unknown biome {}", biome))?;
+        Ok(FlatGenerator { layers: blocks, biome })
+    }
+
+    /// Which biome the whole world is.
+    pub fn biome(&self) -> BiomeId {
+        self.biome
+    }
+}
+
+impl ChunkGenerator for FlatGenerator {
+    fn generate(&self, x: i32, y: i32, z: i32, min_y: i64, height: i64) -> Chunk {
+        let mut chunk = Chunk::generate_new(x, y, z);
+        chunk.fill_biome(self.biome);
+        for ly in 0..16 {
+            let layer = y as i64 * 16 + ly - min_y;
+            if layer < 0 || layer >= height || layer >= self.layers.len() as i64 { continue; }
+
+            let block = self.layers[layer as usize];
+            if block == Block::Air { continue; }
+            for lz in 0..16 {
+                for lx in 0..16 {
+                    chunk.put(lx, ly, lz, block);
+                }
+            }
+        }
+        chunk
+    }
+}
+
+/// Turns a seed from the config into a number like vanilla does: numbers are taken as they are
+/// and anything else is hashed with Java's `String.hashCode`.
+pub fn parse_seed(seed: &str) -> i64 {
+    let seed = seed.trim();
+    match seed.parse::<i64>() {
+        Ok(seed) => seed,
+        Err(_) => seed.encode_utf16().fold(0i32, |h, c| h.wrapping_mul(31).wrapping_add(c as i32)) as i64
+    }
+}
+
+/// Where oceans fill up to, like vanilla.
+pub const SEA_LEVEL: i64 = 62;
+
+/// How a biome covers the stone underneath it.
+struct Surface {
+    biome: &'static str,
+    /// The top block above sea level.
+    top: Block,
+    /// The top block below sea level.
+    underwater: Block,
+    filler: Block,
+    /// How many blocks of filler go under the top block.
+    depth: i64,
+    /// What goes under the filler before the stone starts, if anything.
+    base: Option<Block>,
+    /// Laid on top of the surface, like snow.
+    cover: Option<Block>,
+    frozen: bool
+}
+
+const PLAINS: Surface = Surface {
+    biome: "minecraft:plains", top: Block::GrassBlock, underwater: Block::Dirt, filler: Block::Dirt,
+    depth: 3, base: None, cover: None, frozen: false
+};
+const FOREST: Surface = Surface { biome: "minecraft:forest", ..PLAINS };
+const MOUNTAINS: Surface = Surface { biome: "minecraft:mountains", filler: Block::Stone, depth: 1, ..PLAINS };
+const SNOWY_TUNDRA: Surface = Surface {
+    biome: "minecraft:snowy_tundra", cover: Some(Block::Snow), frozen: true, ..PLAINS
+};
+const DESERT: Surface = Surface {
+    biome: "minecraft:desert", top: Block::Sand, underwater: Block::Sand, filler: Block::Sand,
+    depth: 4, base: Some(Block::Sandstone), cover: None, frozen: false
+};
+const BEACH: Surface = Surface { biome: "minecraft:beach", ..DESERT };
+const SNOWY_BEACH: Surface = Surface { biome: "minecraft:snowy_beach", frozen: true, ..DESERT };
+const OCEAN: Surface = Surface {
+    biome: "minecraft:ocean", top: Block::Gravel, underwater: Block::Gravel, filler: Block::Gravel,
+    depth: 3, base: None, cover: None, frozen: false
+};
+const FROZEN_OCEAN: Surface = Surface { biome: "minecraft:frozen_ocean", frozen: true, ..OCEAN };
+
+/// Survival-style terrain from seeded Perlin noise: rolling height, oceans up to `SEA_LEVEL`,
+/// climate-picked biomes with their own surfaces, and caves carved out by 3D noise. The same seed
+/// always gives the same world.
+pub struct NoiseGenerator {
+    seed: i64,
+    height: OctaveNoise,
+    detail: OctaveNoise,
+    temperature: OctaveNoise,
+    humidity: OctaveNoise,
+    /// Two noises whose zero crossings meet in long tunnels.
+    tunnel_a: OctaveNoise,
+    tunnel_b: OctaveNoise,
+    /// Big open caverns where this noise peaks.
+    caverns: OctaveNoise
+}
+
+impl NoiseGenerator {
+    pub fn new(seed: i64) -> NoiseGenerator {
+        NoiseGenerator {
+            seed,
+            height: OctaveNoise::new(seed, 1, 6),
+            detail: OctaveNoise::new(seed, 2, 3),
+            temperature: OctaveNoise::new(seed, 3, 3),
+            humidity: OctaveNoise::new(seed, 4, 3),
+            tunnel_a: OctaveNoise::new(seed, 5, 2),
+            tunnel_b: OctaveNoise::new(seed, 6, 2),
+            caverns: OctaveNoise::new(seed, 7, 2)
+        }
+    }
+
+    pub fn seed(&self) -> i64 {
+        self.seed
+    }
+
+    /// Height of the surface in the column at `x`, `z`.
+    pub fn height_at(&self, x: i64, z: i64) -> i64 {
+        let (x, z) = (x as f64, z as f64);
+        let continent = self.height.sample_2d(x / 256.0, z / 256.0);
+        let hills = self.detail.sample_2d(x / 48.0, z / 48.0);
+        // the higher the land already is, the more it gets pushed up into mountains
+        let mountains = continent.max(0.0).powi(2) * 160.0;
+        (SEA_LEVEL as f64 + 2.0 + continent * 56.0 + mountains + hills * 5.0).round() as i64
+    }
+
+    /// Name of the biome of the column at `x`, `z`.
+    pub fn biome_at(&self, x: i64, z: i64) -> &'static str {
+        self.surface(x, z, self.height_at(x, z)).biome
+    }
+
+    fn surface(&self, x: i64, z: i64, height: i64) -> &'static Surface {
+        let temperature = self.temperature.sample_2d(x as f64 / 512.0, z as f64 / 512.0);
+        let humidity = self.humidity.sample_2d(x as f64 / 512.0, z as f64 / 512.0);
+        let cold = temperature < -0.25;
+
+        if height < SEA_LEVEL - 3 {
+            if cold { &FROZEN_OCEAN } else { &OCEAN }
+        } else if height <= SEA_LEVEL + 1 {
+            if cold { &SNOWY_BEACH } else { &BEACH }
+        } else if cold {
+            &SNOWY_TUNDRA
+        } else if height > 110 {
+            &MOUNTAINS
+        } else if temperature > 0.25 && humidity < 0.0 {
+            &DESERT
+        } else if humidity > 0.15 {
+            &FOREST
+        } else {
+            &PLAINS
+        }
+    }
+
+    fn is_cave(&self, x: i64, y: i64, z: i64, height: i64) -> bool {
+        let (fx, fy, fz) = (x as f64, y as f64, z as f64);
+        let a = self.tunnel_a.sample(fx / 64.0, fy / 40.0, fz / 64.0);
+        let b = self.tunnel_b.sample(fx / 64.0, fy / 40.0, fz / 64.0);
+        if a.abs() < 0.05 && b.abs() < 0.05 {
+            return true;
+        }
+        // caverns stay well under the surface so they don't leave craters
+        y < height - 12 && self.caverns.sample(fx / 96.0, fy / 48.0, fz / 96.0) > 0.42
+    }
+
+    /// Bedrock thins out over the bottom five layers, deterministically for each position. `y`
+    /// counts up from the bottom of the world.
+    fn is_bedrock(&self, x: i64, y: i64, z: i64) -> bool {
+        let mut h = self.seed as u64 ^ (x as u64).wrapping_mul(0x2545_F491_4F6C_DD1D);
+        h ^= (y as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ (z as u64).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        h ^= h >> 31;
+        h = h.wrapping_mul(0x94D0_49BB_1331_11EB);
+        h ^= h >> 29;
+        y == 0 || (y < 5 && (h % 5) as i64 >= y)
+    }
+}
+
+impl ChunkGenerator for NoiseGenerator {
+    fn generate(&self, cx: i32, cy: i32, cz: i32, min_y: i64, world_height: i64) -> Chunk {
+        let mut chunk = Chunk::generate_new(cx, cy, cz);
+        for bz in 0..4 {
+            for bx in 0..4 {
+                // biomes are picked from the middle of each 4x4 column of cells
+                let (x, z) = (cx as i64 * 16 + bx * 4 + 2, cz as i64 * 16 + bz * 4 + 2);
+                let id = biome::id_of(self.biome_at(x, z)).unwrap_or_else(biome::default_id);
+                for by in 0..4 {
+                    chunk.set_biome(bx * 4, by * 4, bz * 4, id);
+                }
+            }
+        }
+
+        for lz in 0..16 {
+            for lx in 0..16 {
+                let (x, z) = (cx as i64 * 16 + lx, cz as i64 * 16 + lz);
+                // mountains can reach past the top of a short world
+                let height = self.height_at(x, z).clamp(min_y, min_y + world_height - 1);
+                let surface = self.surface(x, z, height);
+
+                for ly in 0..16 {
+                    let y = cy as i64 * 16 + ly;
+                    let block = if y <= height {
+                        let depth = height - y;
+                        if self.is_bedrock(x, y - min_y, z) {
+                            Block::Bedrock
+                        } else if (depth >= 8 || height >= SEA_LEVEL) && self.is_cave(x, y, z, height) {
+                            // caves don't open up into the sea, and flood with lava at the bottom
+                            // of the world like vanilla's
+                            if y <= min_y + 10 { Block::Lava } else { Block::Air }
+                        } else if depth == 0 {
+                            if height >= SEA_LEVEL { surface.top } else { surface.underwater }
+                        } else if depth <= surface.depth {
+                            surface.filler
+                        } else {
+                            match surface.base {
+                                Some(base) if depth <= surface.depth + 3 => base,
+                                _ => Block::Stone
+                            }
+                        }
+                    } else if y <= SEA_LEVEL {
+                        if y == SEA_LEVEL && surface.frozen { Block::Ice } else { Block::Water }
+                    } else if y == height + 1 {
+                        surface.cover.unwrap_or(Block::Air)
+                    } else {
+                        Block::Air
+                    };
+
+                    if block != Block::Air {
+                        chunk.put(lx, ly, lz, block);
+                    }
+                }
+            }
+        }
+        chunk
+    }
+}
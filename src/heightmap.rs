@@ -0,0 +1,206 @@
+use crate::world::Block;
+
+/// The heightmaps Grimstone keeps, named like vanilla's.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum HeightmapKind {
+    /// The highest block that blocks movement or holds a fluid; what the client uses for rain and
+    /// what mobs spawn on.
+    MotionBlocking,
+    /// The highest block that isn't air.
+    WorldSurface
+}
+
+impl HeightmapKind {
+    pub const ALL: [HeightmapKind; 2] = [HeightmapKind::MotionBlocking, HeightmapKind::WorldSurface];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HeightmapKind::MotionBlocking => "MOTION_BLOCKING",
+            HeightmapKind::WorldSurface => "WORLD_SURFACE"
+        }
+    }
+
+    /// Whether `block` counts as the top of a column for this heightmap.
+    pub fn counts(&self, block: Block) -> bool {
+        match self {
+            HeightmapKind::MotionBlocking => block.blocks_motion() || block.holds_fluid(),
+            HeightmapKind::WorldSurface => block != Block::Air
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// Heights of the 16x16 block columns of a chunk column, packed into longs the way the protocol
+/// and Anvil do. Heights count from the bottom of the world and are one above the top block, so
+/// 0 means the column has no such block.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Heightmap {
+    bits: usize,
+    longs: Vec<i64>
+}
+
+impl Heightmap {
+    /// An empty heightmap for a world `height` blocks tall.
+    pub fn for_height(height: i64) -> Heightmap {
+        Heightmap::with_bits((u64::BITS - (height as u64).leading_zeros()) as usize)
+    }
+
+    fn with_bits(bits: usize) -> Heightmap {
+        Heightmap { bits, longs: vec![0; 256usize.div_ceil(64 / bits)] }
+    }
+
+    /// Takes packed longs as they were stored, if there are the right number of them.
+    pub fn from_longs(height: i64, longs: Vec<i64>) -> Option<Heightmap> {
+        let mut map = Heightmap::for_height(height);
+        if longs.len() != map.longs.len() {
+            return None;
+        }
+        map.longs = longs;
+        Some(map)
+    }
+
+    pub fn longs(&self) -> &[i64] {
+        &self.longs
+    }
+
+    pub fn get(&self, x: usize, z: usize) -> u32 {
+        let (long, shift) = self.locate(x, z);
+        ((self.longs[long] as u64 >> shift) & self.mask()) as u32
+    }
+
+    pub fn set(&mut self, x: usize, z: usize, height: u32) {
+        let (long, shift) = self.locate(x, z);
+        let mask = self.mask();
+        let value = self.longs[long] as u64 & !(mask << shift) | (height as u64 & mask) << shift;
+        self.longs[long] = value as i64;
+    }
+
+    fn mask(&self) -> u64 {
+        (1 << self.bits) - 1
+    }
+
+    fn locate(&self, x: usize, z: usize) -> (usize, usize) {
+        let per_long = 64 / self.bits;
+        let i = z * 16 + x;
+        (i / per_long, (i % per_long) * self.bits)
+    }
+}
+
+/// Where the top block of each kind is in every block column of a single 16x16x16 chunk, so a
+/// column's heightmaps can be put together from its chunks without looking at every block.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ChunkHeightmaps {
+    /// One above the chunk-relative Y of the top block, or 0, for each kind and column.
+    tops: [[u8; 256]; 2]
+}
+
+impl ChunkHeightmaps {
+    /// Bits a chunk-relative height takes when packed.
+    const BITS: usize = 5;
+
+    pub fn empty() -> ChunkHeightmaps {
+        ChunkHeightmaps { tops: [[0; 256]; 2] }
+    }
+
+    pub fn compute(blocks: &[Block; 16 * 16 * 16]) -> ChunkHeightmaps {
+        let mut maps = ChunkHeightmaps::empty();
+        for z in 0..16 {
+            for x in 0..16 {
+                for kind in HeightmapKind::ALL {
+                    maps.tops[kind.index()][z * 16 + x] = scan(blocks, kind, x, z, 16);
+                }
+            }
+        }
+        maps
+    }
+
+    /// Chunk-relative Y of the top block of `kind` in the column at `x`, `z`.
+    pub fn top(&self, kind: HeightmapKind, x: usize, z: usize) -> Option<u8> {
+        self.tops[kind.index()][z * 16 + x].checked_sub(1)
+    }
+
+    /// Catches up with the block at `x`, `y`, `z` having changed.
+    pub fn update(&mut self, blocks: &[Block; 16 * 16 * 16], x: usize, y: usize, z: usize) {
+        let block = blocks[(y << 8) | (z << 4) | x];
+        for kind in HeightmapKind::ALL {
+            let top = &mut self.tops[kind.index()][z * 16 + x];
+            if kind.counts(block) {
+                *top = (*top).max(y as u8 + 1);
+            } else if *top == y as u8 + 1 {
+                *top = scan(blocks, kind, x, z, y);
+            }
+        }
+    }
+
+    /// The heightmap of `kind`, packed for storage.
+    pub fn packed(&self, kind: HeightmapKind) -> Vec<i64> {
+        let mut map = Heightmap::with_bits(ChunkHeightmaps::BITS);
+        for (i, top) in self.tops[kind.index()].iter().enumerate() {
+            map.set(i % 16, i / 16, *top as u32);
+        }
+        map.longs
+    }
+
+    /// Unpacks what `packed` gave, if it's the right size and in range.
+    pub fn unpack(&mut self, kind: HeightmapKind, longs: Vec<i64>) -> bool {
+        let mut map = Heightmap::with_bits(ChunkHeightmaps::BITS);
+        if longs.len() != map.longs.len() {
+            return false;
+        }
+        map.longs = longs;
+
+        let mut tops = [0u8; 256];
+        for (i, top) in tops.iter_mut().enumerate() {
+            let value = map.get(i % 16, i / 16);
+            if value > 16 {
+                return false;
+            }
+            *top = value as u8;
+        }
+        self.tops[kind.index()] = tops;
+        true
+    }
+}
+
+/// One above the highest block of `kind` in the column at `x`, `z` below chunk-relative `below`.
+fn scan(blocks: &[Block; 16 * 16 * 16], kind: HeightmapKind, x: usize, z: usize, below: usize) -> u8 {
+    (0..below).rev()
+        .find(|y| kind.counts(blocks[(y << 8) | (z << 4) | x]))
+        .map_or(0, |y| y as u8 + 1)
+}
+
+/// Both heightmaps of a chunk column.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ColumnHeightmaps {
+    maps: [Heightmap; 2]
+}
+
+impl ColumnHeightmaps {
+    /// Puts a column's heightmaps together from its chunks' maps, bottom chunk first.
+    pub fn combine(chunks: &[&ChunkHeightmaps], height: i64) -> ColumnHeightmaps {
+        let mut column = ColumnHeightmaps { maps: [Heightmap::for_height(height), Heightmap::for_height(height)] };
+        for z in 0..16 {
+            for x in 0..16 {
+                column.refresh(chunks, x, z);
+            }
+        }
+        column
+    }
+
+    pub fn get(&self, kind: HeightmapKind) -> &Heightmap {
+        &self.maps[kind.index()]
+    }
+
+    /// Works out the heights of the block column at `x`, `z` again from the chunks' maps.
+    pub fn refresh(&mut self, chunks: &[&ChunkHeightmaps], x: usize, z: usize) {
+        for kind in HeightmapKind::ALL {
+            let height = chunks.iter().enumerate().rev()
+                .find_map(|(i, chunk)| chunk.top(kind, x, z).map(|top| (i * 16) as u32 + top as u32 + 1))
+                .unwrap_or(0);
+            self.maps[kind.index()].set(x, z, height);
+        }
+    }
+}
@@ -0,0 +1,885 @@
+use std::ops::Range;
+use crate::block_entity::StoredItem;
+use crate::item::{self, EquipmentSlot, ItemStack};
+use crate::recipe;
+
+/// What's in a slot; empty slots have nothing rather than air.
+pub type Slot = Option<ItemStack>;
+
+/// Slots of the player's own window, which is always open as window 0.
+pub const CRAFTING_RESULT: usize = 0;
+pub const CRAFTING_GRID: Range<usize> = 1..5;
+/// Head, chest, legs and feet.
+pub const ARMOR: Range<usize> = 5..9;
+pub const MAIN: Range<usize> = 9..36;
+pub const HOTBAR: Range<usize> = 36..45;
+pub const OFFHAND: usize = 45;
+pub const PLAYER_SLOTS: usize = 46;
+
+/// The slot clicks outside of a window are for.
+pub const OUTSIDE: i16 = -999;
+
+/// Slots of a furnace's window.
+pub const FURNACE_INPUT: usize = 0;
+pub const FURNACE_FUEL: usize = 1;
+pub const FURNACE_OUTPUT: usize = 2;
+
+/// The kinds of window players can have open, which decide what goes where in them.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum WindowType {
+    /// The player's own inventory.
+    Player,
+    /// A single chest's 27 slots.
+    Chest,
+    /// A crafting table's result and 3x3 grid.
+    Crafting,
+    /// A furnace's input, fuel and output.
+    Furnace
+}
+
+impl WindowType {
+    /// How many slots the client shows, the player's own included.
+    pub fn size(&self) -> usize {
+        match self {
+            WindowType::Player => PLAYER_SLOTS,
+            _ => self.player_start() + MAIN.len() + HOTBAR.len()
+        }
+    }
+
+    /// Where the player's main inventory starts, which is after everything that's the window's
+    /// own.
+    pub fn player_start(&self) -> usize {
+        match self {
+            WindowType::Player => MAIN.start,
+            WindowType::Chest => 27,
+            WindowType::Crafting => 10,
+            WindowType::Furnace => 3
+        }
+    }
+
+    /// Where the hotbar is among the slots, for the number keys.
+    pub fn hotbar(&self) -> Range<usize> {
+        let start = self.player_start() + MAIN.len();
+        start..start + HOTBAR.len()
+    }
+
+    /// Where the off hand is among the slots, for the swap key. Only the player's own window
+    /// shows it; the others have it just past their last slot.
+    pub fn offhand(&self) -> usize {
+        match self {
+            WindowType::Player => OFFHAND,
+            _ => self.size()
+        }
+    }
+
+    /// What vanilla calls the kind of window when opening it, and the title it gets unless it's
+    /// been given a name. The player's own window is never opened.
+    pub fn menu(&self) -> Option<(i32, &'static str)> {
+        match self {
+            WindowType::Player => None,
+            WindowType::Chest => Some((2, "container.chest")),
+            WindowType::Crafting => Some((11, "container.crafting")),
+            WindowType::Furnace => Some((13, "container.furnace"))
+        }
+    }
+
+    /// Where the crafting grid is among the slots and how wide it is, for windows with one. Its
+    /// result is always slot 0.
+    pub fn crafting(&self) -> Option<(Range<usize>, usize)> {
+        match self {
+            WindowType::Player => Some((CRAFTING_GRID, 2)),
+            WindowType::Crafting => Some((1..10, 3)),
+            _ => None
+        }
+    }
+
+    /// Whether players can only take from the slot, like a crafting result.
+    pub fn is_output(&self, slot: usize) -> bool {
+        match self {
+            WindowType::Player | WindowType::Crafting => slot == CRAFTING_RESULT,
+            WindowType::Furnace => slot == FURNACE_OUTPUT,
+            WindowType::Chest => false
+        }
+    }
+
+    /// Whether taking from the slot takes all of it, like crafting results which come one craft
+    /// at a time.
+    fn takes_whole(&self, slot: usize) -> bool {
+        self.crafting().is_some() && slot == CRAFTING_RESULT
+    }
+
+    /// Whether players can put `stack` in the slot.
+    pub fn accepts(&self, slot: usize, stack: &ItemStack) -> bool {
+        if self.is_output(slot) {
+            return false;
+        }
+        match self {
+            WindowType::Player if ARMOR.contains(&slot) => stack.equipment_slot() == Some(ARMOR_ORDER[slot - ARMOR.start]),
+            WindowType::Furnace if slot == FURNACE_FUEL => recipe::burn_time(stack.item) > 0,
+            _ => true
+        }
+    }
+
+    /// How many of `stack` fit in the slot.
+    pub fn limit(&self, slot: usize, stack: &ItemStack) -> i8 {
+        match self {
+            WindowType::Player if ARMOR.contains(&slot) => 1,
+            _ => stack.max_stack_size()
+        }
+    }
+
+    /// Where shift-clicking `stack` in the slot tries to put it: ranges of slots in the order
+    /// they're tried, each with whether it's filled from the end.
+    fn shift_targets(&self, slot: usize, stack: &ItemStack) -> Vec<(Range<usize>, bool)> {
+        let (start, hotbar) = (self.player_start(), self.hotbar());
+        let (main, player) = (start..hotbar.start, start..hotbar.end);
+        if *self != WindowType::Player && slot < start {
+            // out of the window's own slots, outputs filling from the hotbar's end like vanilla
+            return vec![(player, self.is_output(slot))];
+        }
+        let between = if main.contains(&slot) { hotbar } else { main };
+        match self {
+            WindowType::Player => {
+                if slot == CRAFTING_RESULT {
+                    return vec![(MAIN.start..HOTBAR.end, true)];
+                }
+                if slot < ARMOR.end {
+                    return vec![(MAIN.start..HOTBAR.end, false)];
+                }
+                let mut targets = vec![];
+                match stack.equipment_slot() {
+                    Some(EquipmentSlot::OffHand) if slot != OFFHAND => targets.push((OFFHAND..OFFHAND + 1, false)),
+                    Some(equipment) => if let Some(i) = ARMOR_ORDER.iter().position(|e| *e == equipment) {
+                        targets.push((ARMOR.start + i..ARMOR.start + i + 1, false));
+                    },
+                    None => {}
+                }
+                if MAIN.contains(&slot) {
+                    targets.push((HOTBAR, false));
+                } else if HOTBAR.contains(&slot) {
+                    targets.push((MAIN, false));
+                } else {
+                    targets.push((MAIN.start..HOTBAR.end, false));
+                }
+                targets
+            }
+            WindowType::Chest => vec![(0..start, false)],
+            WindowType::Crafting => vec![(between, false)],
+            WindowType::Furnace => {
+                if recipe::smelt(stack).is_some() {
+                    vec![(FURNACE_INPUT..FURNACE_INPUT + 1, false)]
+                } else if recipe::burn_time(stack.item) > 0 {
+                    vec![(FURNACE_FUEL..FURNACE_FUEL + 1, false)]
+                } else {
+                    vec![(between, false)]
+                }
+            }
+        }
+    }
+}
+
+/// A window open on top of the player's own, for a block they used.
+#[derive(Debug, Clone)]
+pub struct Window {
+    pub id: u8,
+    pub kind: WindowType,
+    /// The block the window is for, which the player has to stay near.
+    pub position: (i64, i64, i64),
+    /// A crafting table's result and grid, which are the player's own while they have it open.
+    /// Chests and furnaces keep what's in them in their block entity instead, where everyone
+    /// with one open sees the same thing.
+    pub slots: Vec<Slot>
+}
+
+/// What each armor slot takes, in slot order.
+const ARMOR_ORDER: [EquipmentSlot; 4] = [EquipmentSlot::Head, EquipmentSlot::Chest, EquipmentSlot::Legs, EquipmentSlot::Feet];
+
+/// A drag across slots that's under way.
+#[derive(Debug, Clone)]
+pub struct Drag {
+    /// 0 spreads the cursor evenly, 1 puts one in each slot and 2, for creative players, fills
+    /// each slot without using the cursor up.
+    kind: i8,
+    slots: Vec<usize>
+}
+
+/// Everything a player carries, and what they're doing with it in windows.
+#[derive(Debug, Clone)]
+pub struct PlayerInventory {
+    /// What's in the player's own window, by its slot numbers.
+    pub slots: Vec<Slot>,
+    /// Which hotbar slot is held, from 0 to 8.
+    pub selected: usize,
+    /// What the player holds with the mouse in an open window.
+    pub cursor: Slot,
+    pub drag: Option<Drag>
+}
+
+impl Default for PlayerInventory {
+    fn default() -> Self {
+        PlayerInventory::new()
+    }
+}
+
+impl PlayerInventory {
+    pub fn new() -> PlayerInventory {
+        PlayerInventory { slots: vec![None; PLAYER_SLOTS], selected: 0, cursor: None, drag: None }
+    }
+
+    /// The slot of the held item.
+    pub fn held_slot(&self) -> usize {
+        HOTBAR.start + self.selected
+    }
+
+    pub fn held(&self) -> Option<&ItemStack> {
+        self.slots[self.held_slot()].as_ref()
+    }
+
+    /// Takes up to `count` of the held item out of the player's hand.
+    pub fn take_held(&mut self, count: i8) -> Option<ItemStack> {
+        self.take_from(self.held_slot(), count)
+    }
+
+    /// Takes up to `count` of what's in the player's window at `slot`.
+    pub fn take_from(&mut self, slot: usize, count: i8) -> Option<ItemStack> {
+        take(&mut self.slots[slot], count)
+    }
+
+    /// Puts `stack` in the inventory, on stacks of the same item first and then in empty slots,
+    /// hotbar before the rest, like picking something up. Gives back what didn't fit.
+    pub fn add(&mut self, mut stack: ItemStack) -> Slot {
+        let order: Vec<usize> = [self.held_slot(), OFFHAND].iter().copied().chain(HOTBAR).chain(MAIN).collect();
+        for slot in order.iter().copied() {
+            if let Some(existing) = &mut self.slots[slot] {
+                if existing.stacks_with(&stack) {
+                    let n = (existing.max_stack_size() - existing.count).max(0).min(stack.count);
+                    existing.count += n;
+                    stack.count -= n;
+                }
+            }
+        }
+        for slot in order.into_iter().skip(2) {
+            if stack.count <= 0 {
+                break;
+            }
+            if self.slots[slot].is_none() {
+                let n = stack.count.min(stack.max_stack_size());
+                self.slots[slot] = Some(split(&mut stack, n));
+            }
+        }
+        non_empty(stack)
+    }
+
+    /// Moves what's on the cursor and in the crafting grid back into the inventory, like when the
+    /// player closes a window. Gives back what didn't fit.
+    pub fn put_away(&mut self) -> Vec<ItemStack> {
+        let mut stacks: Vec<ItemStack> = self.cursor.take().into_iter().collect();
+        stacks.extend(CRAFTING_GRID.filter_map(|slot| self.slots[slot].take()));
+        self.slots[CRAFTING_RESULT] = None;
+        self.drag = None;
+        stacks.into_iter().filter_map(|stack| self.add(stack)).collect()
+    }
+
+    /// The inventory from the items in a player's file, by vanilla's slot numbers: 0 to 8 for the
+    /// hotbar, 9 to 35 for the rest, 100 to 103 for armor from the feet up and -106 for the off
+    /// hand.
+    pub fn from_stored(items: &[StoredItem], selected: usize) -> PlayerInventory {
+        let mut inventory = PlayerInventory::new();
+        inventory.selected = selected.min(8);
+        for stored in items {
+            let slot = match stored.slot {
+                0..=8 => HOTBAR.start + stored.slot as usize,
+                9..=35 => stored.slot as usize,
+                100..=103 => ARMOR.end - 1 - (stored.slot as usize - 100),
+                -106 => OFFHAND,
+                _ => continue
+            };
+            inventory.slots[slot] = stack_of(stored);
+        }
+        inventory
+    }
+
+    /// The items to save, the other way around from `from_stored`.
+    pub fn stored(&self) -> Vec<StoredItem> {
+        let slots = HOTBAR.zip(0..)
+            .chain(MAIN.map(|slot| (slot, slot as i8)))
+            .chain(ARMOR.rev().zip(100..))
+            .chain(std::iter::once((OFFHAND, -106)));
+        slots.filter_map(|(slot, stored)| stored_item(stored, self.slots[slot].as_ref()?)).collect()
+    }
+
+    /// The slots of a window of `kind` with `contents` as its own: those, then the player's
+    /// main inventory and hotbar, and then their off hand just past the end. The player's own
+    /// window is just their slots. Taken out of the inventory until `put_back`.
+    pub fn take_window_slots(&mut self, kind: WindowType, contents: Vec<Slot>) -> Vec<Slot> {
+        if kind == WindowType::Player {
+            return std::mem::take(&mut self.slots);
+        }
+        let mut slots = contents;
+        slots.extend(self.slots[MAIN.start..HOTBAR.end].iter_mut().map(Option::take));
+        slots.push(self.slots[OFFHAND].take());
+        slots
+    }
+
+    /// Puts the player's slots of what `take_window_slots` gave back where they were, giving back
+    /// the window's own.
+    pub fn put_back(&mut self, kind: WindowType, mut slots: Vec<Slot>) -> Vec<Slot> {
+        if kind == WindowType::Player {
+            self.slots = slots;
+            return vec![];
+        }
+        self.slots[OFFHAND] = slots.pop().flatten();
+        let player = slots.split_off(kind.player_start());
+        for (slot, stack) in (MAIN.start..HOTBAR.end).zip(player) {
+            self.slots[slot] = stack;
+        }
+        slots
+    }
+
+    /// Does what a click in a window of `kind` would do to `slots`, the cursor and any drag
+    /// under way, the same way the client works it out. `mode` is what sort of click it is and
+    /// `button` which one within that. Gives back what the player threw out of the window, or
+    /// None if the client couldn't have sent the click.
+    pub fn click(&mut self, kind: WindowType, slots: &mut [Slot], slot: i16, button: i8, mode: i32,
+                 creative: bool) -> Option<Vec<ItemStack>> {
+        let mut dropped = vec![];
+        let index = if slot >= 0 && (slot as usize) < kind.size() { Some(slot as usize) } else { None };
+        if mode != 5 {
+            self.drag = None;
+        }
+        let result = kind.crafting().and_then(|_| slots[CRAFTING_RESULT].clone());
+
+        match (mode, index) {
+            (0, None) if slot == OUTSIDE && (button == 0 || button == 1) => {
+                let count = if button == 0 { i8::MAX } else { 1 };
+                dropped.extend(take(&mut self.cursor, count));
+            }
+            (0, Some(index)) if button == 0 || button == 1 => self.pickup(kind, slots, index, button == 1),
+            (1, Some(index)) if (button == 0 || button == 1) && kind.takes_whole(index) => craft_all(kind, slots),
+            (1, Some(index)) if button == 0 || button == 1 => quick_move(kind, slots, index),
+            (2, Some(index)) => {
+                let target = match button {
+                    0..=8 => kind.hotbar().start + button as usize,
+                    40 => kind.offhand(),
+                    _ => return None
+                };
+                swap(kind, slots, index, target);
+            }
+            (3, Some(index)) if button == 2 => {
+                if let (true, None, Some(stack)) = (creative, &self.cursor, &slots[index]) {
+                    self.cursor = Some(ItemStack { count: stack.max_stack_size(), ..stack.clone() });
+                }
+            }
+            (4, None) if slot == OUTSIDE => {}
+            (4, Some(index)) if button == 0 || button == 1 => {
+                if self.cursor.is_none() {
+                    let count = if button == 0 && !kind.takes_whole(index) { 1 } else { i8::MAX };
+                    dropped.extend(take(&mut slots[index], count));
+                }
+            }
+            (5, _) => self.drag(kind, slots, index, button, creative)?,
+            (6, Some(index)) if button == 0 || button == 1 => collect(kind, slots, &mut self.cursor, index, button == 1),
+            _ => return None
+        }
+
+        // taking a crafting result uses up what made it, and the grid may make something else now
+        if result.is_some() && slots[CRAFTING_RESULT].is_none() && mode != 1 {
+            use_ingredients(kind, slots);
+        }
+        update_crafting(kind, slots);
+        Some(dropped)
+    }
+
+    /// A plain click: picks up, puts down, swaps or adds to a stack.
+    fn pickup(&mut self, kind: WindowType, slots: &mut [Slot], index: usize, right: bool) {
+        match (slots[index].take(), self.cursor.take()) {
+            (None, None) => {}
+            (None, Some(mut carried)) => {
+                if kind.accepts(index, &carried) {
+                    let n = if right { 1 } else { carried.count }.min(kind.limit(index, &carried));
+                    slots[index] = Some(split(&mut carried, n));
+                }
+                self.cursor = non_empty(carried);
+            }
+            (Some(mut stack), None) => {
+                let n = if right && !kind.takes_whole(index) { (stack.count + 1) / 2 } else { stack.count };
+                self.cursor = Some(split(&mut stack, n));
+                slots[index] = non_empty(stack);
+            }
+            (Some(mut stack), Some(mut carried)) => {
+                if kind.accepts(index, &carried) {
+                    if stack.stacks_with(&carried) {
+                        let room = (kind.limit(index, &carried) - stack.count).max(0);
+                        let n = if right { 1 } else { carried.count }.min(room);
+                        stack.count += n;
+                        carried.count -= n;
+                    } else if carried.count <= kind.limit(index, &carried) {
+                        std::mem::swap(&mut stack, &mut carried);
+                    }
+                } else if stack.stacks_with(&carried) && stack.count + carried.count <= carried.max_stack_size() {
+                    // taking from an output onto what's already carried
+                    carried.count += stack.count;
+                    stack.count = 0;
+                }
+                slots[index] = non_empty(stack);
+                self.cursor = non_empty(carried);
+            }
+        }
+    }
+
+    /// One step of a drag: starting it, adding a slot to it, or spreading the cursor over its
+    /// slots at the end.
+    fn drag(&mut self, kind: WindowType, slots: &mut [Slot], index: Option<usize>, button: i8,
+            creative: bool) -> Option<()> {
+        let (stage, drag_kind) = (button & 3, button >> 2);
+        if drag_kind > 2 || (drag_kind == 2 && !creative) {
+            self.drag = None;
+            return None;
+        }
+        match stage {
+            0 => {
+                self.drag = match (&self.drag, &self.cursor) {
+                    (None, Some(_)) => Some(Drag { kind: drag_kind, slots: vec![] }),
+                    _ => None
+                };
+            }
+            1 => {
+                let (drag, carried, index) = match (&mut self.drag, &self.cursor, index) {
+                    (Some(drag), Some(carried), Some(index)) if drag.kind == drag_kind => (drag, carried, index),
+                    _ => {
+                        self.drag = None;
+                        return Some(());
+                    }
+                };
+                if !drag.slots.contains(&index) && can_drag_to(kind, slots, index, carried)
+                    && (drag.kind == 2 || carried.count as usize > drag.slots.len()) {
+                    drag.slots.push(index);
+                }
+            }
+            2 => {
+                let (drag, mut carried) = match (self.drag.take(), self.cursor.take()) {
+                    (Some(drag), Some(carried)) if drag.kind == drag_kind => (drag, carried),
+                    (_, carried) => {
+                        self.cursor = carried;
+                        return Some(());
+                    }
+                };
+                if drag.slots.len() == 1 {
+                    // dragging over one slot is just a click on it, which a middle click isn't
+                    self.cursor = Some(carried);
+                    if drag.kind != 2 {
+                        self.pickup(kind, slots, drag.slots[0], drag.kind == 1);
+                    }
+                    return Some(());
+                }
+                let each = match drag.kind {
+                    0 => carried.count / drag.slots.len().max(1) as i8,
+                    1 => 1,
+                    _ => carried.max_stack_size()
+                };
+                for index in drag.slots {
+                    if !can_drag_to(kind, slots, index, &carried) {
+                        continue;
+                    }
+                    let existing = slots[index].as_ref().map_or(0, |s| s.count);
+                    let mut n = each.min(kind.limit(index, &carried) - existing).max(0);
+                    if drag.kind != 2 {
+                        n = n.min(carried.count);
+                        carried.count -= n;
+                    }
+                    slots[index] = Some(ItemStack { count: existing + n, ..carried.clone() });
+                }
+                self.cursor = non_empty(carried);
+            }
+            _ => {
+                self.drag = None;
+                return None;
+            }
+        }
+        Some(())
+    }
+}
+
+/// Whether a drag of `carried` can put some in the slot.
+fn can_drag_to(kind: WindowType, slots: &[Slot], index: usize, carried: &ItemStack) -> bool {
+    kind.accepts(index, carried) && match &slots[index] {
+        None => true,
+        Some(stack) => stack.stacks_with(carried) && stack.count < kind.limit(index, carried)
+    }
+}
+
+/// Shift-click: moves the slot's stack to wherever the window sends it.
+fn quick_move(kind: WindowType, slots: &mut [Slot], index: usize) {
+    let targets = match &slots[index] {
+        Some(stack) => kind.shift_targets(index, stack),
+        None => return
+    };
+    for (range, reverse) in targets {
+        move_into(kind, slots, index, range, reverse);
+        if slots[index].is_none() {
+            break;
+        }
+    }
+}
+
+/// Shift-click on a crafting result: crafts as many times as there's room for the result, and
+/// ingredients for it, in the player's inventory.
+fn craft_all(kind: WindowType, slots: &mut [Slot]) {
+    while let Some(result) = slots[CRAFTING_RESULT].clone() {
+        let before = slots.to_vec();
+        quick_move(kind, slots, CRAFTING_RESULT);
+        if slots[CRAFTING_RESULT].is_some() {
+            // only whole crafts come out
+            slots.clone_from_slice(&before);
+            return;
+        }
+        use_ingredients(kind, slots);
+        update_crafting(kind, slots);
+        if !slots[CRAFTING_RESULT].as_ref().is_some_and(|next| next.stacks_with(&result)) {
+            return;
+        }
+    }
+}
+
+/// Takes one of each ingredient out of the crafting grid, for a result that's been taken.
+fn use_ingredients(kind: WindowType, slots: &mut [Slot]) {
+    if let Some((grid, _)) = kind.crafting() {
+        for slot in grid {
+            take(&mut slots[slot], 1);
+        }
+    }
+}
+
+/// Puts what the crafting grid makes in the result slot, for windows that have one.
+pub fn update_crafting(kind: WindowType, slots: &mut [Slot]) {
+    if let Some((grid, width)) = kind.crafting() {
+        slots[CRAFTING_RESULT] = recipe::craft(&slots[grid], width);
+    }
+}
+
+/// Moves as much of the stack in `from` as fits into the slots of `range`, onto stacks of the
+/// same item first and then into empty slots.
+fn move_into(kind: WindowType, slots: &mut [Slot], from: usize, range: Range<usize>, reverse: bool) {
+    let mut moving = match slots[from].take() {
+        Some(stack) => stack,
+        None => return
+    };
+    let order: Vec<usize> = if reverse { range.rev().collect() } else { range.collect() };
+    for empty in [false, true] {
+        for &index in &order {
+            if moving.count <= 0 || index == from || !kind.accepts(index, &moving) {
+                continue;
+            }
+            let limit = kind.limit(index, &moving);
+            match &mut slots[index] {
+                Some(stack) if !empty && stack.stacks_with(&moving) => {
+                    let n = (limit - stack.count).max(0).min(moving.count);
+                    stack.count += n;
+                    moving.count -= n;
+                }
+                None if empty => {
+                    let n = moving.count.min(limit);
+                    slots[index] = Some(split(&mut moving, n));
+                }
+                _ => {}
+            }
+        }
+    }
+    slots[from] = non_empty(moving);
+}
+
+/// A number key or the swap key: swaps the slot with a hotbar slot or the off hand.
+fn swap(kind: WindowType, slots: &mut [Slot], index: usize, target: usize) {
+    if index == target || target >= slots.len() {
+        return;
+    }
+    let allowed = match (&slots[index], &slots[target]) {
+        (_, None) => true,
+        (None, Some(held)) => kind.accepts(index, held),
+        (Some(_), Some(held)) => kind.accepts(index, held) && held.count <= kind.limit(index, held)
+    };
+    if !allowed {
+        return;
+    }
+    if slots[index].is_none() {
+        if let Some(mut held) = slots[target].take() {
+            // only as many as the slot takes go in; the rest stay where they were
+            let n = held.count.min(kind.limit(index, &held));
+            slots[index] = Some(split(&mut held, n));
+            slots[target] = non_empty(held);
+            return;
+        }
+    }
+    slots.swap(index, target);
+}
+
+/// Double-click: gathers stacks of what's on the cursor onto it, partial stacks first.
+fn collect(kind: WindowType, slots: &mut [Slot], cursor: &mut Slot, index: usize, reverse: bool) {
+    let carried = match cursor {
+        Some(carried) if slots[index].is_none() => carried,
+        _ => return
+    };
+    let max = carried.max_stack_size();
+    let order: Vec<usize> = if reverse { (0..kind.size()).rev().collect() } else { (0..kind.size()).collect() };
+    for full in [false, true] {
+        for &i in &order {
+            if carried.count >= max {
+                return;
+            }
+            if kind.is_output(i) {
+                continue;
+            }
+            if let Some(stack) = &mut slots[i] {
+                if stack.stacks_with(carried) && (full || stack.count < stack.max_stack_size()) {
+                    let n = stack.count.min(max - carried.count);
+                    carried.count += n;
+                    stack.count -= n;
+                    if stack.count <= 0 {
+                        slots[i] = None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The slots of a container with `size` of them, from the items saved in its block entity.
+pub fn slots_from_stored(items: &[StoredItem], size: usize) -> Vec<Slot> {
+    let mut slots = vec![None; size];
+    for stored in items {
+        if let Some(slot) = slots.get_mut(stored.slot as usize).filter(|_| stored.slot >= 0) {
+            *slot = stack_of(stored);
+        }
+    }
+    slots
+}
+
+/// The items to save in a container's block entity, the other way around from `slots_from_stored`.
+pub fn stored_from_slots(slots: &[Slot]) -> Vec<StoredItem> {
+    slots.iter()
+        .enumerate()
+        .filter_map(|(i, slot)| stored_item(i as i8, slot.as_ref()?))
+        .collect()
+}
+
+/// The stack a saved item is, if it's an item there is.
+fn stack_of(stored: &StoredItem) -> Slot {
+    item::id_of(&stored.id)
+        .filter(|item| *item != item::AIR && stored.count > 0)
+        .map(|item| ItemStack { item, count: stored.count, nbt: stored.tag.as_ref().map(item::clone_compound) })
+}
+
+fn stored_item(slot: i8, stack: &ItemStack) -> Option<StoredItem> {
+    let tag = stack.nbt.as_ref().map(item::clone_compound);
+    Some(StoredItem { slot, id: stack.name()?.to_string(), count: stack.count, tag })
+}
+
+/// Takes up to `count` out of the slot.
+fn take(slot: &mut Slot, count: i8) -> Option<ItemStack> {
+    let mut stack = slot.take()?;
+    let count = count.min(stack.count);
+    let taken = split(&mut stack, count);
+    *slot = non_empty(stack);
+    Some(taken)
+}
+
+/// Takes `count` off `stack` as a stack of its own.
+fn split(stack: &mut ItemStack, count: i8) -> ItemStack {
+    stack.count -= count;
+    ItemStack { count, ..stack.clone() }
+}
+
+fn non_empty(stack: ItemStack) -> Slot {
+    if stack.count > 0 { Some(stack) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::item::ItemId;
+
+    fn stack(name: &str, count: i8) -> Slot {
+        Some(ItemStack::named(name, count).expect("no such item"))
+    }
+
+    /// How many of each item there are in the slots, on the cursor and thrown out. A crafting
+    /// result isn't counted; it's only what the grid would make.
+    fn totals(kind: WindowType, inventory: &PlayerInventory, slots: &[Slot], dropped: &[ItemStack]) -> HashMap<ItemId, i32> {
+        let result = kind.crafting().map(|_| CRAFTING_RESULT);
+        let mut totals = HashMap::new();
+        let in_slots = slots.iter().enumerate().filter(|(i, _)| Some(*i) != result).filter_map(|(_, s)| s.as_ref());
+        for stack in in_slots.chain(inventory.cursor.as_ref()).chain(dropped) {
+            *totals.entry(stack.item).or_insert(0) += stack.count as i32;
+        }
+        totals
+    }
+
+    fn count(slot: &Slot) -> i8 {
+        slot.as_ref().map_or(0, |s| s.count)
+    }
+
+    /// Clicks as a survival player, checking nothing was made or lost.
+    fn click(inventory: &mut PlayerInventory, kind: WindowType, slots: &mut [Slot], slot: i16, button: i8, mode: i32) {
+        let before = totals(kind, inventory, slots, &[]);
+        let dropped = inventory.click(kind, slots, slot, button, mode, false).expect("the client could send this");
+        assert_eq!(totals(kind, inventory, slots, &dropped), before);
+    }
+
+    #[test]
+    fn pickup_takes_places_and_swaps() {
+        let mut inventory = PlayerInventory::new();
+        let mut slots = inventory.take_window_slots(WindowType::Chest, vec![None; 27]);
+        slots[0] = stack("minecraft:stone", 10);
+        slots[2] = stack("minecraft:dirt", 3);
+
+        click(&mut inventory, WindowType::Chest, &mut slots, 0, 0, 0);
+        assert_eq!((count(&slots[0]), count(&inventory.cursor)), (0, 10));
+        click(&mut inventory, WindowType::Chest, &mut slots, 1, 1, 0);
+        assert_eq!((count(&slots[1]), count(&inventory.cursor)), (1, 9));
+        click(&mut inventory, WindowType::Chest, &mut slots, 1, 0, 0);
+        assert_eq!((count(&slots[1]), inventory.cursor.clone()), (10, None));
+        click(&mut inventory, WindowType::Chest, &mut slots, 1, 1, 0);
+        assert_eq!((count(&slots[1]), count(&inventory.cursor)), (5, 5));
+        click(&mut inventory, WindowType::Chest, &mut slots, 2, 0, 0);
+        assert_eq!((slots[2].clone(), inventory.cursor.clone()), (stack("minecraft:stone", 5), stack("minecraft:dirt", 3)));
+        click(&mut inventory, WindowType::Chest, &mut slots, OUTSIDE, 1, 0);
+        assert_eq!(count(&inventory.cursor), 2);
+    }
+
+    #[test]
+    fn shift_click_fills_partial_stacks_first() {
+        let mut inventory = PlayerInventory::new();
+        let mut slots = inventory.take_window_slots(WindowType::Chest, vec![None; 27]);
+        let start = WindowType::Chest.player_start();
+        slots[0] = stack("minecraft:stone", 64);
+        slots[start + 5] = stack("minecraft:stone", 60);
+
+        click(&mut inventory, WindowType::Chest, &mut slots, 0, 0, 1);
+        assert_eq!(slots[0], None);
+        assert_eq!((count(&slots[start]), count(&slots[start + 5])), (60, 64));
+
+        // and back into the chest
+        click(&mut inventory, WindowType::Chest, &mut slots, start as i16, 0, 1);
+        assert_eq!((count(&slots[0]), slots[start].clone()), (60, None));
+    }
+
+    #[test]
+    fn number_keys_swap_with_the_hotbar() {
+        let mut inventory = PlayerInventory::new();
+        let mut slots = inventory.take_window_slots(WindowType::Chest, vec![None; 27]);
+        let key = WindowType::Chest.hotbar().start + 3;
+        slots[0] = stack("minecraft:stone", 10);
+        slots[key] = stack("minecraft:dirt", 2);
+
+        click(&mut inventory, WindowType::Chest, &mut slots, 0, 3, 2);
+        assert_eq!((slots[0].clone(), slots[key].clone()), (stack("minecraft:dirt", 2), stack("minecraft:stone", 10)));
+        click(&mut inventory, WindowType::Chest, &mut slots, 1, 3, 2);
+        assert_eq!((slots[1].clone(), slots[key].clone()), (stack("minecraft:stone", 10), None));
+    }
+
+    #[test]
+    fn drags_spread_the_cursor() {
+        let mut inventory = PlayerInventory::new();
+        let mut slots = inventory.take_window_slots(WindowType::Chest, vec![None; 27]);
+        inventory.cursor = stack("minecraft:stone", 10);
+
+        // left drag: evenly, with the rest left on the cursor
+        click(&mut inventory, WindowType::Chest, &mut slots, OUTSIDE, 0, 5);
+        for slot in 0..3 {
+            click(&mut inventory, WindowType::Chest, &mut slots, slot, 1, 5);
+        }
+        click(&mut inventory, WindowType::Chest, &mut slots, OUTSIDE, 2, 5);
+        assert_eq!([count(&slots[0]), count(&slots[1]), count(&slots[2]), count(&inventory.cursor)], [3, 3, 3, 1]);
+
+        // right drag: one each, as far as the cursor goes
+        inventory.cursor = stack("minecraft:stone", 2);
+        click(&mut inventory, WindowType::Chest, &mut slots, OUTSIDE, 4, 5);
+        for slot in 3..6 {
+            click(&mut inventory, WindowType::Chest, &mut slots, slot, 5, 5);
+        }
+        click(&mut inventory, WindowType::Chest, &mut slots, OUTSIDE, 6, 5);
+        assert_eq!([count(&slots[3]), count(&slots[4]), count(&slots[5]), count(&inventory.cursor)], [1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn an_aborted_drag_changes_nothing() {
+        let mut inventory = PlayerInventory::new();
+        let mut slots = inventory.take_window_slots(WindowType::Chest, vec![None; 27]);
+        inventory.cursor = stack("minecraft:stone", 10);
+
+        click(&mut inventory, WindowType::Chest, &mut slots, OUTSIDE, 0, 5);
+        click(&mut inventory, WindowType::Chest, &mut slots, 0, 1, 5);
+        click(&mut inventory, WindowType::Chest, &mut slots, 1, 1, 5);
+        // ended as a right drag when it started as a left one
+        click(&mut inventory, WindowType::Chest, &mut slots, OUTSIDE, 6, 5);
+        assert!(slots.iter().all(Option::is_none));
+        assert_eq!(count(&inventory.cursor), 10);
+        assert!(inventory.drag.is_none());
+
+        // a creative drag from a survival player isn't something the client sends
+        assert!(inventory.click(WindowType::Chest, &mut slots, OUTSIDE, 8, 5, false).is_none());
+        assert_eq!(count(&inventory.cursor), 10);
+    }
+
+    #[test]
+    fn double_click_collects_partial_stacks_first() {
+        let mut inventory = PlayerInventory::new();
+        let mut slots = inventory.take_window_slots(WindowType::Chest, vec![None; 27]);
+        let start = WindowType::Chest.player_start();
+        slots[0] = stack("minecraft:stone", 64);
+        slots[1] = stack("minecraft:stone", 10);
+        slots[start] = stack("minecraft:stone", 20);
+        slots[2] = stack("minecraft:dirt", 5);
+        inventory.cursor = stack("minecraft:stone", 1);
+
+        click(&mut inventory, WindowType::Chest, &mut slots, 3, 0, 6);
+        assert_eq!(count(&inventory.cursor), 64);
+        assert_eq!([count(&slots[0]), count(&slots[1]), count(&slots[start]), count(&slots[2])], [31, 0, 0, 5]);
+    }
+
+    #[test]
+    fn taking_the_crafting_result_uses_the_ingredients() {
+        let mut inventory = PlayerInventory::new();
+        let mut slots = inventory.take_window_slots(WindowType::Player, vec![]);
+        slots[CRAFTING_GRID.start] = stack("minecraft:oak_log", 3);
+        update_crafting(WindowType::Player, &mut slots);
+        assert_eq!(slots[CRAFTING_RESULT], stack("minecraft:oak_planks", 4));
+        let (log, planks) = (item::id_of("minecraft:oak_log").unwrap(), item::id_of("minecraft:oak_planks").unwrap());
+
+        // a click takes one craft, which the ingredients pay for
+        let before = totals(WindowType::Player, &inventory, &slots, &[]);
+        inventory.click(WindowType::Player, &mut slots, CRAFTING_RESULT as i16, 0, 0, false).unwrap();
+        let after = totals(WindowType::Player, &inventory, &slots, &[]);
+        assert_eq!((after[&log], after[&planks]), (before[&log] - 1, 4));
+        assert_eq!(slots[CRAFTING_RESULT], stack("minecraft:oak_planks", 4));
+
+        // a shift-click crafts as many times as there are ingredients for
+        let dropped = inventory.click(WindowType::Player, &mut slots, CRAFTING_RESULT as i16, 0, 1, false).unwrap();
+        let after = totals(WindowType::Player, &inventory, &slots, &dropped);
+        assert_eq!((after.get(&log).copied(), after[&planks]), (None, 12));
+        assert_eq!(slots[CRAFTING_RESULT], None);
+    }
+
+    #[test]
+    fn only_creative_players_clone_stacks() {
+        let mut inventory = PlayerInventory::new();
+        let mut slots = inventory.take_window_slots(WindowType::Chest, vec![None; 27]);
+        slots[0] = stack("minecraft:stone", 5);
+
+        click(&mut inventory, WindowType::Chest, &mut slots, 0, 2, 3);
+        assert_eq!(inventory.cursor, None);
+
+        let before = slots.clone();
+        inventory.click(WindowType::Chest, &mut slots, 0, 2, 3, true).unwrap();
+        assert_eq!(slots, before);
+        assert_eq!(inventory.cursor, stack("minecraft:stone", 64));
+
+        // a creative drag fills each slot without using the cursor up
+        inventory.click(WindowType::Chest, &mut slots, OUTSIDE, 8, 5, true).unwrap();
+        inventory.click(WindowType::Chest, &mut slots, 1, 9, 5, true).unwrap();
+        inventory.click(WindowType::Chest, &mut slots, 2, 9, 5, true).unwrap();
+        inventory.click(WindowType::Chest, &mut slots, OUTSIDE, 10, 5, true).unwrap();
+        assert_eq!([count(&slots[0]), count(&slots[1]), count(&slots[2]), count(&inventory.cursor)], [5, 64, 64, 64]);
+    }
+}
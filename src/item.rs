@@ -0,0 +1,205 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use nbt::Tag;
+use crate::registry::Registry;
+
+/// Index of an item in the item registry, as sent to clients.
+pub type ItemId = i32;
+
+/// The id of air, which never goes in a slot.
+pub const AIR: ItemId = 0;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Item {
+    /// How many fit in one slot.
+    pub max_stack_size: i8,
+    /// How many uses it has before it breaks, or 0 if it doesn't wear out.
+    #[serde(default)]
+    pub max_damage: i32
+}
+
+#[derive(Deserialize)]
+struct VanillaItem {
+    name: String,
+    id: ItemId,
+    element: Item
+}
+
+static ITEMS: OnceLock<Registry<'static, Item>> = OnceLock::new();
+
+/// Every item of vanilla 1.17, under vanilla's ids.
+pub fn vanilla() -> Registry<'static, Item> {
+    let items: Vec<VanillaItem> = serde_json::from_str(include_str!("data/items.json"))
+        .expect("Built-in item data is broken");
+
+    let mut registry = Registry::new("minecraft:item");
+    for i in items {
+        registry.register_with_id(&i.name, i.id, i.element);
+    }
+    registry
+}
+
+pub fn registry() -> &'static Registry<'static, Item> {
+    ITEMS.get_or_init(vanilla)
+}
+
+pub fn id_of(name: &str) -> Option<ItemId> {
+    registry().get(name).map(|e| e.id)
+}
+
+pub fn name_of(id: ItemId) -> Option<&'static str> {
+    registry().by_id(id).map(|e| e.name.as_str())
+}
+
+pub fn get(id: ItemId) -> Option<&'static Item> {
+    registry().by_id(id).map(|e| &e.element)
+}
+
+/// Where on a player an item can be worn or held, besides the main hand.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum EquipmentSlot {
+    OffHand,
+    Head,
+    Chest,
+    Legs,
+    Feet
+}
+
+/// Which blocks a tool digs faster.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ToolKind {
+    Pickaxe,
+    Shovel,
+    Axe,
+    Hoe
+}
+
+/// Some of one item in a slot. Empty slots have no stack rather than one of air.
+#[derive(Debug, PartialEq)]
+pub struct ItemStack {
+    pub item: ItemId,
+    pub count: i8,
+    /// Anything else about the items, like enchantments or a custom name, as vanilla's `tag`.
+    pub nbt: Option<HashMap<String, Tag>>
+}
+
+impl ItemStack {
+    pub fn new(item: ItemId, count: i8) -> ItemStack {
+        ItemStack { item, count, nbt: None }
+    }
+
+    /// A stack of the item called `name`, if there's such an item.
+    pub fn named(name: &str, count: i8) -> Option<ItemStack> {
+        id_of(name).map(|item| ItemStack::new(item, count))
+    }
+
+    pub fn name(&self) -> Option<&'static str> {
+        name_of(self.item)
+    }
+
+    /// How many of the item fit in one slot; 64 for items the registry doesn't know.
+    pub fn max_stack_size(&self) -> i8 {
+        get(self.item).map_or(64, |i| i.max_stack_size)
+    }
+
+    /// Where the item goes when it's worn, or the off hand for shields. Worked out from the
+    /// item's name until items have more to them.
+    pub fn equipment_slot(&self) -> Option<EquipmentSlot> {
+        let name = self.name()?;
+        if name == "minecraft:shield" {
+            Some(EquipmentSlot::OffHand)
+        } else if name.ends_with("_helmet") || name.ends_with("_head") || name.ends_with("_skull")
+            || name == "minecraft:carved_pumpkin" {
+            Some(EquipmentSlot::Head)
+        } else if name.ends_with("_chestplate") || name == "minecraft:elytra" {
+            Some(EquipmentSlot::Chest)
+        } else if name.ends_with("_leggings") {
+            Some(EquipmentSlot::Legs)
+        } else if name.ends_with("_boots") {
+            Some(EquipmentSlot::Feet)
+        } else {
+            None
+        }
+    }
+
+    /// What kind of tool the item is and how fast it digs the blocks it's for, like vanilla's tier
+    /// speeds with Efficiency on top. Worked out from the item's name like `equipment_slot`.
+    pub fn tool(&self) -> Option<(ToolKind, f32)> {
+        let name = self.name()?.strip_prefix("minecraft:")?;
+        let (tier, kind) = name.rsplit_once('_')?;
+        let kind = match kind {
+            "pickaxe" => ToolKind::Pickaxe,
+            "shovel" => ToolKind::Shovel,
+            "axe" => ToolKind::Axe,
+            "hoe" => ToolKind::Hoe,
+            _ => return None
+        };
+        let speed = match tier {
+            "wooden" => 2.0,
+            "stone" => 4.0,
+            "iron" => 6.0,
+            "diamond" => 8.0,
+            "netherite" => 9.0,
+            "golden" => 12.0,
+            _ => return None
+        };
+        let efficiency = self.enchantment("minecraft:efficiency");
+        Some((kind, if efficiency > 0 { speed + (efficiency * efficiency + 1) as f32 } else { speed }))
+    }
+
+    /// The level of the enchantment called `name` on the item, or 0 if it doesn't have it.
+    pub fn enchantment(&self, name: &str) -> i32 {
+        let enchantments = match self.nbt.as_ref().and_then(|nbt| nbt.get("Enchantments")) {
+            Some(Tag::List(enchantments)) => enchantments,
+            _ => return 0
+        };
+        enchantments.iter().find_map(|enchantment| match enchantment {
+            Tag::Compound(enchantment) if matches!(enchantment.get("id"), Some(Tag::String(id)) if id == name) => {
+                match enchantment.get("lvl") {
+                    Some(Tag::Short(level)) => Some(*level as i32),
+                    Some(Tag::Int(level)) => Some(*level),
+                    _ => None
+                }
+            }
+            _ => None
+        }).unwrap_or(0)
+    }
+
+    /// Whether `other` is the same item with the same tag, so the two can go in one slot.
+    pub fn stacks_with(&self, other: &ItemStack) -> bool {
+        self.item == other.item && self.nbt == other.nbt
+    }
+}
+
+impl Clone for ItemStack {
+    fn clone(&self) -> Self {
+        ItemStack {
+            item: self.item,
+            count: self.count,
+            nbt: self.nbt.as_ref().map(clone_compound)
+        }
+    }
+}
+
+pub(crate) fn clone_compound(compound: &HashMap<String, Tag>) -> HashMap<String, Tag> {
+    compound.iter().map(|(name, tag)| (name.clone(), clone_tag(tag))).collect()
+}
+
+/// A copy of `tag`, which nbt can't make itself.
+fn clone_tag(tag: &Tag) -> Tag {
+    match tag {
+        Tag::Byte(v) => Tag::Byte(*v),
+        Tag::Short(v) => Tag::Short(*v),
+        Tag::Int(v) => Tag::Int(*v),
+        Tag::Long(v) => Tag::Long(*v),
+        Tag::Float(v) => Tag::Float(*v),
+        Tag::Double(v) => Tag::Double(*v),
+        Tag::ByteArray(v) => Tag::ByteArray(v.clone()),
+        Tag::String(v) => Tag::String(v.clone()),
+        Tag::List(v) => Tag::List(v.iter().map(clone_tag).collect()),
+        Tag::Compound(v) => Tag::Compound(clone_compound(v)),
+        Tag::IntArray(v) => Tag::IntArray(v.clone()),
+        Tag::LongArray(v) => Tag::LongArray(v.clone())
+    }
+}
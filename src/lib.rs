@@ -2,14 +2,31 @@ use std::fmt::{Display, Formatter, Write};
 use serde::{Serialize, Deserialize};
 
 pub mod client;
+pub mod command;
 pub mod config;
 pub mod packets;
 pub mod traits;
 pub mod buffer;
 pub mod world;
+pub mod biome;
+pub mod dimension;
+pub mod datapack;
+pub mod heightmap;
+pub mod light;
+pub mod block_entity;
+pub mod entity;
+pub mod item;
+pub mod inventory;
+pub mod recipe;
+pub mod playerdata;
+pub mod generator;
+pub mod noise;
+#[cfg(feature = "world_syncers")] pub mod chunk_io;
+#[cfg(feature = "world_syncers")] pub mod memory;
 pub mod server;
 #[cfg(feature = "single")] pub mod single;
-mod registry;
+#[cfg(feature = "anvil")] pub mod anvil;
+pub mod registry;
 
 pub const MINECRAFT_VERSION: &str = "1.17.0";
 pub const GRIMSTONE_VERSION: &str = "1.17.0.2";
@@ -19,7 +36,7 @@ pub const MINECRAFT_PROTOCOL_VERSION: u32 = 755;
 pub struct NamespacedId<'id>(&'id str, &'id str);
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
-pub struct Vector3I(i64, i64, i64);
+pub struct Vector3I(pub i64, pub i64, pub i64);
 
 impl<'a> Display for NamespacedId<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -36,6 +53,69 @@ pub const fn nsid<'a>(ns: &'a str, id: &'a str) -> NamespacedId<'a> {
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum GameMode {
-    Survival
+    Survival,
+    Creative,
+    Adventure,
+    Spectator
+}
+
+impl GameMode {
+    /// The number vanilla sends and saves for the game mode.
+    pub fn id(&self) -> u8 {
+        match self {
+            GameMode::Survival => 0,
+            GameMode::Creative => 1,
+            GameMode::Adventure => 2,
+            GameMode::Spectator => 3
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<GameMode> {
+        match id {
+            0 => Some(GameMode::Survival),
+            1 => Some(GameMode::Creative),
+            2 => Some(GameMode::Adventure),
+            3 => Some(GameMode::Spectator),
+            _ => None
+        }
+    }
+
+    /// The name used for the game mode in commands and the config, like `creative`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Survival => "survival",
+            GameMode::Creative => "creative",
+            GameMode::Adventure => "adventure",
+            GameMode::Spectator => "spectator"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<GameMode> {
+        (0..=3).filter_map(GameMode::from_id).find(|mode| mode.name() == name)
+    }
+
+    /// Whether players can break and place blocks.
+    pub fn can_build(&self) -> bool {
+        matches!(self, GameMode::Survival | GameMode::Creative)
+    }
+
+    /// Whether blocks break as soon as players start digging them, bedrock included.
+    pub fn instant_build(&self) -> bool {
+        *self == GameMode::Creative
+    }
+
+    pub fn invulnerable(&self) -> bool {
+        matches!(self, GameMode::Creative | GameMode::Spectator)
+    }
+
+    pub fn can_fly(&self) -> bool {
+        matches!(self, GameMode::Creative | GameMode::Spectator)
+    }
+
+    /// Whether players go through blocks.
+    pub fn no_clip(&self) -> bool {
+        *self == GameMode::Spectator
+    }
 }
@@ -0,0 +1,361 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryInto;
+use crate::Vector3I;
+use crate::world::{Block, Chunk, ChunkLoadState, World, WorldError};
+
+/// The highest light level.
+pub const MAX_LIGHT: u8 = 15;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum LightKind {
+    /// Light from the sky, 15 wherever it can be seen.
+    Sky,
+    /// Light from blocks like torches.
+    Block
+}
+
+impl LightKind {
+    pub const ALL: [LightKind; 2] = [LightKind::Sky, LightKind::Block];
+}
+
+/// A light level for each block of a chunk, two to a byte like vanilla: the block at index `i`
+/// is the low nibble of byte `i / 2` when `i` is even and the high one when it's odd.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LightArray {
+    nibbles: [u8; 2048]
+}
+
+impl LightArray {
+    pub fn empty() -> LightArray {
+        LightArray { nibbles: [0; 2048] }
+    }
+
+    pub fn full() -> LightArray {
+        LightArray { nibbles: [0xFF; 2048] }
+    }
+
+    /// Takes stored nibbles, if there are exactly 2048 bytes of them.
+    pub fn from_bytes(bytes: &[u8]) -> Option<LightArray> {
+        Some(LightArray { nibbles: bytes.try_into().ok()? })
+    }
+
+    pub fn bytes(&self) -> &[u8; 2048] {
+        &self.nibbles
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nibbles.iter().all(|b| *b == 0)
+    }
+
+    pub fn get(&self, i: usize) -> u8 {
+        (self.nibbles[i >> 1] >> ((i & 1) * 4)) & 0xF
+    }
+
+    pub fn set(&mut self, i: usize, level: u8) {
+        let shift = (i & 1) * 4;
+        let byte = &mut self.nibbles[i >> 1];
+        *byte = (*byte & !(0xF << shift)) | ((level & 0xF) << shift);
+    }
+}
+
+const NEIGHBOURS: [(i64, i64, i64); 6] = [(1, 0, 0), (-1, 0, 0), (0, 1, 0), (0, -1, 0), (0, 0, 1), (0, 0, -1)];
+
+/// How much light is left after spreading from a block with `level` into `to`, going down if
+/// `down`. Sky light keeps going straight down without fading through blocks that don't take any.
+fn spread(kind: LightKind, level: u8, to: Block, down: bool) -> u8 {
+    let opacity = to.light_opacity();
+    if kind == LightKind::Sky && down && level == MAX_LIGHT && opacity == 0 {
+        MAX_LIGHT
+    } else {
+        level.saturating_sub(opacity.max(1))
+    }
+}
+
+/// Lights a whole column from its own blocks. `sections` are the column's chunks from the bottom
+/// up; light that would come from neighbouring columns is left to `World::light_column`.
+pub(crate) fn light_sections(sections: &mut [&mut Chunk]) {
+    let height = sections.len() * 16;
+    let index = |x: usize, y: usize, z: usize| (y * 16 + z) * 16 + x;
+    let mut blocks = vec![Block::Air; height * 256];
+    for (s, section) in sections.iter().enumerate() {
+        for (i, block) in section.blocks.iter().enumerate() {
+            blocks[s * 4096 + i] = *block;
+        }
+    }
+
+    for kind in LightKind::ALL {
+        let mut light = vec![0u8; height * 256];
+        let mut queue = VecDeque::new();
+        match kind {
+            LightKind::Sky => {
+                for z in 0..16 {
+                    for x in 0..16 {
+                        let mut level = MAX_LIGHT;
+                        for y in (0..height).rev() {
+                            let i = index(x, y, z);
+                            level = level.saturating_sub(blocks[i].light_opacity());
+                            light[i] = level;
+                            if level == 0 { break; }
+                        }
+                    }
+                }
+                // only light next to something darker has anywhere to spread
+                for y in 0..height {
+                    for z in 0..16 {
+                        for x in 0..16 {
+                            let level = light[index(x, y, z)];
+                            if level <= 1 { continue; }
+                            let darker = (x > 0 && light[index(x - 1, y, z)] < level - 1)
+                                || (x < 15 && light[index(x + 1, y, z)] < level - 1)
+                                || (z > 0 && light[index(x, y, z - 1)] < level - 1)
+                                || (z < 15 && light[index(x, y, z + 1)] < level - 1)
+                                || (y > 0 && light[index(x, y - 1, z)] < level - 1)
+                                || (y < height - 1 && light[index(x, y + 1, z)] < level - 1);
+                            if darker {
+                                queue.push_back((x, y, z));
+                            }
+                        }
+                    }
+                }
+            }
+            LightKind::Block => {
+                for (i, block) in blocks.iter().enumerate() {
+                    let emission = block.light_emission();
+                    if emission > 0 {
+                        light[i] = emission;
+                        queue.push_back((i % 16, i / 256, (i / 16) % 16));
+                    }
+                }
+            }
+        }
+
+        while let Some((x, y, z)) = queue.pop_front() {
+            let level = light[index(x, y, z)];
+            for (dx, dy, dz) in NEIGHBOURS {
+                let (nx, ny, nz) = (x as i64 + dx, y as i64 + dy, z as i64 + dz);
+                if !(0..16).contains(&nx) || !(0..16).contains(&nz) || !(0..height as i64).contains(&ny) {
+                    continue;
+                }
+                let (nx, ny, nz) = (nx as usize, ny as usize, nz as usize);
+                let n = index(nx, ny, nz);
+                let new = spread(kind, level, blocks[n], dy < 0);
+                if new > light[n] {
+                    light[n] = new;
+                    queue.push_back((nx, ny, nz));
+                }
+            }
+        }
+
+        for (s, section) in sections.iter_mut().enumerate() {
+            let mut array = LightArray::empty();
+            for i in 0..4096 {
+                array.set(i, light[s * 4096 + i]);
+            }
+            *section.light_mut(kind) = array;
+        }
+    }
+
+    for section in sections.iter_mut() {
+        section.lit = true;
+        section.dirty = true;
+    }
+}
+
+/// Reads and writes light anywhere in a world's lit chunks by block position. Blocks in chunks
+/// that aren't loaded or lit are treated as walls, and the sky above the world is always bright.
+struct LightView<'a> {
+    chunks: &'a mut HashMap<Vector3I, ChunkLoadState>,
+    min_y: i64,
+    max_y: i64
+}
+
+impl LightView<'_> {
+    fn chunk(&mut self, x: i64, y: i64, z: i64) -> Option<&mut Chunk> {
+        if y < self.min_y || y >= self.max_y {
+            return None;
+        }
+        match self.chunks.get_mut(&Vector3I(x.div_euclid(16), y.div_euclid(16), z.div_euclid(16))) {
+            Some(ChunkLoadState::Loaded { chunk }) if chunk.lit => Some(chunk),
+            _ => None
+        }
+    }
+
+    fn get(&mut self, kind: LightKind, x: i64, y: i64, z: i64) -> Option<(u8, Block)> {
+        let chunk = self.chunk(x, y, z)?;
+        let i = local_index(x, y, z);
+        Some((chunk.light(kind).get(i), chunk.blocks[i]))
+    }
+
+    fn set(&mut self, kind: LightKind, x: i64, y: i64, z: i64, level: u8) {
+        if let Some(chunk) = self.chunk(x, y, z) {
+            chunk.light_mut(kind).set(local_index(x, y, z), level);
+            chunk.dirty = true;
+        }
+    }
+
+    /// Spreads light outwards from every position in `queue`.
+    fn spread(&mut self, kind: LightKind, mut queue: VecDeque<(i64, i64, i64)>, changed: &mut Vec<Vector3I>) {
+        while let Some((x, y, z)) = queue.pop_front() {
+            let level = match self.get(kind, x, y, z) {
+                Some((level, _)) => level,
+                None => continue
+            };
+            for (dx, dy, dz) in NEIGHBOURS {
+                let (nx, ny, nz) = (x + dx, y + dy, z + dz);
+                if let Some((current, block)) = self.get(kind, nx, ny, nz) {
+                    let new = spread(kind, level, block, dy < 0);
+                    if new > current {
+                        self.set(kind, nx, ny, nz, new);
+                        changed.push(chunk_of(nx, ny, nz));
+                        queue.push_back((nx, ny, nz));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Takes away the light at `x`, `y`, `z` and everything that only had it from there, then
+    /// fills the gap back in from what's around it.
+    fn remove(&mut self, kind: LightKind, x: i64, y: i64, z: i64, changed: &mut Vec<Vector3I>) {
+        let mut removing = VecDeque::new();
+        let mut refill = VecDeque::new();
+        if let Some((level, _)) = self.get(kind, x, y, z) {
+            self.set(kind, x, y, z, 0);
+            removing.push_back((x, y, z, level));
+        }
+
+        while let Some((x, y, z, level)) = removing.pop_front() {
+            for (dx, dy, dz) in NEIGHBOURS {
+                let (nx, ny, nz) = (x + dx, y + dy, z + dz);
+                let current = match self.get(kind, nx, ny, nz) {
+                    Some((current, _)) => current,
+                    None => continue
+                };
+                if current == 0 { continue; }
+                let fed = current < level || (kind == LightKind::Sky && dy < 0 && level == MAX_LIGHT);
+                if fed {
+                    self.set(kind, nx, ny, nz, 0);
+                    changed.push(chunk_of(nx, ny, nz));
+                    removing.push_back((nx, ny, nz, current));
+                } else {
+                    refill.push_back((nx, ny, nz));
+                }
+            }
+        }
+        self.spread(kind, refill, changed);
+    }
+}
+
+fn local_index(x: i64, y: i64, z: i64) -> usize {
+    ((y.rem_euclid(16) << 8) | (z.rem_euclid(16) << 4) | x.rem_euclid(16)) as usize
+}
+
+fn chunk_of(x: i64, y: i64, z: i64) -> Vector3I {
+    Vector3I(x.div_euclid(16), y.div_euclid(16), z.div_euclid(16))
+}
+
+impl World {
+    fn light_view(&mut self) -> LightView<'_> {
+        let (min_y, max_y) = (self.min_y(), self.min_y() + self.height());
+        LightView { chunks: &mut self.chunks, min_y, max_y }
+    }
+
+    /// Lights the column at `x`, `z` if any of its sections has no light yet, and lets light flow
+    /// between it and the lit columns around it. The column has to be loaded.
+    pub(crate) fn light_column(&mut self, x: i32, z: i32) {
+        let range = self.section_range();
+        let unlit = range.clone().any(|y| {
+            !matches!(self.chunks.get(&Vector3I(x as i64, y as i64, z as i64)), Some(ChunkLoadState::Loaded { chunk }) if chunk.lit)
+        });
+        if !unlit {
+            return;
+        }
+
+        // take the sections out so they can all be borrowed at once
+        let mut sections = vec![];
+        for y in range.clone() {
+            match self.chunks.remove(&Vector3I(x as i64, y as i64, z as i64)) {
+                Some(ChunkLoadState::Loaded { chunk }) => sections.push(chunk),
+                other => {
+                    if let Some(state) = other {
+                        self.chunks.insert(Vector3I(x as i64, y as i64, z as i64), state);
+                    }
+                    for chunk in sections {
+                        self.chunks.insert(Vector3I(chunk.x as i64, chunk.y as i64, chunk.z as i64), ChunkLoadState::Loaded { chunk });
+                    }
+                    return;
+                }
+            }
+        }
+        light_sections(&mut sections.iter_mut().collect::<Vec<_>>());
+        for chunk in sections {
+            self.chunks.insert(Vector3I(chunk.x as i64, chunk.y as i64, chunk.z as i64), ChunkLoadState::Loaded { chunk });
+        }
+
+        // light on either side of each edge with a lit column spreads over it
+        let (bx, bz) = (x as i64 * 16, z as i64 * 16);
+        let (min_y, max_y) = (self.min_y(), self.min_y() + self.height());
+        let mut view = self.light_view();
+        let sides = [((1, 0), (bx + 15, bz), (0, 1)), ((-1, 0), (bx, bz), (0, 1)),
+                     ((0, 1), (bx, bz + 15), (1, 0)), ((0, -1), (bx, bz), (1, 0))];
+        let mut edges = vec![];
+        for ((dx, dz), (ex, ez), (ix, iz)) in sides {
+            if view.chunk(ex + dx, min_y, ez + dz).is_none() {
+                continue;
+            }
+            for y in min_y..max_y {
+                for i in 0..16 {
+                    edges.push((ex + ix * i, y, ez + iz * i));
+                    edges.push((ex + ix * i + dx, y, ez + iz * i + dz));
+                }
+            }
+        }
+        let mut changed = vec![];
+        for kind in LightKind::ALL {
+            view.spread(kind, edges.iter().copied().collect(), &mut changed);
+        }
+    }
+
+    /// The light of `kind` at `x`, `y`, `z`, loading and lighting its column if needed.
+    pub fn light(&mut self, kind: LightKind, x: i64, y: i64, z: i64) -> Result<u8, WorldError> {
+        if y >= self.min_y() + self.height() {
+            return Ok(if kind == LightKind::Sky { MAX_LIGHT } else { 0 });
+        }
+        if y < self.min_y() {
+            return Ok(0);
+        }
+        self.load_column(x.div_euclid(16) as i32, z.div_euclid(16) as i32)?;
+        Ok(self.light_view().get(kind, x, y, z).map_or(0, |(level, _)| level))
+    }
+
+    /// Brings light up to date after the block at `x`, `y`, `z` changed, giving the chunks whose
+    /// light changed.
+    pub(crate) fn relight(&mut self, x: i64, y: i64, z: i64) -> Vec<Vector3I> {
+        let mut view = self.light_view();
+        let mut changed = vec![chunk_of(x, y, z)];
+        let block = match view.chunk(x, y, z) {
+            Some(chunk) => chunk.blocks[local_index(x, y, z)],
+            None => return vec![]
+        };
+
+        for kind in LightKind::ALL {
+            view.remove(kind, x, y, z, &mut changed);
+            let emission = if kind == LightKind::Block { block.light_emission() } else { 0 };
+            if emission > 0 {
+                view.set(kind, x, y, z, emission);
+            }
+            // the top of the world sees the sky directly
+            if kind == LightKind::Sky && y == view.max_y - 1 {
+                view.set(kind, x, y, z, MAX_LIGHT.saturating_sub(block.light_opacity()));
+            }
+            // let the surroundings shine in, or out again from the block itself
+            let mut queue: VecDeque<_> = NEIGHBOURS.iter().map(|(dx, dy, dz)| (x + dx, y + dy, z + dz)).collect();
+            queue.push_back((x, y, z));
+            view.spread(kind, queue, &mut changed);
+        }
+
+        changed.sort_by_key(|p| (p.0, p.1, p.2));
+        changed.dedup();
+        changed
+    }
+}
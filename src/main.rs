@@ -2,25 +2,30 @@ use std::net::TcpListener;
 use std::process::exit;
 use std::sync::{Arc, mpsc};
 use std::thread;
+use std::io::{stdin, BufRead};
+use std::time::Duration;
 
 use grimstone::traits::Packet;
 use grimstone::client::{Client, PacketRef, Error};
-use grimstone::config::{Config, ConcreteConfig};
+use grimstone::config::{Config, ConcreteConfig, ConcreteWorldConfig};
 use grimstone::packets;
+use grimstone::command;
+use grimstone::biome;
+use grimstone::dimension;
+use grimstone::datapack;
+use grimstone::generator::{ChunkGenerator, FlatGenerator, VoidGenerator, NoiseGenerator, parse_seed};
 use simple_logger::SimpleLogger;
 use log::LevelFilter;
-use grimstone::server::Server;
+use grimstone::server::{self, Server};
 use grimstone::single::SingleWorldFile;
-use grimstone::world::{WorldSyncer, World, ChunkContainer, Chunk};
+use grimstone::memory::{MemoryWorldSyncer, OverlayWorldSyncer};
+use grimstone::world::{WorldSyncer, World, ChunkContainer, Chunk, WorldError};
+#[cfg(feature = "anvil")] use grimstone::anvil::AnvilWorld;
 use std::any::Any;
 use std::borrow::BorrowMut;
 use grimstone::world::Block::Air;
 
 fn main() -> Result<(), Error> {
-    Server::global().world = Some(World::new(Box::new(SingleWorldFile::new("world.sng"))));
-    let w = Server::global().world.as_mut().unwrap();
-    w.load_chunk(0, 0, 0);
-
     SimpleLogger::new()
         .with_level(LevelFilter::Debug)
         .init()
@@ -28,24 +33,92 @@ fn main() -> Result<(), Error> {
     log::debug!("Initializing server");
 
     let config = &ConcreteConfig::from(Config::load());
+
+    let packs = match datapack::load(std::path::Path::new(&config.server_datapacks)) {
+        Ok(packs) => packs,
+        Err(e) => {
+            log::error!("Could not load data packs: {}", e);
+            exit(1);
+        }
+    };
+    log::info!("Loaded {} dimension types and {} biomes from data packs",
+        packs.dimension_types.len(), packs.biomes.len());
+    dimension::init(packs.dimension_types).expect("Dimension types were looked up before the registry was set up");
+
+    let custom_biomes = match &config.server_custom_biomes {
+        Some(path) => match biome::load_custom(path) {
+            Ok(biomes) => biomes,
+            Err(e) => {
+                log::error!("Could not load custom biomes: {}", e);
+                exit(1);
+            }
+        },
+        None => vec![]
+    };
+    log::info!("Loaded {} custom biomes", custom_biomes.len());
+    let mut biomes = packs.biomes;
+    biomes.extend(custom_biomes);
+    biome::init(biomes).expect("Biomes were looked up before the registry was set up");
+
+    for world in &config.worlds {
+        let world = open_world(world, config);
+        Server::global().add_world(world);
+    }
+    for world in Server::global().worlds() {
+        let mut world = server::lock(world);
+        if let Err(e) = world.load_chunk(0, 0, 0) {
+            log::error!("Could not load spawn chunk of {}: {}", world.name(), e);
+            exit(1);
+        }
+    }
+
     let server = Arc::new(TcpListener::bind(
         format!("127.0.0.1:{}", config.server_port))
         .expect("Could not create server"));
 
     log::info!("Server started on port {}", config.server_port);
 
+    thread::spawn(|| {
+        for line in stdin().lock().lines().map_while(Result::ok) {
+            command::execute(&line);
+        }
+    });
+
+    thread::spawn(|| loop {
+        thread::sleep(Duration::from_millis(50));
+        Server::global().tick();
+    });
+
+    if config.world_autosave_interval > 0 {
+        let interval = Duration::from_secs(config.world_autosave_interval);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            for world in Server::global().worlds() {
+                server::lock(world).save_all();
+            }
+            Server::global().save_players();
+        });
+    }
+
     loop {
         let result = server.accept();
         if let Ok((stream, addr)) = result {
             let conf = config.clone();
             thread::spawn(move || {
                 let mut client = Client::new(stream, conf);
-                packets::register(&mut client);
+                if let Err(e) = packets::register(&mut client) {
+                    log::error!("Could not register packets for {}: {}", addr, e);
+                    return;
+                }
                 while client.is_valid {
                     let mut packet_result = client.read_packet();
                     match packet_result {
                         Ok(packet) => {
-                            packet.act(&mut client).expect("Error while processing packet {}");
+                            if let Err(error) = packet.act(&mut client) {
+                                log::error!("Error while processing packet {:#04x} from {}: {:?}", packet.id(), addr, error);
+                                break;
+                            }
+                            client.autosave();
                         }
                         Err(error) => {
                             match error {
@@ -64,9 +137,97 @@ fn main() -> Result<(), Error> {
                         }
                     }
                 }
+                if let Some(mut world) = client.current_world() {
+                    client.close_window(&mut world);
+                }
+                client.save();
+                client.leave_world();
             });
         }
     }
 
     Ok(())
 }
+
+/// Opens the world `world` describes, exiting if it can't be.
+fn open_world(world: &ConcreteWorldConfig, config: &ConcreteConfig) -> World {
+    if dimension::get(&world.dimension_type).is_none() {
+        log::error!("Unknown dimension type {} for world {}", world.dimension_type, world.name);
+        exit(1);
+    }
+
+    let sync: Result<Box<dyn WorldSyncer>, WorldError> = match world.format.as_str() {
+        "single" => SingleWorldFile::new(&world.path).map(|s| Box::new(s) as Box<dyn WorldSyncer>),
+        "single-readonly" => OverlayWorldSyncer::<SingleWorldFile>::new(&world.path)
+            .map(|s| Box::new(s) as Box<dyn WorldSyncer>),
+        #[cfg(feature = "anvil")]
+        "anvil" => AnvilWorld::new(&world.path).map(|s| Box::new(s) as Box<dyn WorldSyncer>),
+        #[cfg(feature = "anvil")]
+        "anvil-readonly" => OverlayWorldSyncer::<AnvilWorld>::new(&world.path)
+            .map(|s| Box::new(s) as Box<dyn WorldSyncer>),
+        "memory" => MemoryWorldSyncer::new(&world.path).map(|s| Box::new(s) as Box<dyn WorldSyncer>),
+        other => {
+            log::error!("Unknown world format {} for world {}", other, world.name);
+            exit(1);
+        }
+    };
+    let mut sync = match sync {
+        Ok(sync) => sync,
+        Err(e) => {
+            log::error!("Could not open world {} at {}: {}", world.name, world.path, e);
+            exit(1);
+        }
+    };
+    let seed = match world_seed(sync.as_mut(), world) {
+        Ok(seed) => seed,
+        Err(e) => {
+            log::error!("Could not store the seed of world {}: {}", world.name, e);
+            exit(1);
+        }
+    };
+
+    let generator: Arc<dyn ChunkGenerator> = match world.generator_type.as_str() {
+        "void" => Arc::new(VoidGenerator),
+        "noise" => Arc::new(NoiseGenerator::new(seed)),
+        "flat" => match FlatGenerator::new(&world.generator_layers, &world.generator_biome) {
+            Ok(flat) => Arc::new(flat),
+            Err(e) => {
+                log::error!("Bad superflat layers {} for world {}: {}", world.generator_layers, world.name, e);
+                exit(1);
+            }
+        },
+        other => {
+            log::error!("Unknown world generator {} for world {}", other, world.name);
+            exit(1);
+        }
+    };
+
+    match World::new(&world.name, &world.dimension_type, sync, generator, config.world_io_threads) {
+        Ok(w) => {
+            log::info!("Opened world {} ({})", world.name, world.dimension_type);
+            w
+        }
+        Err(e) => {
+            log::error!("Could not open world {} at {}: {}", world.name, world.path, e);
+            exit(1);
+        }
+    }
+}
+
+/// The seed the world was made with, or a new one from the config (or at random) for new worlds.
+fn world_seed(sync: &mut dyn WorldSyncer, world: &ConcreteWorldConfig) -> Result<i64, WorldError> {
+    let mut meta = sync.load_meta()?;
+    if let Some(seed) = meta.seed {
+        return Ok(seed);
+    }
+
+    let seed = match &world.generator_seed {
+        Some(seed) => parse_seed(seed),
+        None => rand::random()
+    };
+    log::info!("Using seed {} for the new world {}", seed, world.name);
+    meta.seed = Some(seed);
+    sync.save_meta(&meta)?;
+    sync.flush()?;
+    Ok(seed)
+}
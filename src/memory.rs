@@ -0,0 +1,137 @@
+#![cfg(feature = "world_syncers")]
+
+use crate::world::{WorldSyncer, Chunk, WorldError, WorldMeta};
+use crate::Vector3I;
+use std::collections::HashMap;
+
+/// Keeps every saved chunk in memory and forgets them all when dropped. Useful for tests and
+/// worlds that don't need to outlive the server.
+#[derive(Default)]
+pub struct MemoryWorldSyncer {
+    chunks: HashMap<Vector3I, Chunk>,
+    meta: Option<WorldMeta>
+}
+
+impl MemoryWorldSyncer {
+    pub fn empty() -> MemoryWorldSyncer {
+        MemoryWorldSyncer::default()
+    }
+
+    /// Forgets every saved chunk and the world's metadata.
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.meta = None;
+    }
+}
+
+impl WorldSyncer for MemoryWorldSyncer {
+    /// There's nothing to open, so `path` is ignored.
+    fn new(_path: &str) -> Result<Self, WorldError> where Self: Sized {
+        Ok(MemoryWorldSyncer::empty())
+    }
+
+    fn open_read_only(path: &str) -> Result<Self, WorldError> where Self: Sized {
+        Self::new(path)
+    }
+
+    fn save(&mut self, chunk: &mut Chunk) -> Result<(), WorldError> {
+        let pos = Vector3I(chunk.x as i64, chunk.y as i64, chunk.z as i64);
+        let mut copy = chunk.clone();
+        copy.dirty = false;
+        self.chunks.insert(pos, copy);
+        Ok(())
+    }
+
+    fn find_all(&mut self) -> Result<Vec<Vector3I>, WorldError> {
+        Ok(self.chunks.keys().copied().collect())
+    }
+
+    fn load(&mut self, x: i32, y: i32, z: i32) -> Result<Chunk, WorldError> {
+        let pos = Vector3I(x as i64, y as i64, z as i64);
+        self.chunks.get(&pos).cloned().ok_or(WorldError::MissingChunk(pos))
+    }
+
+    fn load_meta(&mut self) -> Result<WorldMeta, WorldError> {
+        Ok(self.meta.clone().unwrap_or_default())
+    }
+
+    fn save_meta(&mut self, meta: &WorldMeta) -> Result<(), WorldError> {
+        self.meta = Some(meta.clone());
+        Ok(())
+    }
+
+    /// Everything was saved since the syncer was opened, so all of it goes.
+    fn reset(&mut self) -> bool {
+        self.clear();
+        true
+    }
+}
+
+/// Serves chunks from a template world but only ever saves into memory, so the template is never
+/// touched. Dropping the overlay (or resetting it, see `World::reset`) puts the map back the way
+/// it was, which is what minigame maps want.
+pub struct OverlayWorldSyncer<T: WorldSyncer> {
+    template: T,
+    changes: MemoryWorldSyncer
+}
+
+impl<T: WorldSyncer> OverlayWorldSyncer<T> {
+    pub fn from_template(template: T) -> OverlayWorldSyncer<T> {
+        OverlayWorldSyncer { template, changes: MemoryWorldSyncer::empty() }
+    }
+
+    pub fn template(&mut self) -> &mut T {
+        &mut self.template
+    }
+}
+
+impl<T: WorldSyncer> WorldSyncer for OverlayWorldSyncer<T> {
+    /// Opens the template world at `path`, read-only.
+    fn new(path: &str) -> Result<Self, WorldError> where Self: Sized {
+        Self::open_read_only(path)
+    }
+
+    fn open_read_only(path: &str) -> Result<Self, WorldError> where Self: Sized {
+        T::open_read_only(path).map(OverlayWorldSyncer::from_template)
+    }
+
+    fn save(&mut self, chunk: &mut Chunk) -> Result<(), WorldError> {
+        self.changes.save(chunk)
+    }
+
+    fn find_all(&mut self) -> Result<Vec<Vector3I>, WorldError> {
+        let mut poses = self.template.find_all()?;
+        for pos in self.changes.chunks.keys() {
+            if !poses.contains(pos) { poses.push(*pos); }
+        }
+        Ok(poses)
+    }
+
+    fn load(&mut self, x: i32, y: i32, z: i32) -> Result<Chunk, WorldError> {
+        match self.changes.load(x, y, z) {
+            Err(WorldError::MissingChunk(_)) => self.template.load(x, y, z),
+            other => other
+        }
+    }
+
+    fn load_meta(&mut self) -> Result<WorldMeta, WorldError> {
+        match &self.changes.meta {
+            Some(meta) => Ok(meta.clone()),
+            None => self.template.load_meta()
+        }
+    }
+
+    fn save_meta(&mut self, meta: &WorldMeta) -> Result<(), WorldError> {
+        self.changes.save_meta(meta)
+    }
+
+    fn set_height(&mut self, min_section: i32, sections: i32) {
+        self.template.set_height(min_section, sections);
+    }
+
+    /// Throws away every change made on top of the template.
+    fn reset(&mut self) -> bool {
+        self.changes.clear();
+        true
+    }
+}
@@ -0,0 +1,97 @@
+use rand::{Rng, SeedableRng};
+use rand::seq::SliceRandom;
+use rand_chacha::ChaCha8Rng;
+
+/// Ken Perlin's improved gradient noise over a permutation table shuffled from a seed.
+pub struct PerlinNoise {
+    perm: [u8; 512],
+    /// Shifts the lattice so different noises from the same seed don't line up at the origin.
+    offset: (f64, f64, f64)
+}
+
+impl PerlinNoise {
+    pub fn new(rng: &mut ChaCha8Rng) -> PerlinNoise {
+        let mut table: Vec<u8> = (0..=255).collect();
+        table.shuffle(rng);
+
+        let mut perm = [0u8; 512];
+        for i in 0..512 {
+            perm[i] = table[i & 255];
+        }
+        let offset = (rng.gen::<f64>() * 256.0, rng.gen::<f64>() * 256.0, rng.gen::<f64>() * 256.0);
+        PerlinNoise { perm, offset }
+    }
+
+    /// Samples the noise, giving a value in roughly `-1.0..=1.0`.
+    pub fn sample(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (x, y, z) = (x + self.offset.0, y + self.offset.1, z + self.offset.2);
+        let (fx, fy, fz) = (x.floor(), y.floor(), z.floor());
+        let (xi, yi, zi) = ((fx as i64 & 255) as usize, (fy as i64 & 255) as usize, (fz as i64 & 255) as usize);
+        let (x, y, z) = (x - fx, y - fy, z - fz);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let p = &self.perm;
+        let a = p[xi] as usize + yi;
+        let aa = p[a] as usize + zi;
+        let ab = p[a + 1] as usize + zi;
+        let b = p[xi + 1] as usize + yi;
+        let ba = p[b] as usize + zi;
+        let bb = p[b + 1] as usize + zi;
+
+        lerp(w,
+            lerp(v,
+                lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1.0, y, z)),
+                lerp(u, grad(p[ab], x, y - 1.0, z), grad(p[bb], x - 1.0, y - 1.0, z))),
+            lerp(v,
+                lerp(u, grad(p[aa + 1], x, y, z - 1.0), grad(p[ba + 1], x - 1.0, y, z - 1.0)),
+                lerp(u, grad(p[ab + 1], x, y - 1.0, z - 1.0), grad(p[bb + 1], x - 1.0, y - 1.0, z - 1.0))))
+    }
+}
+
+/// Several octaves of Perlin noise, each at twice the frequency and half the amplitude of the
+/// one before, for detail at every scale.
+pub struct OctaveNoise {
+    octaves: Vec<PerlinNoise>
+}
+
+impl OctaveNoise {
+    /// Builds the noise for `seed`. Each `salt` gives an unrelated noise from the same seed.
+    pub fn new(seed: i64, salt: u64, octaves: usize) -> OctaveNoise {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed as u64 ^ salt.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        OctaveNoise { octaves: (0..octaves).map(|_| PerlinNoise::new(&mut rng)).collect() }
+    }
+
+    /// Samples every octave, giving a value in roughly `-1.0..=1.0`.
+    pub fn sample(&self, x: f64, y: f64, z: f64) -> f64 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut max = 0.0;
+        for octave in &self.octaves {
+            total += octave.sample(x * frequency, y * frequency, z * frequency) * amplitude;
+            max += amplitude;
+            amplitude /= 2.0;
+            frequency *= 2.0;
+        }
+        total / max
+    }
+
+    pub fn sample_2d(&self, x: f64, z: f64) -> f64 {
+        self.sample(x, 0.0, z)
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+fn grad(hash: u8, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 { y } else if h == 12 || h == 14 { x } else { z };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
@@ -1,14 +1,12 @@
 use uuid::Uuid;
 use crate::traits::{Packet, Readable, Writable};
 use crate::client::{Error, Client};
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
 use crate::client::Error::Refusal;
 use crate::client::PacketState::Play;
-use rand::{Rng, SeedableRng};
-use rand_chacha::ChaCha8Rng;
-use crate::packets::play::JoinGamePacket;
-use crate::GameMode;
+use crate::packets::play::{JoinGamePacket, PlayerInfoPacket};
+use crate::server::Server;
+use crate::entity;
+use crate::playerdata;
 
 #[derive(Debug)]
 pub struct StartLoginPacket {
@@ -40,11 +38,8 @@ impl Packet for StartLoginPacket {
     }
 
     fn act(&self, client: &mut Client) -> Result<(), Error> {
-        let mut h = DefaultHasher::new();
         client.username = Some(self.username.clone());
-        client.username.hash(&mut h);
-        let mut rng = ChaCha8Rng::seed_from_u64(h.finish());
-        client.uuid = Some(Uuid::from_u128(rng.gen()));
+        client.uuid = Some(offline_uuid(&self.username));
 
         client.write_packet(&EndLoginPacket {
             uuid: client.uuid.clone().unwrap(),
@@ -60,6 +55,15 @@ impl Packet for StartLoginPacket {
     }
 }
 
+/// The UUID vanilla gives `username` in offline mode, so player data carries over between servers:
+/// a version 3 UUID from the MD5 of `OfflinePlayer:<username>`, like Java's `nameUUIDFromBytes`.
+pub fn offline_uuid(username: &str) -> Uuid {
+    let mut bytes = md5::compute(format!("OfflinePlayer:{}", username)).0;
+    bytes[6] = (bytes[6] & 0x0f) | 0x30;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    Uuid::from_bytes(bytes)
+}
+
 impl Packet for EndLoginPacket {
     fn id(&self) -> u32 { 0x02 }
 
@@ -76,10 +80,49 @@ impl Packet for EndLoginPacket {
     fn act(&self, client: &mut Client) -> Result<(), Error> {
         log::info!("State swap occurring; {:?} -> {:?}", client.state, Play);
         client.state = Play;
-        client.write_packet(&JoinGamePacket {
-            eid: 0, // TODO
-            game_mode: GameMode::Survival
+        let server = Server::global();
+        let world_names = server.world_names();
+        let saved = client.load_player_data();
+        if let Some(data) = &saved {
+            client.game_mode = data.game_mode();
+        }
+        // players go back to the world they were saved in, if it's still there
+        let saved_world = saved.as_ref().and_then(|data| {
+            let name = world_names.iter().find(|name| playerdata::dimension_id(name) == data.dimension);
+            if name.is_none() {
+                log::warn!("{} was saved in {}, which isn't a world here; they'll join the default world",
+                    self.username, data.dimension);
+            }
+            name.cloned()
         });
+        let world = match &saved_world {
+            Some(name) => server.world(name),
+            None => server.default_world()
+        }.expect("The server has no worlds");
+        client.entity_id = entity::next_id();
+        let join = JoinGamePacket {
+            eid: client.entity_id,
+            game_mode: client.game_mode,
+            hashed_seed: world.hashed_seed(),
+            dimension_type: world.dimension_type().to_string(),
+            world_names,
+            world_name: world.name().to_string()
+        };
+        drop(world);
+        client.write_packet(&join)?;
+        // the client goes by its own entry to know whether it's a spectator
+        if let (Some(uuid), Some(name)) = (client.uuid, client.username.clone()) {
+            client.write_packet(&PlayerInfoPacket::AddPlayer { uuid, name, game_mode: client.game_mode })?;
+        }
+        client.enter_world(&join.world_name);
+        if let Some(mut data) = saved {
+            if saved_world.is_none() {
+                // the saved position is in some other world
+                data.pos.clear();
+            }
+            client.apply_player_data(&data);
+        }
+        client.write_initial_play_packets()?;
         Ok(())
     }
 }
@@ -1,9 +1,13 @@
 use crate::client::{Client, PacketState, Error};
-use crate::client::PacketState::{Handshake, Status, Login};
+use crate::client::PacketState::{Handshake, Status, Login, Play};
 use crate::traits::Packet;
 use crate::packets::status::{RequestPacket, PingPongPacket};
 use crate::packets::handshake::HandshakePacket;
 use crate::packets::login::StartLoginPacket;
+use crate::packets::play::{ChatPacket, PlayerDiggingPacket, PlayerBlockPlacementPacket, AnimationPacket, TeleportConfirmPacket,
+                           PlayerPositionPacket, PlayerPositionAndRotationPacket, PlayerRotationPacket, PlayerMovementPacket,
+                           EntityActionPacket, ToggleFlyingPacket, ClickWindowPacket, CloseWindowPacket, SelectSlotPacket,
+                           CreativeInventoryActionPacket};
 
 pub mod handshake;
 pub mod status;
@@ -17,13 +21,29 @@ impl Client {
     }
 }
 
-pub fn register(client: &mut Client) -> Result<(), ()> {
-    client.register::<HandshakePacket>(Handshake, 0x00);
+pub fn register(client: &mut Client) -> Result<(), Error> {
+    client.register::<HandshakePacket>(Handshake, 0x00)?;
 
-    client.register::<RequestPacket>(Status, 0x00);
-    client.register::<PingPongPacket>(Status, 0x01);
+    client.register::<RequestPacket>(Status, 0x00)?;
+    client.register::<PingPongPacket>(Status, 0x01)?;
 
-    client.register::<StartLoginPacket>(Login, 0x00);
+    client.register::<StartLoginPacket>(Login, 0x00)?;
+
+    client.register::<TeleportConfirmPacket>(Play, 0x00)?;
+    client.register::<ChatPacket>(Play, 0x03)?;
+    client.register::<ClickWindowPacket>(Play, 0x08)?;
+    client.register::<CloseWindowPacket>(Play, 0x09)?;
+    client.register::<PlayerPositionPacket>(Play, 0x11)?;
+    client.register::<PlayerPositionAndRotationPacket>(Play, 0x12)?;
+    client.register::<PlayerRotationPacket>(Play, 0x13)?;
+    client.register::<PlayerMovementPacket>(Play, 0x14)?;
+    client.register::<ToggleFlyingPacket>(Play, 0x19)?;
+    client.register::<PlayerDiggingPacket>(Play, 0x1A)?;
+    client.register::<EntityActionPacket>(Play, 0x1B)?;
+    client.register::<SelectSlotPacket>(Play, 0x25)?;
+    client.register::<CreativeInventoryActionPacket>(Play, 0x28)?;
+    client.register::<AnimationPacket>(Play, 0x2C)?;
+    client.register::<PlayerBlockPlacementPacket>(Play, 0x2E)?;
 
     Ok(())
 }
\ No newline at end of file
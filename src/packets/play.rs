@@ -2,147 +2,467 @@ use crate::GameMode;
 use crate::traits::{Packet, Readable, Writable};
 use crate::client::{Error, Client};
 use crate::client::Error::Refusal;
-use serde::Serialize;
 use nbt::{Blob, Tag, NBTWrite};
-use std::collections::HashMap;
-use crate::registry::Registry;
+use crate::world::{World, WorldError, Chunk, Block, SectionChanges};
+use crate::buffer::Buffer;
+use crate::heightmap::{ColumnHeightmaps, HeightmapKind};
+use crate::light::{LightArray, LightKind};
+use crate::block_entity::BlockEntity;
+use crate::biome;
+use crate::dimension;
+use crate::command;
+use uuid::Uuid;
+use crate::Vector3I;
+use crate::inventory::{self, Slot, WindowType};
+use crate::server::Server;
+use crate::world::ChunkContainer;
+use crate::entity::{PlayerEntity, Entity, Metadata, MetadataValue, Pose, EYE_HEIGHT, FLAGS_INDEX, POSE_INDEX,
+                    FLAG_CROUCHING, FLAG_SPRINTING};
+use crate::item::{ItemStack, ToolKind};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct JoinGamePacket {
     pub eid: i32,
+    pub game_mode: GameMode,
+    /// See `World::hashed_seed`.
+    pub hashed_seed: i64,
+    pub dimension_type: String,
+    /// Every world on the server.
+    pub world_names: Vec<String>,
+    /// The world the player spawns in.
+    pub world_name: String
+}
+
+/// How many columns around the player they're sent in each direction.
+pub const VIEW_DISTANCE: i32 = 4;
+
+/// A whole column of chunks, from the bottom of the world to the top.
+#[derive(Debug, Clone)]
+pub struct ChunkDataPacket {
+    pub x: i32,
+    pub z: i32,
+    /// Which sections are in `data`, a bit for each from the bottom up; all-air ones are left out.
+    pub bitmask: Vec<i64>,
+    pub heightmaps: ColumnHeightmaps,
+    /// Biome ids of every 4x4x4 cell in the column, bottom section first.
+    pub biomes: Vec<i32>,
+    pub data: Vec<u8>,
+    /// The column's block entities by world position.
+    pub block_entities: Vec<((i64, i64, i64), BlockEntity)>
+}
+
+/// How far from a block's middle a player's eyes can be to dig it, squared.
+const DIG_REACH_SQUARED: f64 = 6.0 * 6.0;
+/// How far from the middle of the clicked block a player's feet can be to place against it, squared.
+const PLACE_REACH_SQUARED: f64 = 8.0 * 8.0;
+/// How far a player can move from one movement packet to the next, squared, like vanilla's limit.
+const MAX_MOVE_SQUARED: f64 = 10.0 * 10.0;
+/// How far apart the spots checked for blocks in the way along a move are.
+const MOVE_STEP: f64 = 0.25;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DiggingStatus {
+    Started,
+    Cancelled,
+    Finished,
+    DropStack,
+    DropItem,
+    /// Letting go of a bow, or finishing eating.
+    ReleaseUseItem,
+    SwapHands
+}
+
+/// A player starting, stopping or finishing digging, or one of the other things the same packet
+/// is used for.
+#[derive(Debug)]
+pub struct PlayerDiggingPacket {
+    pub status: DiggingStatus,
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+    pub face: u8
+}
+
+/// Tells the client whether the server went along with its digging, and what the block is now.
+#[derive(Debug, Clone)]
+pub struct AckPlayerDiggingPacket {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+    pub block: Block,
+    pub status: DiggingStatus,
+    pub successful: bool
+}
+
+/// A player using their hand on a block, which places what they're holding.
+#[derive(Debug)]
+pub struct PlayerBlockPlacementPacket {
+    /// 0 for the main hand, 1 for the off hand.
+    pub hand: i32,
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+    /// Which side of the block was clicked: down, up, north, south, west or east.
+    pub face: i32,
+    /// Where on that side, from 0 to 1 along each axis.
+    pub cursor: (f32, f32, f32),
+    pub inside_block: bool
+}
+
+/// A player swinging their arm.
+#[derive(Debug)]
+pub struct AnimationPacket {
+    pub hand: i32
+}
+
+/// A player letting the server know they've been moved by a teleport.
+#[derive(Debug)]
+pub struct TeleportConfirmPacket {
+    pub teleport_id: i32
+}
+
+/// A player moving without turning.
+#[derive(Debug)]
+pub struct PlayerPositionPacket {
+    /// Where their feet are.
+    pub position: (f64, f64, f64),
+    pub on_ground: bool
+}
+
+/// A player moving and turning.
+#[derive(Debug)]
+pub struct PlayerPositionAndRotationPacket {
+    pub position: (f64, f64, f64),
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool
+}
+
+/// A player turning without moving.
+#[derive(Debug)]
+pub struct PlayerRotationPacket {
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool
+}
+
+/// A player standing still, sent now and then and whenever they land or leave the ground.
+#[derive(Debug)]
+pub struct PlayerMovementPacket {
+    pub on_ground: bool
+}
+
+/// Puts the player somewhere, which the client confirms with the same teleport id.
+#[derive(Debug, Clone)]
+pub struct PlayerPositionAndLookPacket {
+    pub position: (f64, f64, f64),
+    pub yaw: f32,
+    pub pitch: f32,
+    pub teleport_id: i32
+}
+
+/// Something a player started or stopped doing that others can see.
+#[derive(Debug)]
+pub struct EntityActionPacket {
+    pub eid: i32,
+    /// 0 and 1 start and stop sneaking, 3 and 4 start and stop sprinting; the rest are left alone.
+    pub action: i32,
+    pub jump_boost: i32
+}
+
+/// Adds a player to the client's player list, which it needs before it can show them, changes
+/// their entry or takes one off.
+#[derive(Debug, Clone)]
+pub enum PlayerInfoPacket {
+    AddPlayer {
+        uuid: Uuid,
+        name: String,
+        game_mode: GameMode
+    },
+    UpdateGameMode {
+        uuid: Uuid,
+        game_mode: GameMode
+    },
+    RemovePlayer {
+        uuid: Uuid
+    }
+}
+
+/// Shows an entity other than a player or mob.
+#[derive(Debug, Clone)]
+pub struct SpawnEntityPacket {
+    pub eid: i32,
+    pub uuid: Uuid,
+    pub type_id: i32,
+    pub position: (f64, f64, f64),
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Means something different for each kind of entity.
+    pub data: i32,
+    /// In blocks per tick.
+    pub velocity: (f64, f64, f64)
+}
+
+/// Shows a player, who must be on the client's player list already.
+#[derive(Debug, Clone)]
+pub struct SpawnPlayerPacket {
+    pub eid: i32,
+    pub uuid: Uuid,
+    pub position: (f64, f64, f64),
+    pub yaw: f32,
+    pub pitch: f32
+}
+
+/// Moves an entity by less than eight blocks along each axis, in 4096ths of a block.
+#[derive(Debug, Clone)]
+pub struct EntityPositionPacket {
+    pub eid: i32,
+    pub delta: (i16, i16, i16),
+    pub on_ground: bool
+}
+
+#[derive(Debug, Clone)]
+pub struct EntityPositionAndRotationPacket {
+    pub eid: i32,
+    pub delta: (i16, i16, i16),
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool
+}
+
+#[derive(Debug, Clone)]
+pub struct EntityRotationPacket {
+    pub eid: i32,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool
+}
+
+/// Turns an entity's head, which its body follows.
+#[derive(Debug, Clone)]
+pub struct EntityHeadLookPacket {
+    pub eid: i32,
+    pub head_yaw: f32
+}
+
+/// Moves an entity any distance.
+#[derive(Debug, Clone)]
+pub struct EntityTeleportPacket {
+    pub eid: i32,
+    pub position: (f64, f64, f64),
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool
+}
+
+#[derive(Debug, Clone)]
+pub struct DestroyEntityPacket {
+    pub eid: i32
+}
+
+/// Changes some of an entity's metadata, by index.
+#[derive(Debug, Clone)]
+pub struct EntityMetadataPacket {
+    pub eid: i32,
+    pub metadata: Metadata
+}
+
+/// Shows an entity doing something, like a player swinging their arm.
+#[derive(Debug, Clone)]
+pub struct EntityAnimationPacket {
+    pub eid: i32,
+    /// 0 swings the main arm and 3 the off hand.
+    pub animation: u8
+}
+
+/// Tells the client a single block changed.
+#[derive(Debug, Clone)]
+pub struct BlockChangePacket {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+    pub block: Block
+}
+
+/// Tells the client several blocks of one section changed.
+#[derive(Debug, Clone)]
+pub struct MultiBlockChangePacket {
+    pub section: Vector3I,
+    pub blocks: SectionChanges
+}
+
+/// Tells the client about a block entity's new data.
+#[derive(Debug, Clone)]
+pub struct BlockEntityDataPacket {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+    /// What kind of update it is, from `BlockEntity::update_action`.
+    pub action: u8,
+    pub entity: BlockEntity
+}
+
+/// The light of a column, sent before its `ChunkDataPacket`. The masks have a bit for each
+/// section from the one below the world to the one above it; sections in the `empty` masks are
+/// all dark, and the arrays are there for the rest of the set bits, bottom first.
+#[derive(Debug, Clone)]
+pub struct UpdateLightPacket {
+    pub x: i32,
+    pub z: i32,
+    pub sky_mask: Vec<i64>,
+    pub block_mask: Vec<i64>,
+    pub empty_sky_mask: Vec<i64>,
+    pub empty_block_mask: Vec<i64>,
+    pub sky: Vec<LightArray>,
+    pub block: Vec<LightArray>
+}
+
+/// Tells the client which column the player is in, so it keeps the chunks around it.
+#[derive(Debug, Clone)]
+pub struct UpdateViewPositionPacket {
+    pub x: i32,
+    pub z: i32
+}
+
+/// Tells the client to forget a column that's gone out of view.
+#[derive(Debug, Clone)]
+pub struct UnloadChunkPacket {
+    pub x: i32,
+    pub z: i32
+}
+
+/// Moves the player into another world, or respawns them in the same one.
+#[derive(Debug, Clone)]
+pub struct RespawnPacket {
+    pub dimension_type: String,
+    pub world_name: String,
+    pub hashed_seed: i64,
     pub game_mode: GameMode
 }
 
-#[derive(Serialize)]
-pub struct DimType<'str> {
-    pub piglin_safe: bool,
-    pub natural: bool,
-    pub ambient_light: f32,
-    pub infiniburn: &'str str,
-    pub respawn_anchor_works: bool,
-    pub has_skylight: bool,
-    pub bed_works: bool,
-    pub effects: &'str str,
-    pub has_raids: bool,
-    pub min_y: i32,
-    pub height: i32,
-    pub logical_height: i32,
-    pub coordinate_scale: f64,
-    pub ultrawarm: bool,
-    pub has_ceiling: bool
-}
-
-#[derive(Serialize)]
-pub struct BiomeMoodSound<'str> {
-    pub sound: &'str str,
-    pub tick_delay: i32,
-    pub offset: f64,
-    pub block_search_extent: i32
-}
-
-#[derive(Serialize)]
-pub struct BiomeAdditionsSound<'str> {
-    pub sound: &'str str,
-    pub tick_chance: f64
-}
-
-#[derive(Serialize)]
-pub struct BiomeMusic<'str> {
-    pub replace_current_music: bool,
-    pub sound: &'str str,
-    pub max_delay: i32,
-    pub min_delay: i32
-}
-
-#[derive(Serialize)]
-pub struct BiomeParticleOptions<'str> {
-    #[serde(rename = "type")]
-    pub particle_type: &'str str
-}
-
-#[derive(Serialize)]
-pub struct BiomeParticles<'str> {
-    pub probability: f32,
-    pub options: BiomeParticleOptions<'str>
-}
-
-#[derive(Serialize)]
-pub struct BiomeEffects<'str> {
-    pub sky_color: i32,
-    pub water_fog_color: i32,
-    pub fog_color: i32,
-    pub water_color: i32,
-    pub foliage_color: Option<i32>,
-    pub grass_color: Option<i32>,
-    pub grass_color_modifier: Option<&'str str>,
-    pub music: Option<BiomeMusic<'str>>,
-    pub ambient_sound: Option<&'str str>,
-    pub additions_sound: Option<BiomeAdditionsSound<'str>>,
-    pub mood_sound: Option<BiomeMoodSound<'str>>,
-}
-
-#[derive(Serialize)]
-pub struct Biome<'str> {
-    pub precipitation: &'str str,
-    pub depth: f32,
-    pub temperature: f32,
-    pub scale: f32,
-    pub downfall: f32,
-    pub category: &'str str,
-    pub temperature_modifier: Option<&'str str>,
-    pub particle: Option<BiomeParticles<'str>>,
-    pub effects: BiomeEffects<'str>
-}
-
-impl<'a> DimType<'a> {
-    pub const OVERWORLD: DimType<'a> = DimType::<'a> {
-        piglin_safe: false,
-        natural: true,
-        ambient_light: 0.0,
-        infiniburn: "minecraft:infiniburn_overworld",
-        respawn_anchor_works: false,
-        has_skylight: true,
-        bed_works: true,
-        effects: "minecraft:overworld",
-        has_raids: true,
-        min_y: 0,
-        height: 256,
-        logical_height: 256,
-        coordinate_scale: 1.0,
-        ultrawarm: false,
-        has_ceiling: false
-    };
+/// A chat message from the server.
+#[derive(Debug, Clone)]
+pub struct ChatMessagePacket {
+    /// The message as chat component JSON.
+    pub json: String,
+    /// 0 for chat, 1 for system messages and 2 above the hotbar.
+    pub position: u8,
+    pub sender: Uuid
 }
 
-impl<'str> Biome<'str> {
-    pub const OCEAN: Biome<'str> = Biome::<'str> {
-        precipitation: "rain",
-        depth: -1.0,
-        temperature: 0.5,
-        scale: 0.1,
-        downfall: 0.5,
-        category: "ocean",
-        temperature_modifier: None,
-        particle: None,
-        effects: BiomeEffects {
-            sky_color: 8103167,
-            water_fog_color: 329011,
-            fog_color: 12638463,
-            water_color: 4159204,
-            foliage_color: None,
-            grass_color: None,
-            grass_color_modifier: None,
-            music: None,
-            ambient_sound: None,
-            additions_sound: None,
-            mood_sound: Some(BiomeMoodSound {
-                sound: "minecraft:ambient.cave",
-                tick_delay: 6000,
-                offset: 2.0,
-                block_search_extent: 8
-            })
+impl ChatMessagePacket {
+    pub fn system(text: &str) -> ChatMessagePacket {
+        ChatMessagePacket {
+            json: serde_json::json!({ "text": text }).to_string(),
+            position: 1,
+            sender: Uuid::nil()
         }
-    };
+    }
+}
+
+#[derive(Debug)]
+pub struct ChatPacket {
+    pub message: String
+}
+
+/// Everything in a window, by slot.
+#[derive(Debug, Clone)]
+pub struct WindowItemsPacket {
+    pub window_id: u8,
+    pub slots: Vec<Slot>
+}
+
+/// Changes one slot of a window, or what's on the player's cursor.
+#[derive(Debug, Clone)]
+pub struct SetSlotPacket {
+    /// -1 with slot -1 for the cursor.
+    pub window_id: i8,
+    pub slot: i16,
+    pub stack: Slot
+}
+
+/// A player clicking in a window, with what they think it changed.
+#[derive(Debug)]
+pub struct ClickWindowPacket {
+    pub window_id: u8,
+    /// `inventory::OUTSIDE` for clicks outside the window.
+    pub slot: i16,
+    pub button: i8,
+    /// 0 for a plain click, 1 shift, 2 a number key, 3 middle, 4 drop, 5 drag and 6 double-click.
+    pub mode: i32,
+    pub changed: Vec<(i16, Slot)>,
+    pub cursor: Slot
+}
+
+/// Opens a window on top of the player's own.
+#[derive(Debug, Clone)]
+pub struct OpenWindowPacket {
+    pub window_id: u8,
+    /// What vanilla calls the kind of window, from `WindowType::menu`.
+    pub menu: i32,
+    /// Chat component JSON.
+    pub title: String
+}
+
+/// A window closing: players send it, as 0x09, when they close one, their own included, and are
+/// sent it when one closes for them.
+#[derive(Debug, Clone)]
+pub struct CloseWindowPacket {
+    pub window_id: u8
+}
+
+/// One of the numbers a window shows besides its slots, like how far a furnace's cooking is.
+#[derive(Debug, Clone)]
+pub struct WindowPropertyPacket {
+    pub window_id: u8,
+    pub property: i16,
+    pub value: i16
+}
+
+/// Which hotbar slot the player holds.
+#[derive(Debug, Clone)]
+pub struct HeldItemChangePacket {
+    pub slot: u8
+}
+
+/// A player picking another hotbar slot.
+#[derive(Debug)]
+pub struct SelectSlotPacket {
+    pub slot: i16
+}
+
+/// A creative player setting a slot of their inventory to anything they like, or throwing it
+/// out with slot -1.
+#[derive(Debug)]
+pub struct CreativeInventoryActionPacket {
+    pub slot: i16,
+    pub stack: Slot
+}
+
+/// What the player can do as far as their client is concerned, from their game mode.
+#[derive(Debug, Clone)]
+pub struct PlayerAbilitiesPacket {
+    pub invulnerable: bool,
+    pub flying: bool,
+    pub allow_flying: bool,
+    /// Whether blocks break at once, and placing takes nothing from the player's hand.
+    pub instant_build: bool,
+    pub flying_speed: f32,
+    /// How much the field of view widens with walking speed.
+    pub fov_modifier: f32
+}
+
+/// A player starting or stopping flying.
+#[derive(Debug)]
+pub struct ToggleFlyingPacket {
+    pub flying: bool
+}
+
+/// Tells the client about a change to the game around it, like its game mode.
+#[derive(Debug, Clone)]
+pub struct ChangeGameStatePacket {
+    pub reason: u8,
+    pub value: f32
 }
 
 impl Packet for JoinGamePacket {
@@ -155,41 +475,26 @@ impl Packet for JoinGamePacket {
     fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
         output.write_i32(self.eid)?;
         output.write_u8(0)?;
-        output.write_u8(match self.game_mode {
-            GameMode::Survival => 0
-        })?;
+        output.write_u8(self.game_mode.id())?;
         output.write_i8(-1)?;
-        output.write_var_int(1)?;
-        output.write_string("minecraft:overworld".to_string())?;
-
-        let mut dim_types = Registry::<DimType> {
-            name: "minecraft:dimension_type",
-            entries: vec![]
-        };
-
-        dim_types.register("minecraft:overworld", DimType::OVERWORLD);
-
-        let mut biomes = Registry::<Biome> {
-            name: "minecraft:worldgen/biome",
-            entries: vec![]
-        };
-
-        biomes.register("minecraft:ocean", Biome::OCEAN);
+        output.write_var_int(self.world_names.len() as i32)?;
+        for name in &self.world_names {
+            output.write_string(name.clone())?;
+        }
 
         let mut blob = Blob::new();
-        blob.insert("minecraft:dimension_type", dim_types.encode().compound());
-        blob.insert("minecraft:worldgen/biome", biomes.encode().compound());
-        let bytes_vec = blob.bytes().unwrap();
-        println!("{:#?}", blob);
-
-        output.write(bytes_vec.as_slice())?;
-        std::fs::write("debug.nbt", bytes_vec.as_slice());
-        output.write(nbt::encode(&DimType::OVERWORLD).unwrap().bytes().unwrap().as_slice())?;
+        blob.insert("minecraft:dimension_type", dimension::registry().encode().compound());
+        blob.insert("minecraft:worldgen/biome", biome::registry().encode().compound());
+        let bytes = blob.bytes()
+            .map_err(|e| Error::IoError(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}", e))))?;
+        output.write(bytes.as_slice())?;
+        let dim_type = dimension::get(&self.dimension_type).expect("World has an unregistered dimension type");
+        output.write(nbt::encode(dim_type).unwrap().bytes().unwrap().as_slice())?;
 
-        output.write_string("minecraft:overworld".to_string())?;
-        output.write_u64(0)?;
+        output.write_string(self.world_name.clone())?;
+        output.write_u64(self.hashed_seed as u64)?;
         output.write_var_int(0)?;
-        output.write_var_int(4)?;
+        output.write_var_int(VIEW_DISTANCE)?;
         output.write_u8(0)?;
         output.write_u8(1)?;
         output.write_u8(0)?;
@@ -202,3 +507,1575 @@ impl Packet for JoinGamePacket {
         Ok(())
     }
 }
+
+impl Packet for RespawnPacket {
+    fn id(&self) -> u32 { 0x3D }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        let dim_type = dimension::get(&self.dimension_type).expect("World has an unregistered dimension type");
+        output.write(nbt::encode(dim_type).unwrap().bytes().unwrap().as_slice())?;
+        output.write_string(self.world_name.clone())?;
+        output.write_u64(self.hashed_seed as u64)?;
+        output.write_u8(self.game_mode.id())?;
+        output.write_i8(-1)?;
+        output.write_u8(0)?; // debug world
+        output.write_u8(0)?; // flat world
+        output.write_u8(0)?; // keep metadata
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for ChatMessagePacket {
+    fn id(&self) -> u32 { 0x0F }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_string(self.json.clone())?;
+        output.write_u8(self.position)?;
+        output.write_u128(self.sender.as_u128())?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for ChatPacket {
+    fn id(&self) -> u32 { 0x03 }
+
+    fn read(input: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Ok(Self {
+            message: input.read_string(256)?
+        })
+    }
+
+    fn write(&self, _: &mut dyn Writable) -> Result<(), Error> {
+        Err(Refusal)
+    }
+
+    fn act(&self, client: &mut Client) -> Result<(), Error> {
+        match self.message.strip_prefix('/') {
+            Some(command) => command::execute_as(client, command),
+            None => {
+                log::info!("<{}> {}", client.username.clone().unwrap_or_default(), self.message);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Whether `pos` is within `reach` (squared) of the middle of the block at `x`, `y`, `z`, looking
+/// from `eye` above it.
+fn within_reach(pos: (f64, f64, f64), eye: f64, x: i64, y: i64, z: i64, reach: f64) -> bool {
+    let (dx, dy, dz) = (pos.0 - (x as f64 + 0.5), pos.1 + eye - (y as f64 + 0.5), pos.2 - (z as f64 + 0.5));
+    dx * dx + dy * dy + dz * dz <= reach
+}
+
+/// The fewest ticks a player holding `held` can take to dig `block`, like vanilla: the right tool
+/// speeds it up, and blocks that want a pickaxe take over three times as long without one. As in
+/// vanilla, finishing once 70% of the way there is let through, for lag.
+fn min_dig_ticks(block: Block, held: Option<&ItemStack>) -> u64 {
+    let hardness = match block.hardness() {
+        Some(hardness) if hardness > 0.0 => hardness,
+        _ => return 0
+    };
+    let wanted = block.tool();
+    let speed = match held.and_then(ItemStack::tool) {
+        Some((kind, speed)) if Some(kind) == wanted => Some(speed),
+        _ => None
+    };
+    let per_tick = match (speed, wanted) {
+        (Some(speed), _) => speed / hardness / 30.0,
+        (None, Some(ToolKind::Pickaxe)) => 1.0 / hardness / 100.0,
+        (None, _) => 1.0 / hardness / 30.0
+    };
+    (0.7 / per_tick).floor() as u64
+}
+
+/// Breaks the block at `x`, `y`, `z`, spilling out what was in it if it's a container.
+fn break_block(world: &mut World, x: i64, y: i64, z: i64) -> Result<(), WorldError> {
+    let contents = world.block_entity(x, y, z)?.and_then(BlockEntity::slots).unwrap_or_default();
+    for stack in contents.into_iter().flatten() {
+        let spread = || rand::random::<f64>() * 0.2 - 0.1;
+        let position = (x as f64 + 0.5, y as f64 + 0.5, z as f64 + 0.5);
+        world.entities_mut().add(Entity::item(position, (spread(), 0.2, spread()), stack));
+    }
+    world.put(x, y, z, Block::Air)
+}
+
+/// Whether a player standing at `pos` is partly inside the block at `x`, `y`, `z`.
+fn inside_player(pos: (f64, f64, f64), x: i64, y: i64, z: i64) -> bool {
+    let (low, high) = PlayerEntity::bounding_box(pos);
+    high.0 > x as f64 && low.0 < x as f64 + 1.0
+        && high.1 > y as f64 && low.1 < y as f64 + 1.0
+        && high.2 > z as f64 && low.2 < z as f64 + 1.0
+}
+
+/// Whether a player can go from `from` to `to` between two movement packets: not too far, and
+/// not through any block they weren't already stuck in unless they `no_clip`. Like the client,
+/// they're taken to move up or down first, then along whichever of X and Z they move further on.
+/// Moving where the columns aren't loaded yet isn't allowed, so this never waits on the disk.
+fn can_move(world: &mut World, from: (f64, f64, f64), to: (f64, f64, f64), no_clip: bool) -> Result<bool, WorldError> {
+    let (dx, dy, dz) = (to.0 - from.0, to.1 - from.1, to.2 - from.2);
+    if !(to.0.is_finite() && to.1.is_finite() && to.2.is_finite())
+        || dx * dx + dy * dy + dz * dz > MAX_MOVE_SQUARED {
+        return Ok(false);
+    }
+    if no_clip {
+        return Ok(true);
+    }
+    // a block either side covers the player's width
+    let column = |v: f64| (v.floor() as i64).div_euclid(16) as i32;
+    let (x_range, z_range) = ((column(from.0.min(to.0) - 1.0), column(from.0.max(to.0) + 1.0)),
+                              (column(from.2.min(to.2) - 1.0), column(from.2.max(to.2) + 1.0)));
+    if !(x_range.0..=x_range.1).all(|x| (z_range.0..=z_range.1).all(|z| world.is_column_loaded(x, z))) {
+        return Ok(false);
+    }
+
+    let stuck_in = PlayerEntity::colliding_blocks(world, from)?;
+    let vertical = (from.0, to.1, from.2);
+    let corner = if dx.abs() < dz.abs() { (from.0, to.1, to.2) } else { (to.0, to.1, from.2) };
+    for (start, end) in [(from, vertical), (vertical, corner), (corner, to)] {
+        let length = ((end.0 - start.0).powi(2) + (end.1 - start.1).powi(2) + (end.2 - start.2).powi(2)).sqrt();
+        let steps = (length / MOVE_STEP).ceil() as u32;
+        for step in 1..=steps {
+            let t = step as f64 / steps as f64;
+            let at = (start.0 + (end.0 - start.0) * t, start.1 + (end.1 - start.1) * t, start.2 + (end.2 - start.2) * t);
+            if PlayerEntity::colliding_blocks(world, at)?.iter().any(|block| !stuck_in.contains(block)) {
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Updates where the player is and which way they're looking from one of the movement packets,
+/// sending them back where they were if they can't have gone where they say.
+fn move_player(client: &mut Client, position: Option<(f64, f64, f64)>, rotation: Option<(f32, f32)>,
+               on_ground: bool) -> Result<(), Error> {
+    // movement from before a teleport the client hasn't caught up with yet
+    if client.awaiting_teleport.is_some() {
+        return Ok(());
+    }
+    if let Some((yaw, pitch)) = rotation {
+        client.player.yaw = yaw;
+        client.player.pitch = pitch;
+    }
+    client.player.on_ground = on_ground;
+
+    let (to, mut world) = match (position, client.current_world()) {
+        (Some(to), Some(world)) => (to, world),
+        (_, world) => {
+            drop(world);
+            client.update_entity();
+            return Ok(());
+        }
+    };
+    let from = client.player.position;
+    let allowed = match can_move(&mut world, from, to, client.game_mode.no_clip()) {
+        Ok(allowed) => allowed,
+        Err(e) => {
+            log::error!("Could not check {}'s move in {}: {}", client.username.as_deref().unwrap_or("?"), world.name(), e);
+            false
+        }
+    };
+    drop(world);
+    if allowed {
+        client.player.position = to;
+        client.update_entity();
+        client.update_view()
+    } else {
+        client.teleport(from)
+    }
+}
+
+impl PlayerDiggingPacket {
+    /// Does what the player asked for, giving back whether it was allowed.
+    fn dig(&self, client: &mut Client, world: &mut World) -> Result<bool, WorldError> {
+        let (x, y, z) = (self.x, self.y, self.z);
+        match self.status {
+            DiggingStatus::Started => {
+                let block = world.get(x, y, z)?;
+                let instant = client.game_mode.instant_build();
+                let breakable = block.is_breakable() || (instant && block == Block::Bedrock);
+                if !world.contains_y(y) || !client.game_mode.can_build() || !breakable
+                    || !within_reach(client.player.position, EYE_HEIGHT, x, y, z, DIG_REACH_SQUARED) {
+                    return Ok(false);
+                }
+                if instant || block.breaks_instantly() {
+                    break_block(world, x, y, z)?;
+                } else {
+                    client.digging = Some(((x, y, z), Server::global().current_tick()));
+                }
+                Ok(true)
+            }
+            DiggingStatus::Cancelled => {
+                client.digging = None;
+                Ok(true)
+            }
+            DiggingStatus::Finished => {
+                let started = match client.digging.take() {
+                    Some((at, started)) if at == (x, y, z) => started,
+                    _ => return Ok(false)
+                };
+                let block = world.get(x, y, z)?;
+                let took = Server::global().current_tick().saturating_sub(started);
+                if !block.is_breakable() || took < min_dig_ticks(block, client.inventory.held())
+                    || !within_reach(client.player.position, EYE_HEIGHT, x, y, z, DIG_REACH_SQUARED) {
+                    return Ok(false);
+                }
+                break_block(world, x, y, z)?;
+                Ok(true)
+            }
+            DiggingStatus::DropStack | DiggingStatus::DropItem => {
+                if client.game_mode == GameMode::Spectator {
+                    return Ok(false);
+                }
+                let count = if self.status == DiggingStatus::DropStack { i8::MAX } else { 1 };
+                if let Some(stack) = client.inventory.take_held(count) {
+                    client.drop_item(world, stack);
+                }
+                Ok(true)
+            }
+            DiggingStatus::SwapHands => {
+                if client.game_mode == GameMode::Spectator {
+                    return Ok(false);
+                }
+                let held = client.inventory.held_slot();
+                client.inventory.slots.swap(held, inventory::OFFHAND);
+                Ok(true)
+            }
+            // nothing can be used yet
+            DiggingStatus::ReleaseUseItem => Ok(true)
+        }
+    }
+}
+
+impl PlayerBlockPlacementPacket {
+    /// Places the player's block if they're allowed to, giving back where it went or would have.
+    fn place(&self, client: &mut Client, world: &mut World) -> Result<(i64, i64, i64), WorldError> {
+        let (x, y, z) = (self.x, self.y, self.z);
+        let target = if world.get(x, y, z)?.is_replaceable() {
+            (x, y, z)
+        } else {
+            match self.face {
+                0 => (x, y - 1, z),
+                1 => (x, y + 1, z),
+                2 => (x, y, z - 1),
+                3 => (x, y, z + 1),
+                4 => (x - 1, y, z),
+                _ => (x + 1, y, z)
+            }
+        };
+        if !client.game_mode.can_build() || !within_reach(client.player.position, 0.0, x, y, z, PLACE_REACH_SQUARED) {
+            return Ok(target);
+        }
+
+        let (tx, ty, tz) = target;
+        let slot = self.slot(client);
+        let block = match client.inventory.slots[slot].as_ref().and_then(|stack| stack.name()).and_then(Block::from_name) {
+            Some(block) if block != Block::Air => block,
+            _ => return Ok(target)
+        };
+        if world.contains_y(ty) && world.get(tx, ty, tz)?.is_replaceable()
+            && !(block.blocks_motion() && inside_player(client.player.position, tx, ty, tz)) {
+            world.put(tx, ty, tz, block)?;
+            if !client.game_mode.instant_build() {
+                client.inventory.take_from(slot, 1);
+            }
+        }
+        Ok(target)
+    }
+
+    /// The window the player gets from using the clicked block, unless they're sneaking with
+    /// something in hand, which places it instead.
+    fn window(&self, client: &Client, world: &mut World) -> Option<WindowType> {
+        let (x, y, z) = (self.x, self.y, self.z);
+        if client.game_mode == GameMode::Spectator || !within_reach(client.player.position, 0.0, x, y, z, PLACE_REACH_SQUARED) {
+            return None;
+        }
+        let sneaking = world.entities().get(client.entity_id).is_some_and(|e| e.flags() & FLAG_CROUCHING != 0);
+        let holding = client.inventory.held().is_some() || client.inventory.slots[inventory::OFFHAND].is_some();
+        if sneaking && holding {
+            return None;
+        }
+        match world.get(x, y, z).ok()? {
+            Block::CraftingTable => Some(WindowType::Crafting),
+            _ => world.block_entity(x, y, z).ok()??.window_type()
+        }
+    }
+
+    /// The slot of the player's window holding what's in the hand they used.
+    fn slot(&self, client: &Client) -> usize {
+        if self.hand == 0 { client.inventory.held_slot() } else { inventory::OFFHAND }
+    }
+}
+
+impl ChunkDataPacket {
+    /// Builds the column at `x`, `z`, loading whatever of it isn't loaded yet.
+    pub fn new(world: &mut World, x: i32, z: i32) -> Result<ChunkDataPacket, WorldError> {
+        let sections = world.load_column(x, z)?;
+
+        let mut bitmask = vec![0i64; sections.len().div_ceil(64)];
+        let mut biomes = Vec::with_capacity(sections.len() * 64);
+        let mut data = Buffer::new();
+        let mut block_entities = vec![];
+        for (i, section) in sections.iter().enumerate() {
+            biomes.extend(section.biomes.iter().map(|b| *b as i32));
+            block_entities.extend(section.block_entities().map(|((x, y, z), e)| (section.world_pos(*x, *y, *z), e.clone())));
+            if section.blocks.iter().all(|b| *b == Block::Air) {
+                continue;
+            }
+            bitmask[i / 64] |= 1 << (i % 64);
+            write_section(section, &mut data).expect("Writing to a buffer can't fail");
+        }
+        let heightmaps = world.column_heightmaps(x, z)?.clone();
+
+        Ok(ChunkDataPacket { x, z, bitmask, heightmaps, biomes, data: data.bytes, block_entities })
+    }
+}
+
+impl BlockEntityDataPacket {
+    /// The update for the block entity at `x`, `y`, `z`, if there is one there and the client
+    /// cares about its kind.
+    pub fn new(world: &mut World, x: i64, y: i64, z: i64) -> Result<Option<BlockEntityDataPacket>, WorldError> {
+        Ok(world.block_entity(x, y, z)?.and_then(|entity| {
+            entity.update_action().map(|action| BlockEntityDataPacket { x, y, z, action, entity: entity.clone() })
+        }))
+    }
+}
+
+/// Writes a block entity as the root compound of an NBT blob.
+fn write_block_entity(entity: &BlockEntity, x: i64, y: i64, z: i64, output: &mut dyn Writable) -> Result<(), Error> {
+    let mut blob = Blob::new();
+    blob.elements = entity.to_nbt(x, y, z);
+    output.write(blob.bytes().unwrap().as_slice())?;
+    Ok(())
+}
+
+impl UpdateLightPacket {
+    /// Builds the light of the column at `x`, `z`, loading and lighting it if needed.
+    pub fn new(world: &mut World, x: i32, z: i32) -> Result<UpdateLightPacket, WorldError> {
+        let sections = world.load_column(x, z)?;
+
+        // below the world is dark and above it is open sky
+        let dark = LightArray::empty();
+        let sky = LightArray::full();
+        let mut column = vec![(&dark, &dark)];
+        column.extend(sections.iter().map(|s| (s.light(LightKind::Sky), s.light(LightKind::Block))));
+        column.push((&sky, &dark));
+
+        let masks = column.len().div_ceil(64);
+        let mut packet = UpdateLightPacket {
+            x, z,
+            sky_mask: vec![0; masks],
+            block_mask: vec![0; masks],
+            empty_sky_mask: vec![0; masks],
+            empty_block_mask: vec![0; masks],
+            sky: vec![],
+            block: vec![]
+        };
+        for (i, (sky, block)) in column.into_iter().enumerate() {
+            let bit = 1i64 << (i % 64);
+            if sky.is_empty() {
+                packet.empty_sky_mask[i / 64] |= bit;
+            } else {
+                packet.sky_mask[i / 64] |= bit;
+                packet.sky.push(sky.clone());
+            }
+            if block.is_empty() {
+                packet.empty_block_mask[i / 64] |= bit;
+            } else {
+                packet.block_mask[i / 64] |= bit;
+                packet.block.push(block.clone());
+            }
+        }
+        Ok(packet)
+    }
+}
+
+/// Packs `values` into longs `bits` at a time, lowest bits first, without spreading any value over
+/// two longs; the layout 1.16 and later use everywhere.
+fn pack_longs(values: &[u64], bits: usize) -> Vec<i64> {
+    let per_long = 64 / bits;
+    let mut longs = vec![0i64; values.len().div_ceil(per_long)];
+    for (i, value) in values.iter().enumerate() {
+        longs[i / per_long] |= (value << ((i % per_long) * bits)) as i64;
+    }
+    longs
+}
+
+/// Bits of a block state id in the global palette, which sections fall back to when their own
+/// palette would need more than 8.
+const GLOBAL_PALETTE_BITS: usize = 15;
+
+fn write_section(chunk: &Chunk, output: &mut dyn Writable) -> Result<(), Error> {
+    let mut palette: Vec<Block> = vec![];
+    let mut indices = Vec::with_capacity(chunk.blocks.len());
+    for block in chunk.blocks.iter() {
+        let i = match palette.iter().position(|b| b == block) {
+            Some(i) => i,
+            None => {
+                palette.push(*block);
+                palette.len() - 1
+            }
+        };
+        indices.push(i as u64);
+    }
+
+    let non_air = chunk.blocks.iter().filter(|b| **b != Block::Air).count();
+    output.write_i16(non_air as i16)?;
+
+    let bits = (usize::BITS - palette.len().saturating_sub(1).leading_zeros()) as usize;
+    let longs = if bits <= 8 {
+        let bits = bits.max(4);
+        output.write_u8(bits as u8)?;
+        output.write_var_int(palette.len() as i32)?;
+        for block in &palette {
+            output.write_var_int(block.state_id() as i32)?;
+        }
+        pack_longs(&indices, bits)
+    } else {
+        output.write_u8(GLOBAL_PALETTE_BITS as u8)?;
+        let states: Vec<u64> = chunk.blocks.iter().map(|b| b.state_id() as u64).collect();
+        pack_longs(&states, GLOBAL_PALETTE_BITS)
+    };
+
+    output.write_var_int(longs.len() as i32)?;
+    for long in longs {
+        output.write_i64(long)?;
+    }
+    Ok(())
+}
+
+impl Packet for ChunkDataPacket {
+    fn id(&self) -> u32 { 0x22 }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_i32(self.x)?;
+        output.write_i32(self.z)?;
+        output.write_var_int(self.bitmask.len() as i32)?;
+        for long in &self.bitmask {
+            output.write_i64(*long)?;
+        }
+
+        let mut heightmaps = Blob::new();
+        for kind in HeightmapKind::ALL {
+            heightmaps.insert(kind.name(), Tag::LongArray(self.heightmaps.get(kind).longs().to_vec()));
+        }
+        output.write(heightmaps.bytes().unwrap().as_slice())?;
+
+        output.write_var_int(self.biomes.len() as i32)?;
+        for biome in &self.biomes {
+            output.write_var_int(*biome)?;
+        }
+        output.write_var_int(self.data.len() as i32)?;
+        output.write(&self.data)?;
+        output.write_var_int(self.block_entities.len() as i32)?;
+        for ((x, y, z), entity) in &self.block_entities {
+            write_block_entity(entity, *x, *y, *z, output)?;
+        }
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for PlayerDiggingPacket {
+    fn id(&self) -> u32 { 0x1A }
+
+    fn read(input: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        let status = match input.read_var_int()? {
+            0 => DiggingStatus::Started,
+            1 => DiggingStatus::Cancelled,
+            2 => DiggingStatus::Finished,
+            3 => DiggingStatus::DropStack,
+            4 => DiggingStatus::DropItem,
+            5 => DiggingStatus::ReleaseUseItem,
+            6 => DiggingStatus::SwapHands,
+            other => return Err(Error::IoError(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                format!("unknown digging status {}", other))))
+        };
+        let (x, y, z) = input.read_position()?;
+        Ok(Self { status, x, y, z, face: input.read_u8()? })
+    }
+
+    fn write(&self, _: &mut dyn Writable) -> Result<(), Error> {
+        Err(Refusal)
+    }
+
+    fn act(&self, client: &mut Client) -> Result<(), Error> {
+        let mut world = match client.current_world() {
+            Some(world) => world,
+            None => return Ok(())
+        };
+        let successful = match self.dig(client, &mut world) {
+            Ok(successful) => successful,
+            Err(e) => {
+                log::error!("Could not dig at [{},{},{}] in {}: {}", self.x, self.y, self.z, world.name(), e);
+                false
+            }
+        };
+        if let DiggingStatus::Started | DiggingStatus::Cancelled | DiggingStatus::Finished = self.status {
+            let block = world.get(self.x, self.y, self.z).unwrap_or(Block::Air);
+            client.write_packet(&AckPlayerDiggingPacket {
+                x: self.x, y: self.y, z: self.z,
+                block,
+                status: self.status,
+                successful
+            })?;
+        }
+        if let DiggingStatus::DropStack | DiggingStatus::DropItem | DiggingStatus::SwapHands = self.status {
+            client.send_slot(client.inventory.held_slot())?;
+        }
+        if self.status == DiggingStatus::SwapHands {
+            client.send_slot(inventory::OFFHAND)?;
+        }
+        Ok(())
+    }
+}
+
+impl Packet for AckPlayerDiggingPacket {
+    fn id(&self) -> u32 { 0x08 }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_position(self.x, self.y, self.z)?;
+        output.write_var_int(self.block.state_id() as i32)?;
+        output.write_var_int(self.status as i32)?;
+        output.write_u8(self.successful as u8)?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for PlayerBlockPlacementPacket {
+    fn id(&self) -> u32 { 0x2E }
+
+    fn read(input: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        let hand = input.read_var_int()?;
+        let (x, y, z) = input.read_position()?;
+        Ok(Self {
+            hand, x, y, z,
+            face: input.read_var_int()?,
+            cursor: (input.read_f32()?, input.read_f32()?, input.read_f32()?),
+            inside_block: input.read_u8()? != 0
+        })
+    }
+
+    fn write(&self, _: &mut dyn Writable) -> Result<(), Error> {
+        Err(Refusal)
+    }
+
+    fn act(&self, client: &mut Client) -> Result<(), Error> {
+        let mut world = match client.current_world() {
+            Some(world) => world,
+            None => return Ok(())
+        };
+        if let Some(kind) = self.window(client, &mut world) {
+            return client.open_window(&mut world, kind, self.x, self.y, self.z);
+        }
+        let target = match self.place(client, &mut world) {
+            Ok(target) => target,
+            Err(e) => {
+                log::error!("Could not place at [{},{},{}] in {}: {}", self.x, self.y, self.z, world.name(), e);
+                return Ok(());
+            }
+        };
+        // the client shows what it guessed would happen; tell it what did
+        for (x, y, z) in [(self.x, self.y, self.z), target] {
+            if let Ok(block) = world.get(x, y, z) {
+                client.write_packet(&BlockChangePacket { x, y, z, block })?;
+            }
+        }
+        client.send_slot(self.slot(client))
+    }
+}
+
+impl Packet for AnimationPacket {
+    fn id(&self) -> u32 { 0x2C }
+
+    fn read(input: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Ok(Self { hand: input.read_var_int()? })
+    }
+
+    fn write(&self, _: &mut dyn Writable) -> Result<(), Error> {
+        Err(Refusal)
+    }
+
+    fn act(&self, client: &mut Client) -> Result<(), Error> {
+        let (world, uuid) = match (client.current_world(), client.uuid) {
+            (Some(world), Some(uuid)) => (world, uuid),
+            _ => return Ok(())
+        };
+        let (x, z) = ((client.player.position.0 / 16.0).floor() as i32, (client.player.position.2 / 16.0).floor() as i32);
+        let others: Vec<_> = world.trackers(x, z).into_iter().filter(|p| *p != uuid).collect();
+        let animation = if self.hand == 0 { 0 } else { 3 };
+        Server::global().send_to(&others, &EntityAnimationPacket { eid: client.entity_id, animation });
+        Ok(())
+    }
+}
+
+impl Packet for EntityAnimationPacket {
+    fn id(&self) -> u32 { 0x06 }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_var_int(self.eid)?;
+        output.write_u8(self.animation)?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for TeleportConfirmPacket {
+    fn id(&self) -> u32 { 0x00 }
+
+    fn read(input: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Ok(Self { teleport_id: input.read_var_int()? })
+    }
+
+    fn write(&self, _: &mut dyn Writable) -> Result<(), Error> {
+        Err(Refusal)
+    }
+
+    fn act(&self, client: &mut Client) -> Result<(), Error> {
+        if client.awaiting_teleport == Some(self.teleport_id) {
+            client.awaiting_teleport = None;
+        }
+        Ok(())
+    }
+}
+
+impl Packet for PlayerPositionPacket {
+    fn id(&self) -> u32 { 0x11 }
+
+    fn read(input: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Ok(Self {
+            position: (input.read_f64()?, input.read_f64()?, input.read_f64()?),
+            on_ground: input.read_u8()? != 0
+        })
+    }
+
+    fn write(&self, _: &mut dyn Writable) -> Result<(), Error> {
+        Err(Refusal)
+    }
+
+    fn act(&self, client: &mut Client) -> Result<(), Error> {
+        move_player(client, Some(self.position), None, self.on_ground)
+    }
+}
+
+impl Packet for PlayerPositionAndRotationPacket {
+    fn id(&self) -> u32 { 0x12 }
+
+    fn read(input: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Ok(Self {
+            position: (input.read_f64()?, input.read_f64()?, input.read_f64()?),
+            yaw: input.read_f32()?,
+            pitch: input.read_f32()?,
+            on_ground: input.read_u8()? != 0
+        })
+    }
+
+    fn write(&self, _: &mut dyn Writable) -> Result<(), Error> {
+        Err(Refusal)
+    }
+
+    fn act(&self, client: &mut Client) -> Result<(), Error> {
+        move_player(client, Some(self.position), Some((self.yaw, self.pitch)), self.on_ground)
+    }
+}
+
+impl Packet for PlayerRotationPacket {
+    fn id(&self) -> u32 { 0x13 }
+
+    fn read(input: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Ok(Self { yaw: input.read_f32()?, pitch: input.read_f32()?, on_ground: input.read_u8()? != 0 })
+    }
+
+    fn write(&self, _: &mut dyn Writable) -> Result<(), Error> {
+        Err(Refusal)
+    }
+
+    fn act(&self, client: &mut Client) -> Result<(), Error> {
+        move_player(client, None, Some((self.yaw, self.pitch)), self.on_ground)
+    }
+}
+
+impl Packet for PlayerMovementPacket {
+    fn id(&self) -> u32 { 0x14 }
+
+    fn read(input: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Ok(Self { on_ground: input.read_u8()? != 0 })
+    }
+
+    fn write(&self, _: &mut dyn Writable) -> Result<(), Error> {
+        Err(Refusal)
+    }
+
+    fn act(&self, client: &mut Client) -> Result<(), Error> {
+        move_player(client, None, None, self.on_ground)
+    }
+}
+
+impl Packet for PlayerPositionAndLookPacket {
+    fn id(&self) -> u32 { 0x38 }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_f64(self.position.0)?;
+        output.write_f64(self.position.1)?;
+        output.write_f64(self.position.2)?;
+        output.write_f32(self.yaw)?;
+        output.write_f32(self.pitch)?;
+        output.write_u8(0)?; // nothing relative to where the client thinks it is
+        output.write_var_int(self.teleport_id)?;
+        output.write_u8(0)?; // don't dismount
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// An angle in degrees as the 256ths of a turn entity packets use.
+fn angle(degrees: f32) -> u8 {
+    (degrees.rem_euclid(360.0) / 360.0 * 256.0) as u8
+}
+
+fn write_metadata(metadata: &[(u8, MetadataValue)], output: &mut dyn Writable) -> Result<(), Error> {
+    for (index, value) in metadata {
+        output.write_u8(*index)?;
+        match value {
+            MetadataValue::Byte(v) => {
+                output.write_var_int(0)?;
+                output.write_i8(*v)?;
+            }
+            MetadataValue::VarInt(v) => {
+                output.write_var_int(1)?;
+                output.write_var_int(*v)?;
+            }
+            MetadataValue::Float(v) => {
+                output.write_var_int(2)?;
+                output.write_f32(*v)?;
+            }
+            MetadataValue::String(v) => {
+                output.write_var_int(3)?;
+                output.write_string(v.clone())?;
+            }
+            MetadataValue::Boolean(v) => {
+                output.write_var_int(7)?;
+                output.write_u8(*v as u8)?;
+            }
+            MetadataValue::Slot(v) => {
+                output.write_var_int(6)?;
+                output.write_slot(v.as_ref())?;
+            }
+            MetadataValue::Pose(v) => {
+                output.write_var_int(18)?;
+                output.write_var_int(*v as i32)?;
+            }
+        }
+    }
+    output.write_u8(0xFF)?;
+    Ok(())
+}
+
+impl SpawnEntityPacket {
+    pub fn new(entity: &Entity) -> SpawnEntityPacket {
+        SpawnEntityPacket {
+            eid: entity.id,
+            uuid: entity.uuid,
+            type_id: entity.kind.type_id(),
+            position: entity.position,
+            yaw: entity.yaw,
+            pitch: entity.pitch,
+            data: 0,
+            velocity: entity.velocity
+        }
+    }
+}
+
+impl SpawnPlayerPacket {
+    pub fn new(entity: &Entity) -> SpawnPlayerPacket {
+        SpawnPlayerPacket { eid: entity.id, uuid: entity.uuid, position: entity.position, yaw: entity.yaw, pitch: entity.pitch }
+    }
+}
+
+impl EntityPositionPacket {
+    /// How far it is from `from` to `to` in 4096ths of a block, or `None` if that's too far for a
+    /// relative move. Both ends are rounded the same way, so moves add up to where the entity is.
+    pub fn delta(from: (f64, f64, f64), to: (f64, f64, f64)) -> Option<(i16, i16, i16)> {
+        let axis = |a: f64, b: f64| {
+            let d = (b * 4096.0).round() as i64 - (a * 4096.0).round() as i64;
+            if (i16::MIN as i64..=i16::MAX as i64).contains(&d) { Some(d as i16) } else { None }
+        };
+        Some((axis(from.0, to.0)?, axis(from.1, to.1)?, axis(from.2, to.2)?))
+    }
+}
+
+impl Packet for EntityActionPacket {
+    fn id(&self) -> u32 { 0x1B }
+
+    fn read(input: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Ok(Self { eid: input.read_var_int()?, action: input.read_var_int()?, jump_boost: input.read_var_int()? })
+    }
+
+    fn write(&self, _: &mut dyn Writable) -> Result<(), Error> {
+        Err(Refusal)
+    }
+
+    fn act(&self, client: &mut Client) -> Result<(), Error> {
+        let mut world = match client.current_world() {
+            Some(world) => world,
+            None => return Ok(())
+        };
+        let flags = match world.entities().get(client.entity_id) {
+            Some(entity) => entity.flags(),
+            None => return Ok(())
+        };
+        let (flags, pose) = match self.action {
+            0 => (flags | FLAG_CROUCHING, Some(Pose::Crouching)),
+            1 => (flags & !FLAG_CROUCHING, Some(Pose::Standing)),
+            3 => (flags | FLAG_SPRINTING, None),
+            4 => (flags & !FLAG_SPRINTING, None),
+            _ => return Ok(())
+        };
+        let entities = world.entities_mut();
+        entities.set_metadata(client.entity_id, FLAGS_INDEX, MetadataValue::Byte(flags));
+        if let Some(pose) = pose {
+            entities.set_metadata(client.entity_id, POSE_INDEX, MetadataValue::Pose(pose));
+        }
+        Ok(())
+    }
+}
+
+impl PlayerAbilitiesPacket {
+    pub fn new(game_mode: GameMode, flying: bool) -> PlayerAbilitiesPacket {
+        PlayerAbilitiesPacket {
+            invulnerable: game_mode.invulnerable(),
+            flying,
+            allow_flying: game_mode.can_fly(),
+            instant_build: game_mode.instant_build(),
+            flying_speed: 0.05,
+            fov_modifier: 0.1
+        }
+    }
+}
+
+impl Packet for PlayerAbilitiesPacket {
+    fn id(&self) -> u32 { 0x32 }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        let flags = self.invulnerable as u8 | (self.flying as u8) << 1 | (self.allow_flying as u8) << 2
+            | (self.instant_build as u8) << 3;
+        output.write_u8(flags)?;
+        output.write_f32(self.flying_speed)?;
+        output.write_f32(self.fov_modifier)?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for ToggleFlyingPacket {
+    fn id(&self) -> u32 { 0x19 }
+
+    fn read(input: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Ok(Self { flying: input.read_u8()? & 0x02 != 0 })
+    }
+
+    fn write(&self, _: &mut dyn Writable) -> Result<(), Error> {
+        Err(Refusal)
+    }
+
+    fn act(&self, client: &mut Client) -> Result<(), Error> {
+        if self.flying && !client.game_mode.can_fly() {
+            // put the client back on its feet
+            client.write_packet(&PlayerAbilitiesPacket::new(client.game_mode, false))?;
+            return Ok(());
+        }
+        client.flying = self.flying;
+        Ok(())
+    }
+}
+
+impl ChangeGameStatePacket {
+    pub fn game_mode(game_mode: GameMode) -> ChangeGameStatePacket {
+        ChangeGameStatePacket { reason: 3, value: game_mode.id() as f32 }
+    }
+}
+
+impl Packet for ChangeGameStatePacket {
+    fn id(&self) -> u32 { 0x1E }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_u8(self.reason)?;
+        output.write_f32(self.value)?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for PlayerInfoPacket {
+    fn id(&self) -> u32 { 0x36 }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        match self {
+            PlayerInfoPacket::AddPlayer { uuid, name, game_mode } => {
+                output.write_var_int(0)?;
+                output.write_var_int(1)?;
+                output.write_u128(uuid.as_u128())?;
+                output.write_string(name.clone())?;
+                output.write_var_int(0)?; // no skin or cape
+                output.write_var_int(game_mode.id() as i32)?;
+                output.write_var_int(0)?; // ping
+                output.write_u8(0)?; // no display name
+            }
+            PlayerInfoPacket::UpdateGameMode { uuid, game_mode } => {
+                output.write_var_int(1)?;
+                output.write_var_int(1)?;
+                output.write_u128(uuid.as_u128())?;
+                output.write_var_int(game_mode.id() as i32)?;
+            }
+            PlayerInfoPacket::RemovePlayer { uuid } => {
+                output.write_var_int(4)?;
+                output.write_var_int(1)?;
+                output.write_u128(uuid.as_u128())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for SpawnEntityPacket {
+    fn id(&self) -> u32 { 0x00 }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_var_int(self.eid)?;
+        output.write_u128(self.uuid.as_u128())?;
+        output.write_var_int(self.type_id)?;
+        output.write_f64(self.position.0)?;
+        output.write_f64(self.position.1)?;
+        output.write_f64(self.position.2)?;
+        output.write_u8(angle(self.pitch))?;
+        output.write_u8(angle(self.yaw))?;
+        output.write_i32(self.data)?;
+        // in 8000ths of a block per tick, up to vanilla's limit
+        for v in [self.velocity.0, self.velocity.1, self.velocity.2] {
+            output.write_i16((v.clamp(-3.9, 3.9) * 8000.0) as i16)?;
+        }
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for SpawnPlayerPacket {
+    fn id(&self) -> u32 { 0x04 }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_var_int(self.eid)?;
+        output.write_u128(self.uuid.as_u128())?;
+        output.write_f64(self.position.0)?;
+        output.write_f64(self.position.1)?;
+        output.write_f64(self.position.2)?;
+        output.write_u8(angle(self.yaw))?;
+        output.write_u8(angle(self.pitch))?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for EntityPositionPacket {
+    fn id(&self) -> u32 { 0x29 }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_var_int(self.eid)?;
+        output.write_i16(self.delta.0)?;
+        output.write_i16(self.delta.1)?;
+        output.write_i16(self.delta.2)?;
+        output.write_u8(self.on_ground as u8)?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for EntityPositionAndRotationPacket {
+    fn id(&self) -> u32 { 0x2A }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_var_int(self.eid)?;
+        output.write_i16(self.delta.0)?;
+        output.write_i16(self.delta.1)?;
+        output.write_i16(self.delta.2)?;
+        output.write_u8(angle(self.yaw))?;
+        output.write_u8(angle(self.pitch))?;
+        output.write_u8(self.on_ground as u8)?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for EntityRotationPacket {
+    fn id(&self) -> u32 { 0x2B }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_var_int(self.eid)?;
+        output.write_u8(angle(self.yaw))?;
+        output.write_u8(angle(self.pitch))?;
+        output.write_u8(self.on_ground as u8)?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for EntityHeadLookPacket {
+    fn id(&self) -> u32 { 0x3E }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_var_int(self.eid)?;
+        output.write_u8(angle(self.head_yaw))?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for EntityTeleportPacket {
+    fn id(&self) -> u32 { 0x61 }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_var_int(self.eid)?;
+        output.write_f64(self.position.0)?;
+        output.write_f64(self.position.1)?;
+        output.write_f64(self.position.2)?;
+        output.write_u8(angle(self.yaw))?;
+        output.write_u8(angle(self.pitch))?;
+        output.write_u8(self.on_ground as u8)?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for DestroyEntityPacket {
+    fn id(&self) -> u32 { 0x3A }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_var_int(self.eid)?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for EntityMetadataPacket {
+    fn id(&self) -> u32 { 0x4D }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_var_int(self.eid)?;
+        write_metadata(&self.metadata, output)
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for BlockChangePacket {
+    fn id(&self) -> u32 { 0x0C }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_position(self.x, self.y, self.z)?;
+        output.write_var_int(self.block.state_id() as i32)?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for MultiBlockChangePacket {
+    fn id(&self) -> u32 { 0x3F }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        let Vector3I(x, y, z) = self.section;
+        output.write_i64((x & 0x3FFFFF) << 42 | (z & 0x3FFFFF) << 20 | (y & 0xFFFFF))?;
+        output.write_u8(0)?; // let the client work out the light
+        output.write_var_int(self.blocks.len() as i32)?;
+        for ((x, y, z), block) in &self.blocks {
+            let pos = (*x as i64) << 8 | (*z as i64) << 4 | *y as i64;
+            output.write_var_long((block.state_id() as i64) << 12 | pos)?;
+        }
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for BlockEntityDataPacket {
+    fn id(&self) -> u32 { 0x0A }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_position(self.x, self.y, self.z)?;
+        output.write_u8(self.action)?;
+        write_block_entity(&self.entity, self.x, self.y, self.z, output)
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for UpdateLightPacket {
+    fn id(&self) -> u32 { 0x25 }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_var_int(self.x)?;
+        output.write_var_int(self.z)?;
+        output.write_u8(1)?; // trust edges
+        for mask in [&self.sky_mask, &self.block_mask, &self.empty_sky_mask, &self.empty_block_mask] {
+            output.write_var_int(mask.len() as i32)?;
+            for long in mask {
+                output.write_i64(*long)?;
+            }
+        }
+        for arrays in [&self.sky, &self.block] {
+            output.write_var_int(arrays.len() as i32)?;
+            for array in arrays {
+                output.write_var_int(array.bytes().len() as i32)?;
+                output.write(array.bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for UpdateViewPositionPacket {
+    fn id(&self) -> u32 { 0x49 }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_var_int(self.x)?;
+        output.write_var_int(self.z)?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for UnloadChunkPacket {
+    fn id(&self) -> u32 { 0x1D }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_i32(self.x)?;
+        output.write_i32(self.z)?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for WindowItemsPacket {
+    fn id(&self) -> u32 { 0x14 }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_u8(self.window_id)?;
+        output.write_i16(self.slots.len() as i16)?;
+        for slot in &self.slots {
+            output.write_slot(slot.as_ref())?;
+        }
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl SetSlotPacket {
+    pub fn cursor(stack: Slot) -> SetSlotPacket {
+        SetSlotPacket { window_id: -1, slot: -1, stack }
+    }
+}
+
+impl Packet for SetSlotPacket {
+    fn id(&self) -> u32 { 0x16 }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_i8(self.window_id)?;
+        output.write_i16(self.slot)?;
+        output.write_slot(self.stack.as_ref())?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for ClickWindowPacket {
+    fn id(&self) -> u32 { 0x08 }
+
+    fn read(input: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        let window_id = input.read_u8()?;
+        let slot = input.read_i16()?;
+        let button = input.read_i8()?;
+        let mode = input.read_var_int()?;
+        let count = input.read_var_int()?;
+        // a window has at most a few dozen slots
+        if !(0..=128).contains(&count) {
+            return Err(Error::IoError(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                format!("{} changed slots in one click", count))));
+        }
+        let mut changed = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            changed.push((input.read_i16()?, input.read_slot()?));
+        }
+        Ok(Self { window_id, slot, button, mode, changed, cursor: input.read_slot()? })
+    }
+
+    fn write(&self, _: &mut dyn Writable) -> Result<(), Error> {
+        Err(Refusal)
+    }
+
+    fn act(&self, client: &mut Client) -> Result<(), Error> {
+        let mut world = match client.current_world() {
+            Some(world) => world,
+            None => return Ok(())
+        };
+        // spectators can't touch anything
+        if client.game_mode == GameMode::Spectator {
+            return client.send_window(&mut world);
+        }
+        // the world stays locked until the container has its new contents, so two players
+        // clicking in the same chest can't both take the same stack
+        let (kind, contents) = if self.window_id == 0 {
+            (WindowType::Player, vec![])
+        } else {
+            match client.window_contents(&mut world, self.window_id) {
+                Some(window) => window,
+                // the block's gone, or the player's gone from it
+                None => return client.force_close_window(&mut world, self.window_id)
+            }
+        };
+        let creative = client.game_mode == GameMode::Creative;
+        let mut slots = client.inventory.take_window_slots(kind, contents);
+        let before = slots.clone();
+        let dropped = client.inventory.click(kind, &mut slots, self.slot, self.button, self.mode, creative);
+        let after = slots[..kind.size()].to_vec();
+        let contents = client.inventory.put_back(kind, slots);
+        client.set_window_contents(&mut world, contents);
+        for stack in dropped.iter().flatten() {
+            client.drop_item(&mut world, stack.clone());
+        }
+
+        // the client works the click out itself, and is told what's really there if it got
+        // something else; it leaves crafting results to the server
+        let mut expected = before[..kind.size()].to_vec();
+        let mut agrees = dropped.is_some();
+        for (slot, stack) in &self.changed {
+            match expected.get_mut(*slot as usize).filter(|_| *slot >= 0) {
+                Some(expected) => *expected = stack.clone(),
+                None => agrees = false
+            }
+        }
+        let result = kind.crafting().map(|_| inventory::CRAFTING_RESULT);
+        let differs = |i: usize| expected[i] != after[i] && Some(i) != result;
+        if !agrees || (0..after.len()).any(differs) || self.cursor != client.inventory.cursor {
+            return client.send_window(&mut world);
+        }
+        if let Some(result) = result.filter(|i| expected[*i] != after[*i]) {
+            let window_id = if kind == WindowType::Player { 0 } else { self.window_id as i8 };
+            client.write_packet(&SetSlotPacket { window_id, slot: result as i16, stack: after[result].clone() })?;
+        }
+        Ok(())
+    }
+}
+
+impl Packet for OpenWindowPacket {
+    fn id(&self) -> u32 { 0x2E }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_var_int(self.window_id as i32)?;
+        output.write_var_int(self.menu)?;
+        output.write_string(self.title.clone())?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for CloseWindowPacket {
+    fn id(&self) -> u32 { 0x13 }
+
+    fn read(input: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Ok(Self { window_id: input.read_u8()? })
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_u8(self.window_id)?;
+        Ok(())
+    }
+
+    fn act(&self, client: &mut Client) -> Result<(), Error> {
+        if self.window_id != 0 && client.window.as_ref().map(|w| w.id) != Some(self.window_id) {
+            return Ok(());
+        }
+        if let Some(mut world) = client.current_world() {
+            client.close_window(&mut world);
+        }
+        client.send_inventory()
+    }
+}
+
+impl Packet for WindowPropertyPacket {
+    fn id(&self) -> u32 { 0x15 }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_u8(self.window_id)?;
+        output.write_i16(self.property)?;
+        output.write_i16(self.value)?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for HeldItemChangePacket {
+    fn id(&self) -> u32 { 0x48 }
+
+    fn read(_: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Err(Refusal)
+    }
+
+    fn write(&self, output: &mut dyn Writable) -> Result<(), Error> {
+        output.write_u8(self.slot)?;
+        Ok(())
+    }
+
+    fn act(&self, _: &mut Client) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Packet for SelectSlotPacket {
+    fn id(&self) -> u32 { 0x25 }
+
+    fn read(input: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Ok(Self { slot: input.read_i16()? })
+    }
+
+    fn write(&self, _: &mut dyn Writable) -> Result<(), Error> {
+        Err(Refusal)
+    }
+
+    fn act(&self, client: &mut Client) -> Result<(), Error> {
+        if (0..9).contains(&self.slot) {
+            client.inventory.selected = self.slot as usize;
+            client.digging = None;
+        }
+        Ok(())
+    }
+}
+
+impl Packet for CreativeInventoryActionPacket {
+    fn id(&self) -> u32 { 0x28 }
+
+    fn read(input: &mut dyn Readable) -> Result<Self, Error> where Self: Sized {
+        Ok(Self { slot: input.read_i16()?, stack: input.read_slot()? })
+    }
+
+    fn write(&self, _: &mut dyn Writable) -> Result<(), Error> {
+        Err(Refusal)
+    }
+
+    fn act(&self, client: &mut Client) -> Result<(), Error> {
+        let valid = self.stack.as_ref().is_none_or(|stack| stack.count > 0 && stack.count <= stack.max_stack_size());
+        if client.game_mode != GameMode::Creative || !valid {
+            return client.send_inventory();
+        }
+        match (self.slot, &self.stack) {
+            (-1, Some(stack)) => {
+                if let Some(mut world) = client.current_world() {
+                    client.drop_item(&mut world, stack.clone());
+                }
+            }
+            (-1, None) => {}
+            (1..=45, stack) => client.inventory.slots[self.slot as usize] = stack.clone(),
+            _ => return client.send_inventory()
+        }
+        if inventory::CRAFTING_GRID.contains(&(self.slot as usize)) {
+            inventory::update_crafting(WindowType::Player, &mut client.inventory.slots);
+            client.send_slot(inventory::CRAFTING_RESULT)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Cursor, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use nbt::{Blob, Tag, NBTRead, NBTWrite};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::block_entity::{self, StoredItem};
+use crate::GameMode;
+use crate::dimension::OVERWORLD;
+
+/// What vanilla 1.17 writes as `DataVersion`.
+const DATA_VERSION: i32 = 2724;
+
+/// The tags `PlayerData` reads.
+const FIELDS: &[&str] = &[
+    "Pos", "Rotation", "OnGround", "Dimension", "playerGameType", "Health", "foodLevel",
+    "foodSaturationLevel", "XpLevel", "XpP", "XpTotal", "SelectedItemSlot", "Inventory"
+];
+
+/// The tags `StoredItem` reads, besides its `tag`.
+const ITEM_FIELDS: &[&str] = &["Slot", "id", "Count"];
+
+/// What's kept of a player between visits, with vanilla's names for it. Saving leaves anything
+/// else in the file alone, so files from vanilla keep what Grimstone doesn't use.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerData {
+    /// Where the player's feet are, as X, Y and Z.
+    #[serde(rename = "Pos")]
+    pub pos: Vec<f64>,
+    /// Yaw and pitch.
+    #[serde(rename = "Rotation")]
+    pub rotation: Vec<f32>,
+    #[serde(rename = "OnGround", default)]
+    pub on_ground: bool,
+    /// The dimension id of the world the player is in, from `dimension_id`.
+    #[serde(rename = "Dimension", default = "default_dimension")]
+    pub dimension: String,
+    #[serde(rename = "playerGameType", default)]
+    pub game_type: i32,
+    #[serde(rename = "Health", default = "default_health")]
+    pub health: f32,
+    #[serde(rename = "foodLevel", default = "default_food_level")]
+    pub food_level: i32,
+    #[serde(rename = "foodSaturationLevel", default = "default_saturation")]
+    pub food_saturation_level: f32,
+    #[serde(rename = "XpLevel", default)]
+    pub xp_level: i32,
+    /// How far the player is towards the next level, from 0 to 1.
+    #[serde(rename = "XpP", default)]
+    pub xp_progress: f32,
+    #[serde(rename = "XpTotal", default)]
+    pub xp_total: i32,
+    /// Which hotbar slot the player holds, from 0 to 8.
+    #[serde(rename = "SelectedItemSlot", default)]
+    pub selected_item_slot: i32,
+    /// Items by slot.
+    #[serde(rename = "Inventory", default)]
+    pub inventory: Vec<StoredItem>
+}
+
+fn default_dimension() -> String {
+    OVERWORLD.to_string()
+}
+
+fn default_health() -> f32 {
+    20.0
+}
+
+fn default_food_level() -> i32 {
+    20
+}
+
+fn default_saturation() -> f32 {
+    5.0
+}
+
+impl PlayerData {
+    /// The saved position, if it's three numbers.
+    pub fn position(&self) -> Option<(f64, f64, f64)> {
+        match self.pos[..] {
+            [x, y, z] if x.is_finite() && y.is_finite() && z.is_finite() => Some((x, y, z)),
+            _ => None
+        }
+    }
+
+    /// The saved yaw and pitch, or straight ahead facing south.
+    pub fn rotation(&self) -> (f32, f32) {
+        match self.rotation[..] {
+            [yaw, pitch] => (yaw, pitch),
+            _ => (0.0, 0.0)
+        }
+    }
+
+    pub fn game_mode(&self) -> GameMode {
+        GameMode::from_id(self.game_type as u8).unwrap_or(GameMode::Survival)
+    }
+
+    /// Reads the player's file in `dir`, if they have one.
+    pub fn load(dir: &Path, uuid: Uuid) -> io::Result<Option<PlayerData>> {
+        let blob = match read_blob(&path(dir, uuid))? {
+            Some(blob) => blob,
+            None => return Ok(None)
+        };
+        // the decoder can't skip tags it doesn't know, so only what's read here is passed on
+        let mut elements = only(blob.elements, FIELDS);
+        if let Some(Tag::Int(id)) = elements.get("Dimension") {
+            // vanilla before 1.16 numbered the dimensions
+            let dimension = match id {
+                -1 => "minecraft:the_nether",
+                1 => "minecraft:the_end",
+                _ => OVERWORLD
+            };
+            elements.insert("Dimension".to_string(), Tag::String(dimension.to_string()));
+        }
+        let mut tags = vec![];
+        if let Some(Tag::List(mut items)) = elements.remove("Inventory") {
+            tags = block_entity::take_item_tags(&mut items);
+            let items = items.into_iter()
+                .map(|item| match item {
+                    Tag::Compound(item) => Tag::Compound(only(item, ITEM_FIELDS)),
+                    item => item
+                })
+                .collect();
+            elements.insert("Inventory".to_string(), Tag::List(items));
+        }
+        let mut data: PlayerData = nbt::decode_tag(Tag::Compound(elements))
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("player {}: {}", uuid, e)))?;
+        block_entity::set_tags(&mut data.inventory, tags);
+        Ok(Some(data))
+    }
+
+    /// Writes the player's file in `dir`, keeping the old one as `<uuid>.dat_old` like vanilla.
+    pub fn save(&self, dir: &Path, uuid: Uuid) -> io::Result<()> {
+        let path = path(dir, uuid);
+        std::fs::create_dir_all(dir)?;
+        let mut blob = read_blob(&path).unwrap_or(None).unwrap_or_else(Blob::new);
+        let mut elements: HashMap<String, Tag> = match nbt::encode_tag(self) {
+            Ok(Some(Tag::Compound(elements))) => elements,
+            _ => unreachable!("player data always encodes to a compound")
+        };
+        if let Some(Tag::List(items)) = elements.get_mut("Inventory") {
+            block_entity::put_item_tags(items, &self.inventory);
+        }
+        blob.elements.extend(elements);
+        blob.insert("DataVersion", DATA_VERSION);
+
+        let bytes = blob.bytes()
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("player {}: {}", uuid, e)))?;
+        let new = path.with_extension("dat_new");
+        let mut encoder = GzEncoder::new(File::create(&new)?, Compression::default());
+        encoder.write_all(&bytes)?;
+        encoder.finish()?.sync_all()?;
+        if path.exists() {
+            std::fs::rename(&path, path.with_extension("dat_old"))?;
+        }
+        std::fs::rename(&new, &path)
+    }
+}
+
+/// The id vanilla saves as the `Dimension` of a player in the world called `world`. World names
+/// are resource locations, like `minecraft:overworld`, and vanilla reads them with the `minecraft`
+/// namespace if they have none.
+pub fn dimension_id(world: &str) -> String {
+    if world.contains(':') {
+        world.to_string()
+    } else {
+        format!("minecraft:{}", world)
+    }
+}
+
+fn path(dir: &Path, uuid: Uuid) -> PathBuf {
+    dir.join(format!("{}.dat", uuid))
+}
+
+fn only(mut compound: HashMap<String, Tag>, fields: &[&str]) -> HashMap<String, Tag> {
+    compound.retain(|name, _| fields.contains(&name.as_str()));
+    compound
+}
+
+fn read_blob(path: &Path) -> io::Result<Option<Blob>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut bytes = vec![];
+    GzDecoder::new(File::open(path)?).read_to_end(&mut bytes)?;
+    Blob::read(&mut Cursor::new(bytes))
+        .map(Some)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("grimstone-playerdata-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn write_blob(path: &Path, blob: &Blob) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut encoder = GzEncoder::new(File::create(path).unwrap(), Compression::default());
+        encoder.write_all(&blob.bytes().unwrap()).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    /// A player file like vanilla's, with tags Grimstone doesn't read.
+    fn vanilla_blob(dimension: Tag) -> Blob {
+        let mut display = HashMap::new();
+        display.insert("Name".to_string(), Tag::String("{\"text\":\"Pointy\"}".to_string()));
+        let mut tag = HashMap::new();
+        tag.insert("Damage".to_string(), Tag::Int(12));
+        tag.insert("display".to_string(), Tag::Compound(display));
+        let mut sword = HashMap::new();
+        sword.insert("Slot".to_string(), Tag::Byte(0));
+        sword.insert("id".to_string(), Tag::String("minecraft:iron_sword".to_string()));
+        sword.insert("Count".to_string(), Tag::Byte(1));
+        sword.insert("tag".to_string(), Tag::Compound(tag));
+        let mut dirt = HashMap::new();
+        dirt.insert("Slot".to_string(), Tag::Byte(3));
+        dirt.insert("id".to_string(), Tag::String("minecraft:dirt".to_string()));
+        dirt.insert("Count".to_string(), Tag::Byte(40));
+        let mut abilities = HashMap::new();
+        abilities.insert("flying".to_string(), Tag::Byte(0));
+
+        let mut blob = Blob::new();
+        blob.elements.insert("Pos".to_string(), Tag::List(vec![Tag::Double(1.5), Tag::Double(70.0), Tag::Double(-3.5)]));
+        blob.elements.insert("Rotation".to_string(), Tag::List(vec![Tag::Float(90.0), Tag::Float(0.0)]));
+        blob.elements.insert("Dimension".to_string(), dimension);
+        blob.elements.insert("Inventory".to_string(), Tag::List(vec![Tag::Compound(sword), Tag::Compound(dirt)]));
+        blob.elements.insert("abilities".to_string(), Tag::Compound(abilities));
+        blob.elements.insert("Score".to_string(), Tag::Int(7));
+        blob
+    }
+
+    #[test]
+    fn save_keeps_what_it_does_not_read() {
+        let dir = temp_dir("roundtrip");
+        let uuid = Uuid::from_u128(1);
+        write_blob(&path(&dir, uuid), &vanilla_blob(Tag::String(OVERWORLD.to_string())));
+
+        let data = PlayerData::load(&dir, uuid).unwrap().expect("the file was written");
+        assert_eq!(data.position(), Some((1.5, 70.0, -3.5)));
+        assert_eq!(data.dimension, OVERWORLD);
+        assert_eq!(data.inventory.len(), 2);
+        let sword = data.inventory.iter().find(|item| item.id == "minecraft:iron_sword").unwrap();
+        assert_eq!(sword.tag.as_ref().and_then(|tag| tag.get("Damage")), Some(&Tag::Int(12)));
+
+        data.save(&dir, uuid).unwrap();
+        let saved = read_blob(&path(&dir, uuid)).unwrap().unwrap();
+        assert_eq!(saved.elements.get("Score"), Some(&Tag::Int(7)));
+        assert!(matches!(saved.elements.get("abilities"), Some(Tag::Compound(_))));
+        assert_eq!(saved.elements.get("DataVersion"), Some(&Tag::Int(DATA_VERSION)));
+        assert!(path(&dir, uuid).with_extension("dat_old").exists());
+        assert_eq!(PlayerData::load(&dir, uuid).unwrap(), Some(data));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn dimensions_are_read_and_written_as_vanilla_ids() {
+        assert_eq!(dimension_id(OVERWORLD), OVERWORLD);
+        assert_eq!(dimension_id("lobby"), "minecraft:lobby");
+
+        let dir = temp_dir("dimension");
+        let uuid = Uuid::from_u128(2);
+        write_blob(&path(&dir, uuid), &vanilla_blob(Tag::Int(-1)));
+        let data = PlayerData::load(&dir, uuid).unwrap().unwrap();
+        assert_eq!(data.dimension, "minecraft:the_nether");
+
+        let mut blob = vanilla_blob(Tag::Int(0));
+        blob.elements.remove("Dimension");
+        write_blob(&path(&dir, uuid), &blob);
+        let data = PlayerData::load(&dir, uuid).unwrap().unwrap();
+        assert_eq!(data.dimension, OVERWORLD);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
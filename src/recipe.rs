@@ -0,0 +1,168 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use crate::inventory::Slot;
+use crate::item::{self, ItemId, ItemStack};
+
+/// One item a recipe takes, in vanilla's format: `{"item": ...}`, or a list of those for any of
+/// several items.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum Ingredient {
+    Item { item: String },
+    AnyOf(Vec<Ingredient>)
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RecipeResult {
+    pub item: String,
+    #[serde(default = "one")]
+    pub count: i8
+}
+
+fn one() -> i8 {
+    1
+}
+
+/// The recipes the server knows, in vanilla's data pack format.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "type")]
+pub enum Recipe {
+    #[serde(rename = "minecraft:crafting_shaped")]
+    Shaped {
+        /// Rows of keys, with spaces for empty slots.
+        pattern: Vec<String>,
+        key: HashMap<char, Ingredient>,
+        result: RecipeResult
+    },
+    #[serde(rename = "minecraft:crafting_shapeless")]
+    Shapeless {
+        ingredients: Vec<Ingredient>,
+        result: RecipeResult
+    },
+    #[serde(rename = "minecraft:smelting")]
+    Smelting {
+        ingredient: Ingredient,
+        result: String,
+        experience: f32,
+        /// In ticks.
+        #[serde(rename = "cookingtime", default = "default_cooking_time")]
+        cooking_time: i16
+    }
+}
+
+fn default_cooking_time() -> i16 {
+    200
+}
+
+static RECIPES: OnceLock<Vec<(String, Recipe)>> = OnceLock::new();
+
+/// Some of vanilla 1.17's crafting and smelting recipes, by name.
+pub fn vanilla() -> Vec<(String, Recipe)> {
+    let recipes: HashMap<String, Recipe> = serde_json::from_str(include_str!("data/recipes.json"))
+        .expect("Built-in recipe data is broken");
+    let mut recipes: Vec<_> = recipes.into_iter().collect();
+    recipes.sort_by(|a, b| a.0.cmp(&b.0));
+    recipes
+}
+
+pub fn recipes() -> &'static [(String, Recipe)] {
+    RECIPES.get_or_init(vanilla)
+}
+
+impl Ingredient {
+    pub fn matches(&self, stack: &ItemStack) -> bool {
+        match self {
+            Ingredient::Item { item } => item::id_of(item) == Some(stack.item),
+            Ingredient::AnyOf(any) => any.iter().any(|i| i.matches(stack))
+        }
+    }
+}
+
+impl RecipeResult {
+    fn stack(&self) -> Option<ItemStack> {
+        ItemStack::named(&self.item, self.count)
+    }
+}
+
+/// What the crafting grid makes, if anything. `grid` is `width` slots to a row.
+pub fn craft(grid: &[Slot], width: usize) -> Option<ItemStack> {
+    let grid = trim(grid, width)?;
+    recipes().iter().find_map(|(_, recipe)| match recipe {
+        Recipe::Shaped { pattern, key, result } => {
+            let fits = |mirror: bool| grid.len() == pattern.len() && grid.iter().zip(pattern).all(|(row, keys)| {
+                let keys: Vec<char> = keys.chars().collect();
+                row.len() == keys.len() && row.iter().enumerate().all(|(i, slot)| {
+                    let k = keys[if mirror { keys.len() - 1 - i } else { i }];
+                    match (slot, key.get(&k)) {
+                        (None, None) => k == ' ',
+                        (Some(stack), Some(ingredient)) => ingredient.matches(stack),
+                        _ => false
+                    }
+                })
+            });
+            if fits(false) || fits(true) { result.stack() } else { None }
+        }
+        Recipe::Shapeless { ingredients, result } => {
+            let mut stacks: Vec<&ItemStack> = grid.iter().flatten().filter_map(|slot| slot.as_ref()).collect();
+            if stacks.len() != ingredients.len() {
+                return None;
+            }
+            for ingredient in ingredients {
+                let i = stacks.iter().position(|stack| ingredient.matches(stack))?;
+                stacks.swap_remove(i);
+            }
+            result.stack()
+        }
+        Recipe::Smelting { .. } => None
+    })
+}
+
+/// The rows of the grid from the first to the last that have anything in them, cut down to the
+/// columns that do.
+fn trim(grid: &[Slot], width: usize) -> Option<Vec<Vec<&Slot>>> {
+    let rows: Vec<&[Slot]> = grid.chunks(width).collect();
+    let used_rows: Vec<usize> = (0..rows.len()).filter(|r| rows[*r].iter().any(Option::is_some)).collect();
+    let used_columns: Vec<usize> = (0..width).filter(|c| rows.iter().any(|row| row[*c].is_some())).collect();
+    let (top, bottom) = (*used_rows.first()?, *used_rows.last()?);
+    let (left, right) = (*used_columns.first()?, *used_columns.last()?);
+    Some(rows[top..=bottom].iter().map(|row| row[left..=right].iter().collect()).collect())
+}
+
+/// What smelting `stack` makes and how many ticks it takes.
+pub fn smelt(stack: &ItemStack) -> Option<(ItemStack, i16)> {
+    recipes().iter().find_map(|(_, recipe)| match recipe {
+        Recipe::Smelting { ingredient, result, cooking_time, .. } if ingredient.matches(stack) =>
+            Some((ItemStack::named(result, 1)?, *cooking_time)),
+        _ => None
+    })
+}
+
+/// How many ticks one of the item burns for in a furnace, or 0 if it isn't fuel. Vanilla keeps
+/// this in code rather than data packs too.
+pub fn burn_time(item: ItemId) -> i16 {
+    let name = match item::name_of(item) {
+        Some(name) => name.trim_start_matches("minecraft:"),
+        None => return 0
+    };
+    match name {
+        "lava_bucket" => 20000,
+        "coal_block" => 16000,
+        "blaze_rod" => 2400,
+        "coal" | "charcoal" => 1600,
+        "stick" => 100,
+        "crafting_table" | "chest" | "bookshelf" => 300,
+        _ if name.ends_with("_log") || name.ends_with("_planks") || name.ends_with("_wood") => 300,
+        _ if name.starts_with("wooden_") => 200,
+        _ if name.ends_with("_sapling") => 100,
+        _ => 0
+    }
+}
+
+/// What's left in the slot after one of `item` is burnt, like a bucket from a lava bucket.
+pub fn remainder(item: ItemId) -> Option<ItemStack> {
+    match item::name_of(item)? {
+        "minecraft:lava_bucket" => ItemStack::named("minecraft:bucket", 1),
+        _ => None
+    }
+}
@@ -1,5 +1,5 @@
-use serde::{Serialize, Deserialize};
-use nbt::{Tag, Blob};
+use serde::Serialize;
+use nbt::Blob;
 
 #[derive(Serialize)]
 pub struct Registry<'a, T: Serialize> {
@@ -17,7 +17,11 @@ pub struct RegistryEntry<T: Serialize> {
 }
 
 impl<'a, T: Serialize> Registry<'a, T> {
-    pub fn encode(&mut self) -> Blob {
+    pub fn new(name: &'a str) -> Registry<'a, T> {
+        Registry { name, entries: vec![] }
+    }
+
+    pub fn encode(&self) -> Blob {
         nbt::encode(self).expect("Failed to encode registry")
     }
 
@@ -32,4 +36,26 @@ impl<'a, T: Serialize> Registry<'a, T> {
 
         self.entries.push(RegistryEntry::<T> { name: name.to_string(), id, element: value})
     }
+
+    /// Registers an entry under a fixed id, replacing whatever had that name or id before.
+    pub fn register_with_id(&mut self, name: &str, id: i32, value: T) {
+        self.entries.retain(|e| e.name != name && e.id != id);
+        self.entries.push(RegistryEntry::<T> { name: name.to_string(), id, element: value })
+    }
+
+    /// Registers an entry, replacing the one with the same name but keeping its id.
+    pub fn replace(&mut self, name: &str, value: T) {
+        match self.get(name).map(|e| e.id) {
+            Some(id) => self.register_with_id(name, id, value),
+            None => self.register(name, value)
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RegistryEntry<T>> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    pub fn by_id(&self, id: i32) -> Option<&RegistryEntry<T>> {
+        self.entries.iter().find(|e| e.id == id)
+    }
 }
@@ -1,19 +1,238 @@
-use crate::client::Client;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use uuid::Uuid;
+use crate::client::Connection;
+use crate::traits::Packet;
+use crate::packets::play::{BlockChangePacket, MultiBlockChangePacket, BlockEntityDataPacket, PlayerInfoPacket,
+                           SpawnEntityPacket, SpawnPlayerPacket, EntityPositionPacket, EntityPositionAndRotationPacket,
+                           EntityRotationPacket, EntityHeadLookPacket, EntityTeleportPacket, DestroyEntityPacket,
+                           EntityMetadataPacket, SetSlotPacket, WindowPropertyPacket, CloseWindowPacket};
+use crate::block_entity::BlockEntity;
+use crate::entity::{EntityChanges, EntityKind, Movement};
 use crate::world::World;
-#[cfg(feature = "world_syncers")] use crate::world::WorldSyncer;
+use crate::Vector3I;
+
+/// A world shared between the tick thread, the players' threads and the console. Everything that
+/// touches it holds its lock, and never two worlds' locks at once.
+pub type SharedWorld = &'static Mutex<World>;
 
 pub struct Server {
-    pub clients: Vec<Client>,
-    pub world: Option<World>
+    /// Every loaded world by name, in the order they were added. Players join the first one.
+    worlds: RwLock<Vec<(String, SharedWorld)>>,
+    /// How to reach each player in a world from other threads.
+    connections: Mutex<Vec<(Uuid, Arc<Connection>)>>,
+    /// Goes up each time players are asked to save; each player's thread saves them when it sees
+    /// it change.
+    player_saves: AtomicU64,
+    /// How many ticks have been run since the server started.
+    ticks: AtomicU64
 }
 
-static mut SERVER: Server = Server {
-    clients: vec![],
-    world: None
+static SERVER: Server = Server {
+    worlds: RwLock::new(vec![]),
+    connections: Mutex::new(vec![]),
+    player_saves: AtomicU64::new(0),
+    ticks: AtomicU64::new(0)
 };
 
+/// Waits for the world's lock. A player's thread that panicked while holding it only takes that
+/// player down; the world carries on as it was left.
+pub fn lock(world: SharedWorld) -> MutexGuard<'static, World> {
+    world.lock().unwrap_or_else(|e| e.into_inner())
+}
+
 impl Server {
-    pub fn global() -> &'static mut Server {
-        unsafe { &mut SERVER }
+    pub fn global() -> &'static Server {
+        &SERVER
+    }
+
+    /// Adds a world for players to join. Worlds stay loaded until the server stops.
+    pub fn add_world(&self, world: World) {
+        let name = world.name().to_string();
+        let world: SharedWorld = Box::leak(Box::new(Mutex::new(world)));
+        self.worlds.write().unwrap_or_else(|e| e.into_inner()).push((name, world));
+    }
+
+    /// Every world, to be locked one at a time.
+    pub fn worlds(&self) -> Vec<SharedWorld> {
+        self.worlds.read().unwrap_or_else(|e| e.into_inner()).iter().map(|(_, w)| *w).collect()
+    }
+
+    /// The world called `name`, locked.
+    pub fn world(&self, name: &str) -> Option<MutexGuard<'static, World>> {
+        let world = self.worlds.read().unwrap_or_else(|e| e.into_inner()).iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, w)| *w)?;
+        Some(lock(world))
+    }
+
+    /// The world players join, locked.
+    pub fn default_world(&self) -> Option<MutexGuard<'static, World>> {
+        let world = self.worlds.read().unwrap_or_else(|e| e.into_inner()).first().map(|(_, w)| *w)?;
+        Some(lock(world))
+    }
+
+    pub fn world_names(&self) -> Vec<String> {
+        self.worlds.read().unwrap_or_else(|e| e.into_inner()).iter().map(|(n, _)| n.clone()).collect()
+    }
+
+    pub fn connect(&self, player: Uuid, connection: Arc<Connection>) {
+        let mut connections = self.connections.lock().unwrap_or_else(|e| e.into_inner());
+        connections.retain(|(p, _)| *p != player);
+        connections.push((player, connection));
+    }
+
+    pub fn disconnect(&self, player: Uuid) {
+        self.connections.lock().unwrap_or_else(|e| e.into_inner()).retain(|(p, _)| *p != player);
+    }
+
+    /// Asks every player's thread to save them, which happens once it next handles a packet.
+    pub fn save_players(&self) {
+        self.player_saves.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// How many times players have been asked to save.
+    pub fn player_saves(&self) -> u64 {
+        self.player_saves.load(Ordering::Relaxed)
     }
-}
\ No newline at end of file
+
+    /// How many ticks have been run since the server started.
+    pub fn current_tick(&self) -> u64 {
+        self.ticks.load(Ordering::Relaxed)
+    }
+
+    /// Sends `packet` to each of `players` that's connected. A failed send is left for the
+    /// player's own thread to notice.
+    pub fn send_to(&self, players: &[Uuid], packet: &dyn Packet) {
+        send(&self.connections, players, packet);
+    }
+
+    /// Runs a tick of every world: finishes background chunk loads and runs furnaces, then tells
+    /// players about the blocks and block entities that changed in the columns they have, the
+    /// entities around them and the containers they have open.
+    pub fn tick(&self) {
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+        for world in self.worlds() {
+            let mut world = lock(world);
+            world.poll_chunks();
+            let players = world.players().iter()
+                .filter_map(|player| world.entities().by_uuid(*player))
+                .map(|entity| {
+                    let (x, y, z) = entity.position;
+                    Vector3I((x / 16.0).floor() as i64, (y / 16.0).floor() as i64, (z / 16.0).floor() as i64)
+                })
+                .collect();
+            world.set_player_chunks(players);
+
+            for (section, blocks) in world.take_block_changes() {
+                let players = world.trackers(section.0 as i32, section.2 as i32);
+                if players.is_empty() {
+                    continue;
+                }
+                if let [((x, y, z), block)] = blocks[..] {
+                    let packet = BlockChangePacket {
+                        x: section.0 * 16 + x as i64,
+                        y: section.1 * 16 + y as i64,
+                        z: section.2 * 16 + z as i64,
+                        block
+                    };
+                    send(&self.connections, &players, &packet);
+                } else {
+                    send(&self.connections, &players, &MultiBlockChangePacket { section, blocks });
+                }
+            }
+
+            for (x, y, z) in world.take_block_entity_updates() {
+                let players = world.trackers(x.div_euclid(16) as i32, z.div_euclid(16) as i32);
+                match BlockEntityDataPacket::new(&mut world, x, y, z) {
+                    Ok(Some(packet)) => send(&self.connections, &players, &packet),
+                    Ok(None) => {}
+                    Err(e) => log::error!("Could not send the block entity at [{},{},{}] in {}: {}", x, y, z, world.name(), e)
+                }
+            }
+
+            send_entity_changes(&self.connections, world.take_entity_changes());
+
+            for (x, y, z) in world.tick_furnaces() {
+                let viewers = world.viewers(x, y, z).to_vec();
+                if viewers.is_empty() {
+                    continue;
+                }
+                let (slots, properties) = match world.block_entity(x, y, z) {
+                    Ok(Some(furnace @ BlockEntity::Furnace(data))) => (furnace.slots().unwrap_or_default(), data.properties()),
+                    _ => continue
+                };
+                for (player, window_id) in viewers {
+                    for (slot, stack) in slots.iter().enumerate() {
+                        send(&self.connections, &[player], &SetSlotPacket { window_id: window_id as i8, slot: slot as i16, stack: stack.clone() });
+                    }
+                    for (property, value) in properties {
+                        send(&self.connections, &[player], &WindowPropertyPacket { window_id, property, value });
+                    }
+                }
+            }
+            for (player, window_id) in world.take_closed_containers() {
+                send(&self.connections, &[player], &CloseWindowPacket { window_id });
+            }
+        }
+    }
+}
+
+fn send_entity_changes(connections: &Mutex<Vec<(Uuid, Arc<Connection>)>>, changes: EntityChanges) {
+    for (player, eid) in changes.destroyed {
+        send(connections, &[player], &DestroyEntityPacket { eid });
+    }
+    for (player, uuid) in changes.departed {
+        send(connections, &[player], &PlayerInfoPacket::RemovePlayer { uuid });
+    }
+
+    for (player, entity) in changes.spawned {
+        if let EntityKind::Player { name, game_mode } = &entity.kind {
+            send(connections, &[player], &PlayerInfoPacket::AddPlayer {
+                uuid: entity.uuid,
+                name: name.clone(),
+                game_mode: *game_mode
+            });
+            send(connections, &[player], &SpawnPlayerPacket::new(&entity));
+            send(connections, &[player], &EntityHeadLookPacket { eid: entity.id, head_yaw: entity.yaw });
+        } else {
+            send(connections, &[player], &SpawnEntityPacket::new(&entity));
+        }
+        let metadata = entity.metadata.iter().map(|(i, v)| (*i, v.clone())).collect();
+        send(connections, &[player], &EntityMetadataPacket { eid: entity.id, metadata });
+    }
+
+    for (players, movement) in changes.moved {
+        let Movement { id: eid, from, to, yaw, pitch, on_ground, moved, turned } = movement;
+        match EntityPositionPacket::delta(from, to) {
+            Some(delta) if moved && turned =>
+                send(connections, &players, &EntityPositionAndRotationPacket { eid, delta, yaw, pitch, on_ground }),
+            Some(delta) if moved => send(connections, &players, &EntityPositionPacket { eid, delta, on_ground }),
+            Some(_) => send(connections, &players, &EntityRotationPacket { eid, yaw, pitch, on_ground }),
+            None => send(connections, &players, &EntityTeleportPacket { eid, position: to, yaw, pitch, on_ground })
+        }
+        if turned {
+            send(connections, &players, &EntityHeadLookPacket { eid, head_yaw: yaw });
+        }
+    }
+
+    for (players, eid, metadata) in changes.metadata {
+        send(connections, &players, &EntityMetadataPacket { eid, metadata });
+    }
+
+    for (players, uuid, game_mode) in changes.game_modes {
+        send(connections, &players, &PlayerInfoPacket::UpdateGameMode { uuid, game_mode });
+    }
+}
+
+fn send(connections: &Mutex<Vec<(Uuid, Arc<Connection>)>>, players: &[Uuid], packet: &dyn Packet) {
+    let connections: Vec<_> = connections.lock().unwrap_or_else(|e| e.into_inner()).iter()
+        .filter(|(p, _)| players.contains(p))
+        .map(|(_, c)| c.clone())
+        .collect();
+    for connection in connections {
+        if let Err(e) = connection.send(packet) {
+            log::debug!("Could not send {:?}: {}", packet, e);
+        }
+    }
+}
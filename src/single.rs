@@ -1,110 +1,704 @@
 #![cfg(feature = "single")]
 
-use crate::world::{WorldSyncer, World, Block, ChunkLoadState, ChunkContainer, Chunk};
+use crate::world::{WorldSyncer, Block, Chunk, WorldError, WorldMeta};
+use crate::biome::{self, BiomeId};
+use crate::heightmap::HeightmapKind;
+use crate::light::LightArray;
 use std::path::Path;
 use std::collections::HashMap;
 use crate::Vector3I;
 use std::fs::{File, OpenOptions};
-use std::io::{Seek, SeekFrom, Write, Read};
-use SeekFrom::{Start, Current};
+use std::io::{Seek, SeekFrom, Write, Read, Cursor};
+use SeekFrom::Start;
 use byteorder::{WriteBytesExt, ReadBytesExt, BigEndian};
-use std::mem::{size_of, transmute};
-use crate::world::ChunkLoadState::{Loaded, Unloaded};
-use std::io::SeekFrom::End;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use nbt::{Blob, Tag, NBTRead, NBTWrite};
+
+const MAGIC: &[u8; 4] = b"SNG\0";
+const VERSION: u32 = 4;
+
+// Single World v4 format:
+// - header:
+//   - <magic>: "SNG\0"
+//   - <version>: u32
+//   - <slots>: [Slot; 2]
+// - index table, anywhere after the header:
+//   - <count>: u32
+//   - <entries>: [(x: i32, y: i32, z: i32, offset: u64, length: u32, crc: u32); count]
+//   - <meta length>: u32
+//   - <meta>: NBT with the world-wide data, like the seed
+// - chunk payloads, anywhere after the header: zlib-compressed NBT with `Blocks`, the biomes
+//   (`BiomePalette` and `Biomes`), the chunk's own `Heightmaps`, its `BlockEntities` as vanilla
+//   saves them and, once it's been lit, its `SkyLight` and `BlockLight`; chunks written before
+//   these existed get the default biome, fresh block entities and have their heightmaps and light
+//   worked out on load
+//
+// Writes are copy-on-write: chunks and the index table only ever go into space the committed
+// index doesn't use, and a save is committed by writing the *other* header slot with a higher
+// generation. A crash at any point leaves either the old slot or the new one intact, and
+// opening the file picks the newest slot that checks out and drops anything written after it.
+//
+// Version 3 index tables end after the entries. They're read as v4 tables without metadata,
+// so v3 files are opened as they are and become v4 on their next save.
+// Version 2 had a single slot at offset 8 (without generation or slot crc).
+// Version 1 files have no version field; their chunk count is a u64 at offset 4, so the u32
+// read as the version is 0 for any v1 file with fewer than 2^32 chunks.
+const SLOT_OFFSETS: [u64; 2] = [8, 8 + SLOT_LEN];
+const SLOT_LEN: u64 = 32;
+const HEADER_LEN: u64 = 8 + 2 * SLOT_LEN;
+const INDEX_ENTRY_LEN: u64 = 28;
+
+#[derive(Copy, Clone)]
+struct IndexEntry {
+    offset: u64,
+    length: u32,
+    crc: u32
+}
+
+// Header slot:
+// - <generation>: u64
+// - <index offset>: u64
+// - <index length>: u32
+// - <index crc>: u32
+// - <slot crc>: u32, over the fields above
+// - <padding>: u32
+#[derive(Copy, Clone, Default, Debug)]
+struct Slot {
+    generation: u64,
+    index_offset: u64,
+    index_len: u32,
+    index_crc: u32
+}
+
+impl Slot {
+    fn bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SLOT_LEN as usize);
+        bytes.write_u64::<BigEndian>(self.generation).unwrap();
+        bytes.write_u64::<BigEndian>(self.index_offset).unwrap();
+        bytes.write_u32::<BigEndian>(self.index_len).unwrap();
+        bytes.write_u32::<BigEndian>(self.index_crc).unwrap();
+        let crc = crc32fast::hash(&bytes);
+        bytes.write_u32::<BigEndian>(crc).unwrap();
+        bytes.write_u32::<BigEndian>(0).unwrap();
+        bytes
+    }
+
+    /// Reads a slot, returning `None` if it was never written or was torn mid-write.
+    fn read(file: &mut File, offset: u64) -> Result<Option<Slot>, WorldError> {
+        let mut bytes = [0u8; SLOT_LEN as usize];
+        file.seek(Start(offset))?;
+        file.read_exact(&mut bytes)?;
+
+        let mut r = Cursor::new(&bytes[..]);
+        let slot = Slot {
+            generation: r.read_u64::<BigEndian>()?,
+            index_offset: r.read_u64::<BigEndian>()?,
+            index_len: r.read_u32::<BigEndian>()?,
+            index_crc: r.read_u32::<BigEndian>()?
+        };
+        let crc = r.read_u32::<BigEndian>()?;
+        Ok(if crc == crc32fast::hash(&bytes[..24]) { Some(slot) } else { None })
+    }
+}
 
 pub struct SingleWorldFile {
     file: File,
-    indices: HashMap<Vector3I, usize>,
-    current_chunk_count: u64
+    /// Version in the file's header, which is only raised once a v4 index is committed.
+    version: u32,
+    indices: HashMap<Vector3I, IndexEntry>,
+    meta: WorldMeta,
+    /// Offset and length of the committed index table.
+    index_extent: (u64, u64),
+    index_dirty: bool,
+    generation: u64,
+    /// Which header slot holds the committed generation.
+    active_slot: usize,
+    /// Unused `(offset, length)` extents, sorted and merged.
+    free: Vec<(u64, u64)>,
+    /// Extents the committed index still points at; they only become free once the next
+    /// generation is committed.
+    pending_free: Vec<(u64, u64)>,
+    end: u64,
+    /// Opened with `open_read_only`; the file is never written.
+    read_only: bool,
+    /// Set by `fail_next_flush`.
+    fail_next_commit: bool
 }
 
-impl WorldSyncer for SingleWorldFile {
-    fn new(path: &str) -> Self where Self: Sized {
-        let mut file = if Path::new(path).exists() {
-            OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(path)
-                .expect("Could not open existing file?")
+impl SingleWorldFile {
+    fn create(path: &Path) -> Result<File, WorldError> {
+        let mut file = File::create(path)?;
+        file.write_all(MAGIC)?;
+        file.write_u32::<BigEndian>(VERSION)?;
+        file.write_all(&Slot::default().bytes())?;
+        file.write_all(&[0u8; SLOT_LEN as usize])?;
+        file.sync_all()?;
+        Ok(file)
+    }
+
+    /// Reads and checks the index table a header slot points to. Every chunk's checksum is
+    /// verified too, so a slot is only trusted if all of the data it refers to made it to disk.
+    fn read_index(file: &mut File, len: u64, slot: &Slot) -> Result<(HashMap<Vector3I, IndexEntry>, WorldMeta), WorldError> {
+        let mut indices = HashMap::new();
+        if slot.index_len == 0 {
+            return Ok((indices, WorldMeta::default()));
+        }
+
+        let index_len = slot.index_len as u64;
+        if slot.index_offset < HEADER_LEN || slot.index_offset + index_len > len {
+            return Err(WorldError::Corrupt("index table lies outside the file".to_string()));
+        }
+        let mut table = vec![0u8; index_len as usize];
+        file.seek(Start(slot.index_offset))?;
+        file.read_exact(&mut table)?;
+        if crc32fast::hash(&table) != slot.index_crc {
+            return Err(WorldError::Corrupt("index table checksum mismatch".to_string()));
+        }
+
+        let mut table = Cursor::new(table);
+        let count = table.read_u32::<BigEndian>()? as u64;
+        if 4 + count * INDEX_ENTRY_LEN > index_len {
+            return Err(WorldError::Corrupt("index table length mismatch".to_string()));
+        }
+        for _ in 0..count {
+            let x = table.read_i32::<BigEndian>()?;
+            let y = table.read_i32::<BigEndian>()?;
+            let z = table.read_i32::<BigEndian>()?;
+            let entry = IndexEntry {
+                offset: table.read_u64::<BigEndian>()?,
+                length: table.read_u32::<BigEndian>()?,
+                crc: table.read_u32::<BigEndian>()?
+            };
+            if entry.offset < HEADER_LEN || entry.offset + entry.length as u64 > len {
+                return Err(WorldError::Corrupt(format!("chunk [{},{},{}] lies outside the file", x, y, z)));
+            }
+            let mut payload = vec![0u8; entry.length as usize];
+            file.seek(Start(entry.offset))?;
+            file.read_exact(&mut payload)?;
+            if crc32fast::hash(&payload) != entry.crc {
+                return Err(WorldError::Corrupt(format!("chunk [{},{},{}] checksum mismatch", x, y, z)));
+            }
+            indices.insert(Vector3I(x as i64, y as i64, z as i64), entry);
+        }
+
+        let meta = if table.position() == index_len {
+            WorldMeta::default() // v3 table
         } else {
-            {
-                let mut file = File::create(path).expect("Could not create file.");
-                file.write_all("SNG\0".as_bytes()).expect("Could not write data to Single file");
-                file.write_u64::<BigEndian>(0).expect("Could not write data to Single file");
-                file.seek(Start(0)).expect("Could not seek into Single file");
+            let meta_len = table.read_u32::<BigEndian>()? as u64;
+            if table.position() + meta_len != index_len {
+                return Err(WorldError::Corrupt("index table length mismatch".to_string()));
             }
-            OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(path)
-                .expect("Could not open new file.")
+            decode_meta(&mut table)?
         };
+        Ok((indices, meta))
+    }
+
+    /// Opens a current-version file, recovering from an interrupted save: the newest header
+    /// slot whose index and chunks are all intact wins, and anything past the data it uses
+    /// is cut off (or just ignored, when read-only).
+    fn open(path: &Path, read_only: bool) -> Result<Self, WorldError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(!read_only)
+            .open(path)?;
+        let len = file.metadata()?.len();
 
-        {
-            let mut header_array = [0u8; 4];
-            file.read(&mut header_array).expect("Could not read data from Single file; may be invalid");
-            if String::from_utf8(header_array.to_vec()).expect("UTF-8 is not cooperating") != "SNG\0".to_string() {
-                panic!("Invalid Single world {}", path);
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(WorldError::Corrupt(format!("{} is not a Single world", path.display())));
+        }
+        let version = file.read_u32::<BigEndian>()?;
+        if version != VERSION && version != 3 {
+            return Err(WorldError::VersionMismatch { found: version, expected: VERSION });
+        }
+
+        let mut slots = vec![];
+        for (i, offset) in SLOT_OFFSETS.iter().enumerate() {
+            if let Some(slot) = Slot::read(&mut file, *offset)? {
+                slots.push((i, slot));
             }
         }
+        slots.sort_by_key(|(_, slot)| std::cmp::Reverse(slot.generation));
+        let newest = slots.first().map(|(_, slot)| slot.generation);
 
-        let mut indices = HashMap::new();
-        let index_count = file.read_u64::<BigEndian>().expect("Could not read data from single file; may be invalid");
+        let mut recovered = None;
+        for (i, slot) in slots {
+            match Self::read_index(&mut file, len, &slot) {
+                Ok(index) => {
+                    recovered = Some((i, slot, index));
+                    break;
+                }
+                Err(e) => log::warn!("Header slot {} (generation {}) of {} is unusable: {}", i, slot.generation, path.display(), e)
+            }
+        }
+        let (active_slot, slot, (indices, meta)) = recovered
+            .ok_or_else(|| WorldError::Corrupt(format!("{} has no intact header", path.display())))?;
+        if Some(slot.generation) != newest {
+            log::warn!("Rolled {} back to generation {} after an interrupted save", path.display(), slot.generation);
+        }
 
-        // Single World Chunk format:
-        // - <x>: i32
-        // - <y>: i32
-        // - <z>: i32
-        // - <blocks>: [Block; 16*16*16]
-        for _ in 0..index_count {
-            let start_index = file.stream_position().expect("Could not get position of stream.");
-            let x = file.read_i32::<BigEndian>().expect("Could not read data from single file; may be invalid");
-            let y = file.read_i32::<BigEndian>().expect("Could not read data from single file; may be invalid");
-            let z = file.read_i32::<BigEndian>().expect("Could not read data from single file; may be invalid");
-            indices.insert(Vector3I(x as i64, y as i64, z as i64), start_index as usize);
-            file.seek(Current(size_of::<[Block; 16 * 16 * 16]>() as i64)).expect("Could not seek into Single file");
+        let mut used: Vec<(u64, u64)> = indices.values().map(|e| (e.offset, e.length as u64)).collect();
+        if slot.index_len > 0 {
+            used.push((slot.index_offset, slot.index_len as u64));
         }
 
-        Self { file, indices, current_chunk_count: index_count }
+        // everything between the used extents is free to hand out again
+        used.sort_unstable();
+        let mut free = vec![];
+        let mut cursor = HEADER_LEN;
+        for (offset, len) in used {
+            if offset < cursor {
+                return Err(WorldError::Corrupt(format!("overlapping data at offset {}", offset)));
+            }
+            if offset > cursor { free.push((cursor, offset - cursor)); }
+            cursor = offset + len;
+        }
+        let end = cursor;
+
+        if len > end && !read_only {
+            log::info!("Discarding {} bytes of unfinished writes from {}", len - end, path.display());
+            file.set_len(end)?;
+            file.sync_all()?;
+        }
+
+        Ok(Self {
+            file,
+            version,
+            indices,
+            meta,
+            index_extent: (slot.index_offset, slot.index_len as u64),
+            index_dirty: false,
+            generation: slot.generation,
+            active_slot,
+            free,
+            pending_free: vec![],
+            end,
+            read_only,
+            fail_next_commit: false
+        })
     }
 
-    fn save(&mut self, chunk: &mut Chunk) {
-        let pos = Vector3I(chunk.x as i64, chunk.y as i64, chunk.z as i64);
-        if self.indices.contains_key(&pos) {
-            self.file.seek(Start(*self.indices.get(&pos).unwrap() as u64)).expect("Could not seek into Single file");
-        } else {
-            self.file.seek(End(0)).expect("Could not seek into Single file");
-            self.indices.insert(pos, self.file.stream_position().expect("Could not get position of stream.") as usize);
-            self.current_chunk_count += 1;
+    /// Upgrades an older file to the current format by copying its chunks into a new file and
+    /// renaming it over the old one, so the old file stays untouched if the upgrade fails.
+    fn migrate(path: &Path, version: u32) -> Result<(), WorldError> {
+        log::info!("Upgrading {} from Single v{} to v{}", path.display(), version.max(1), VERSION);
+
+        let mut old = File::open(path)?;
+        let tmp = path.with_extension("sng-upgrade");
+        Self::create(&tmp)?;
+        let mut new = Self::open(&tmp, false)?;
+
+        match version {
+            0 => {
+                old.seek(Start(4))?;
+                let count = old.read_u64::<BigEndian>()?;
+
+                // Single World v1 chunk format:
+                // - <x>: i32
+                // - <y>: i32
+                // - <z>: i32
+                // - <blocks>: [u16; 16*16*16]
+                for _ in 0..count {
+                    let x = old.read_i32::<BigEndian>()?;
+                    let y = old.read_i32::<BigEndian>()?;
+                    let z = old.read_i32::<BigEndian>()?;
+                    let mut chunk = Chunk::new_empty(x, y, z);
+                    for i in 0..(16 * 16 * 16) {
+                        let id = old.read_u16::<BigEndian>()?;
+                        chunk.blocks[i] = Block::from_id(id)
+                            .ok_or_else(|| WorldError::Corrupt(format!("unknown block id {} in chunk [{},{},{}]", id, x, y, z)))?;
+                    }
+                    chunk.recompute_heightmaps();
+                    new.save(&mut chunk)?;
+                }
+            }
+            2 => {
+                // v2 payloads are the same as v3's, only the header changed
+                let len = old.metadata()?.len();
+                old.seek(Start(8))?;
+                let slot = Slot {
+                    generation: 0,
+                    index_offset: old.read_u64::<BigEndian>()?,
+                    index_len: old.read_u32::<BigEndian>()?,
+                    index_crc: old.read_u32::<BigEndian>()?
+                };
+                for (pos, entry) in Self::read_index(&mut old, len, &slot)?.0 {
+                    let mut payload = vec![0u8; entry.length as usize];
+                    old.seek(Start(entry.offset))?;
+                    old.read_exact(&mut payload)?;
+                    new.store(pos, &payload)?;
+                }
+            }
+            found => return Err(WorldError::VersionMismatch { found, expected: VERSION })
+        }
+        new.flush()?;
+        drop(new);
+
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Writes a chunk payload into free space; it isn't visible until the next `flush`.
+    fn store(&mut self, pos: Vector3I, payload: &[u8]) -> Result<(), WorldError> {
+        if self.read_only {
+            return Err(WorldError::ReadOnly);
+        }
+        let offset = self.allocate(payload.len() as u64);
+        self.file.seek(Start(offset))?;
+        self.file.write_all(payload)?;
+
+        let entry = IndexEntry { offset, length: payload.len() as u32, crc: crc32fast::hash(payload) };
+        if let Some(old) = self.indices.insert(pos, entry) {
+            self.pending_free.push((old.offset, old.length as u64));
+        }
+        self.index_dirty = true;
+        Ok(())
+    }
+
+    /// First-fit allocation from the free list, growing the file when nothing fits.
+    fn allocate(&mut self, len: u64) -> u64 {
+        if let Some(i) = self.free.iter().position(|(_, l)| *l >= len) {
+            let (offset, l) = self.free[i];
+            if l == len {
+                self.free.remove(i);
+            } else {
+                self.free[i] = (offset + len, l - len);
+            }
+            return offset;
+        }
+
+        let offset = self.end;
+        self.end += len;
+        offset
+    }
+
+    /// Writes `table` at `offset` and commits it by overwriting the header slot that *isn't*
+    /// live, which becomes the live one.
+    fn commit_index(&mut self, offset: u64, table: &[u8]) -> Result<Slot, WorldError> {
+        self.file.seek(Start(offset))?;
+        self.file.write_all(table)?;
+        if self.version != VERSION {
+            // v4 readers understand v3 tables, but not the other way around
+            self.file.seek(Start(4))?;
+            self.file.write_u32::<BigEndian>(VERSION)?;
+            self.version = VERSION;
+        }
+        self.file.sync_data()?;
+
+        let slot = Slot {
+            generation: self.generation + 1,
+            index_offset: offset,
+            index_len: table.len() as u32,
+            index_crc: crc32fast::hash(table)
+        };
+        let target = 1 - self.active_slot;
+        self.file.seek(Start(SLOT_OFFSETS[target]))?;
+        self.file.write_all(&slot.bytes())?;
+        if std::mem::take(&mut self.fail_next_commit) {
+            return Err(WorldError::Io(std::io::Error::other("injected failure")));
+        }
+        self.file.sync_data()?;
+        self.active_slot = target;
+        Ok(slot)
+    }
+
+    /// Makes the next `flush` fail after writing its header slot but before syncing it, like a
+    /// disk error would. Only for testing recovery.
+    #[doc(hidden)]
+    pub fn fail_next_flush(&mut self) {
+        self.fail_next_commit = true;
+    }
+
+    fn release(&mut self, offset: u64, len: u64) {
+        if len == 0 { return; }
+        let i = self.free.partition_point(|(o, _)| *o < offset);
+        // freeing space twice would let two chunks be given the same bytes
+        let overlaps = (i > 0 && self.free[i - 1].0 + self.free[i - 1].1 > offset)
+            || (i < self.free.len() && offset + len > self.free[i].0)
+            || offset + len > self.end;
+        debug_assert!(!overlaps, "released {}+{} overlaps free space", offset, len);
+        if overlaps {
+            log::error!("Not freeing {} bytes at {} again; they're already free", len, offset);
+            return;
+        }
+        self.free.insert(i, (offset, len));
+
+        // merge with the neighbours on either side
+        if i + 1 < self.free.len() && self.free[i].0 + self.free[i].1 == self.free[i + 1].0 {
+            self.free[i].1 += self.free[i + 1].1;
+            self.free.remove(i + 1);
+        }
+        if i > 0 && self.free[i - 1].0 + self.free[i - 1].1 == self.free[i].0 {
+            self.free[i - 1].1 += self.free[i].1;
+            self.free.remove(i);
+        }
+
+        // a hole at the end of the file is just a shorter file
+        if let Some(&(o, l)) = self.free.last() {
+            if o + l == self.end {
+                self.end = o;
+                self.free.pop();
+            }
+        }
+    }
+}
+
+fn encode_chunk(chunk: &Chunk) -> Result<Vec<u8>, WorldError> {
+    let mut blocks = Vec::with_capacity(16 * 16 * 16 * 2);
+    for b in chunk.blocks.iter() {
+        let id = *b as u16;
+        blocks.push((id >> 8) as i8);
+        blocks.push(id as i8);
+    }
+
+    // biomes are stored by name so changing the custom biomes doesn't shuffle them around
+    let mut palette: Vec<BiomeId> = vec![];
+    let mut biomes = Vec::with_capacity(4 * 4 * 4);
+    for id in chunk.biomes.iter() {
+        let i = match palette.iter().position(|p| p == id) {
+            Some(i) => i,
+            None => {
+                palette.push(*id);
+                palette.len() - 1
+            }
+        };
+        biomes.push(i as i8);
+    }
+    let palette = palette.iter()
+        .map(|id| Tag::String(biome::name_of(*id).unwrap_or(biome::DEFAULT_BIOME).to_string()))
+        .collect();
+
+    let mut blob = Blob::new();
+    blob.insert("Blocks", Tag::ByteArray(blocks));
+    blob.insert("BiomePalette", Tag::List(palette));
+    blob.insert("Biomes", Tag::ByteArray(biomes));
+    let mut heightmaps = HashMap::new();
+    for kind in HeightmapKind::ALL {
+        heightmaps.insert(kind.name().to_string(), Tag::LongArray(chunk.heightmaps.packed(kind)));
+    }
+    blob.insert("Heightmaps", Tag::Compound(heightmaps));
+    let entities = chunk.block_entities().map(|((x, y, z), entity)| {
+        let (x, y, z) = chunk.world_pos(*x, *y, *z);
+        Tag::Compound(entity.to_nbt(x, y, z))
+    }).collect();
+    blob.insert("BlockEntities", Tag::List(entities));
+    if chunk.lit {
+        blob.insert("SkyLight", Tag::ByteArray(chunk.sky_light.bytes().iter().map(|b| *b as i8).collect()));
+        blob.insert("BlockLight", Tag::ByteArray(chunk.block_light.bytes().iter().map(|b| *b as i8).collect()));
+    }
+    let bytes = blob.bytes().map_err(|e| WorldError::Corrupt(e.to_string()))?;
+
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    encoder.write_all(&bytes)?;
+    Ok(encoder.finish()?)
+}
+
+fn light_array(bytes: &[i8]) -> Option<LightArray> {
+    LightArray::from_bytes(&bytes.iter().map(|b| *b as u8).collect::<Vec<_>>())
+}
+
+fn decode_chunk(x: i32, y: i32, z: i32, payload: &[u8]) -> Result<Chunk, WorldError> {
+    let corrupt = |why: String| WorldError::Corrupt(format!("chunk [{},{},{}] {}", x, y, z, why));
+
+    let mut bytes = vec![];
+    ZlibDecoder::new(payload).read_to_end(&mut bytes)?;
+    let mut blob = Blob::read(&mut Cursor::new(bytes)).map_err(|e| corrupt(e.to_string()))?;
+
+    let mut chunk = Chunk::new_empty(x, y, z);
+    match blob.elements.get("Blocks") {
+        Some(Tag::ByteArray(blocks)) if blocks.len() == 16 * 16 * 16 * 2 => {
+            for (i, pair) in blocks.chunks(2).enumerate() {
+                let id = (pair[0] as u8 as u16) << 8 | pair[1] as u8 as u16;
+                chunk.blocks[i] = Block::from_id(id).ok_or_else(|| corrupt(format!("has unknown block id {}", id)))?;
+            }
+        }
+        _ => return Err(corrupt("has no block data".to_string()))
+    }
+
+    // chunks saved before biomes were stored keep the default biome
+    if let (Some(Tag::List(palette)), Some(Tag::ByteArray(biomes))) = (blob.elements.get("BiomePalette"), blob.elements.get("Biomes")) {
+        if biomes.len() != 4 * 4 * 4 {
+            return Err(corrupt("has a bad biome array".to_string()));
         }
-        self.file.write_i32::<BigEndian>(chunk.x).expect("Could not write data to Single file");
-        self.file.write_i32::<BigEndian>(chunk.y).expect("Could not write data to Single file");
-        self.file.write_i32::<BigEndian>(chunk.z).expect("Could not write data to Single file");
-        for b in chunk.blocks {
-            self.file.write_u16::<BigEndian>(b as u16).expect("Could not write data to Single file");
+        let mut ids = vec![];
+        for name in palette {
+            ids.push(match name {
+                Tag::String(name) => biome::id_of(name).unwrap_or_else(|| {
+                    log::warn!("Chunk [{},{},{}] has unknown biome {}; using {}", x, y, z, name, biome::DEFAULT_BIOME);
+                    biome::default_id()
+                }),
+                _ => return Err(corrupt("has a bad biome palette".to_string()))
+            });
+        }
+        for (i, index) in biomes.iter().enumerate() {
+            chunk.biomes[i] = *ids.get(*index as u8 as usize).ok_or_else(|| corrupt("has a bad biome index".to_string()))?;
         }
-        self.file.seek(Start(4)).expect("Could not seek into Single file");
-        self.file.write_u64::<BigEndian>(self.current_chunk_count).expect("Could not write data to Single file");
-        self.file.flush().expect("Could not flush Single file");
     }
 
-    fn find_all(&mut self) -> Vec<Vector3I> {
+    // chunks saved before heightmaps were stored work them out from their blocks
+    let mut stored = false;
+    if let Some(Tag::Compound(heightmaps)) = blob.elements.get("Heightmaps") {
+        stored = HeightmapKind::ALL.iter().all(|kind| match heightmaps.get(kind.name()) {
+            Some(Tag::LongArray(longs)) => chunk.heightmaps.unpack(*kind, longs.clone()),
+            _ => false
+        });
+    }
+    if !stored {
+        chunk.recompute_heightmaps();
+    }
+
+    // chunks saved before light was stored get lit when their column loads
+    if let (Some(Tag::ByteArray(sky)), Some(Tag::ByteArray(block))) = (blob.elements.get("SkyLight"), blob.elements.get("BlockLight")) {
+        let (sky, block) = match (light_array(sky), light_array(block)) {
+            (Some(sky), Some(block)) => (sky, block),
+            _ => return Err(corrupt("has a bad light array".to_string()))
+        };
+        chunk.sky_light = sky;
+        chunk.block_light = block;
+        chunk.lit = true;
+    }
+
+    if let Some(Tag::List(entities)) = blob.elements.remove("BlockEntities") {
+        for entity in entities {
+            match entity {
+                Tag::Compound(entity) => chunk.load_block_entity(entity),
+                _ => return Err(corrupt("has a malformed block entity".to_string()))
+            }
+        }
+    }
+    chunk.repair_block_entities();
+    Ok(chunk)
+}
+
+fn encode_meta(meta: &WorldMeta) -> Result<Vec<u8>, WorldError> {
+    let mut blob = Blob::new();
+    if let Some(seed) = meta.seed {
+        blob.insert("Seed", Tag::Long(seed));
+    }
+    blob.bytes().map_err(|e| WorldError::Corrupt(e.to_string()))
+}
+
+fn decode_meta(table: &mut Cursor<Vec<u8>>) -> Result<WorldMeta, WorldError> {
+    let blob = Blob::read(table).map_err(|e| WorldError::Corrupt(format!("world metadata {}", e)))?;
+    Ok(WorldMeta {
+        seed: match blob.elements.get("Seed") {
+            Some(Tag::Long(seed)) => Some(*seed),
+            _ => None
+        }
+    })
+}
+
+impl WorldSyncer for SingleWorldFile {
+    fn new(path: &str) -> Result<Self, WorldError> where Self: Sized {
+        let path = Path::new(path);
+        if !path.exists() {
+            Self::create(path)?;
+        }
+
+        match Self::open(path, false) {
+            Err(WorldError::VersionMismatch { found, .. }) if found < VERSION => {
+                Self::migrate(path, found)?;
+                Self::open(path, false)
+            }
+            other => other
+        }
+    }
+
+    /// Older files can't be upgraded without writing, so they have to be opened writable once
+    /// first.
+    fn open_read_only(path: &str) -> Result<Self, WorldError> where Self: Sized {
+        Self::open(Path::new(path), true)
+    }
+
+    fn save(&mut self, chunk: &mut Chunk) -> Result<(), WorldError> {
+        let pos = Vector3I(chunk.x as i64, chunk.y as i64, chunk.z as i64);
+        let payload = encode_chunk(chunk)?;
+        self.store(pos, &payload)
+    }
+
+    fn find_all(&mut self) -> Result<Vec<Vector3I>, WorldError> {
         let mut poses = vec![];
         for p in self.indices.keys() { poses.push(*p); }
-        poses
+        Ok(poses)
     }
 
-    fn load(&mut self, x: i32, y: i32, z: i32) -> Chunk {
+    fn load(&mut self, x: i32, y: i32, z: i32) -> Result<Chunk, WorldError> {
         let pos = Vector3I(x as i64, y as i64, z as i64);
-        if self.indices.contains_key(&pos) {
-            let mut chunk = Chunk::new_empty(x, y, z);
-            self.file.seek(Start((self.indices.get(&pos).unwrap() + 12) as u64)).expect("Could not seek into Single file");
-            for i in 0..(16 * 16 * 16) {
-                chunk.blocks[i] = unsafe { transmute(self.file.read_u16::<BigEndian>().expect("Could not read data from single file; may be invalid")) };
+        let entry = match self.indices.get(&pos) {
+            Some(entry) => *entry,
+            None => return Err(WorldError::MissingChunk(pos))
+        };
+
+        let mut payload = vec![0u8; entry.length as usize];
+        self.file.seek(Start(entry.offset))?;
+        self.file.read_exact(&mut payload)?;
+        if crc32fast::hash(&payload) != entry.crc {
+            return Err(WorldError::Corrupt(format!("chunk [{},{},{}] checksum mismatch", x, y, z)));
+        }
+        decode_chunk(x, y, z, &payload)
+    }
+
+    fn load_meta(&mut self) -> Result<WorldMeta, WorldError> {
+        Ok(self.meta.clone())
+    }
+
+    fn save_meta(&mut self, meta: &WorldMeta) -> Result<(), WorldError> {
+        if self.read_only {
+            return Err(WorldError::ReadOnly);
+        }
+        self.meta = meta.clone();
+        self.index_dirty = true;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), WorldError> {
+        if !self.index_dirty { return Ok(()); }
+
+        let mut table = vec![];
+        table.write_u32::<BigEndian>(self.indices.len() as u32)?;
+        for (Vector3I(x, y, z), entry) in self.indices.iter() {
+            table.write_i32::<BigEndian>(*x as i32)?;
+            table.write_i32::<BigEndian>(*y as i32)?;
+            table.write_i32::<BigEndian>(*z as i32)?;
+            table.write_u64::<BigEndian>(entry.offset)?;
+            table.write_u32::<BigEndian>(entry.length)?;
+            table.write_u32::<BigEndian>(entry.crc)?;
+        }
+        let meta = encode_meta(&self.meta)?;
+        table.write_u32::<BigEndian>(meta.len() as u32)?;
+        table.write_all(&meta)?;
+
+        let offset = self.allocate(table.len() as u64);
+        let slot = match self.commit_index(offset, &table) {
+            Ok(slot) => slot,
+            Err(e) => {
+                // the live slot still points at the old table; if the new slot did reach the
+                // disk, its crc stops matching once this space is reused and recovery skips it
+                self.release(offset, table.len() as u64);
+                return Err(e);
             }
-            chunk
-        } else {
-            Chunk::generate_new(x, y, z)
+        };
+
+        // the old index is only garbage now that the new one is committed
+        let (old_offset, old_len) = std::mem::replace(&mut self.index_extent, (offset, table.len() as u64));
+        self.pending_free.push((old_offset, old_len));
+        self.generation = slot.generation;
+        self.index_dirty = false;
+        for (offset, len) in std::mem::take(&mut self.pending_free) {
+            self.release(offset, len);
         }
+        self.file.set_len(self.end)?;
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+impl Drop for SingleWorldFile {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            log::error!("Could not flush Single world file: {}", e);
+        }
+    }
+}
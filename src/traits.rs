@@ -1,6 +1,9 @@
 use crate::client::{Client, Error};
+use crate::item::{self, ItemStack};
 use std::mem::{transmute, size_of};
 use std::fmt::Debug;
+use std::io::{Cursor, ErrorKind, Read};
+use nbt::{Blob, NBTRead, NBTWrite};
 
 pub trait Packet: Debug {
     fn id(&self) -> u32;
@@ -21,7 +24,7 @@ pub trait Writable {
 
     fn write_i16(&mut self, value: i16) -> Result<usize, Error> {
         unsafe {
-            let array = &mut transmute::<i16, [u8; size_of::<i16>()]>(value.to_be());
+            let array = &mut transmute::<i16, [u8; size_of::<i16>()]>(value);
             if cfg!(target_endian = "little") { array.reverse() }
             Ok(self.write(array)?)
         }
@@ -29,7 +32,7 @@ pub trait Writable {
 
     fn write_i32(&mut self, value: i32) -> Result<usize, Error> {
         unsafe {
-            let array = &mut transmute::<i32, [u8; size_of::<i32>()]>(value.to_be());
+            let array = &mut transmute::<i32, [u8; size_of::<i32>()]>(value);
             if cfg!(target_endian = "little") { array.reverse() }
             Ok(self.write(array)?)
         }
@@ -37,7 +40,7 @@ pub trait Writable {
 
     fn write_i64(&mut self, value: i64) -> Result<usize, Error> {
         unsafe {
-            let array = &mut transmute::<i64, [u8; size_of::<i64>()]>(value.to_be());
+            let array = &mut transmute::<i64, [u8; size_of::<i64>()]>(value);
             if cfg!(target_endian = "little") { array.reverse() }
             Ok(self.write(array)?)
         }
@@ -45,7 +48,7 @@ pub trait Writable {
 
     fn write_i128(&mut self, value: i128) -> Result<usize, Error> {
         unsafe {
-            let array = &mut transmute::<i128, [u8; size_of::<i128>()]>(value.to_be());
+            let array = &mut transmute::<i128, [u8; size_of::<i128>()]>(value);
             if cfg!(target_endian = "little") { array.reverse() }
             Ok(self.write(array)?)
         }
@@ -60,7 +63,7 @@ pub trait Writable {
 
     fn write_u16(&mut self, value: u16) -> Result<usize, Error> {
         unsafe {
-            let array = &mut transmute::<u16, [u8; size_of::<u16>()]>(value.to_be());
+            let array = &mut transmute::<u16, [u8; size_of::<u16>()]>(value);
             if cfg!(target_endian = "little") { array.reverse() }
             Ok(self.write(array)?)
         }
@@ -68,7 +71,7 @@ pub trait Writable {
 
     fn write_u32(&mut self, value: u32) -> Result<usize, Error> {
         unsafe {
-            let array = &mut transmute::<u32, [u8; size_of::<u32>()]>(value.to_be());
+            let array = &mut transmute::<u32, [u8; size_of::<u32>()]>(value);
             if cfg!(target_endian = "little") { array.reverse() }
             Ok(self.write(array)?)
         }
@@ -76,7 +79,7 @@ pub trait Writable {
 
     fn write_u64(&mut self, value: u64) -> Result<usize, Error> {
         unsafe {
-            let array = &mut transmute::<u64, [u8; size_of::<u64>()]>(value.to_be());
+            let array = &mut transmute::<u64, [u8; size_of::<u64>()]>(value);
             if cfg!(target_endian = "little") { array.reverse() }
             Ok(self.write(array)?)
         }
@@ -84,7 +87,7 @@ pub trait Writable {
 
     fn write_u128(&mut self, value: u128) -> Result<usize, Error> {
         unsafe {
-            let array = &mut transmute::<u128, [u8; size_of::<u128>()]>(value.to_be());
+            let array = &mut transmute::<u128, [u8; size_of::<u128>()]>(value);
             if cfg!(target_endian = "little") { array.reverse() }
             Ok(self.write(array)?)
         }
@@ -92,7 +95,7 @@ pub trait Writable {
 
     fn write_usize(&mut self, value: usize) -> Result<usize, Error> {
         unsafe {
-            let array = &mut transmute::<usize, [u8; size_of::<usize>()]>(value.to_be());
+            let array = &mut transmute::<usize, [u8; size_of::<usize>()]>(value);
             if cfg!(target_endian = "little") { array.reverse() }
             Ok(self.write(array)?)
         }
@@ -100,18 +103,18 @@ pub trait Writable {
 
     fn write_isize(&mut self, value: isize) -> Result<usize, Error> {
         unsafe {
-            let array = &mut transmute::<isize, [u8; size_of::<isize>()]>(value.to_be());
+            let array = &mut transmute::<isize, [u8; size_of::<isize>()]>(value);
             if cfg!(target_endian = "little") { array.reverse() }
             Ok(self.write(array)?)
         }
     }
 
-    fn write_var_int(&mut self, mut value: i32) -> Result<usize, Error> {
+    fn write_var_int(&mut self, value: i32) -> Result<usize, Error> {
+        let mut value = value as u32;
         let mut count = 0usize;
         loop {
-            let mut u = (value & 0b01111111i32) as u8;
+            let mut u = (value & 0b01111111) as u8;
             count += 1;
-            value &= !0b01111111i32;
             value >>= 7;
             if value != 0 { u |= 0b10000000; }
             self.write_u8(u)?;
@@ -120,24 +123,60 @@ pub trait Writable {
         Ok(count)
     }
 
-    fn write_var_long(&mut self, mut value: i64) -> Result<usize, Error> {
+    fn write_var_long(&mut self, value: i64) -> Result<usize, Error> {
+        let mut value = value as u64;
         let mut count = 0usize;
         loop {
-            let u = (value & 0b01111111i64) as u8;
-            self.write_u8(u)?;
+            let mut u = (value & 0b01111111u64) as u8;
             count += 1;
-            value &= !0b01111111i64;
             value >>= 7;
+            if value != 0 { u |= 0b10000000; }
+            self.write_u8(u)?;
             if value == 0 { break; }
         }
         Ok(count)
     }
 
+    fn write_f32(&mut self, value: f32) -> Result<usize, Error> {
+        self.write_u32(value.to_bits())
+    }
+
+    fn write_f64(&mut self, value: f64) -> Result<usize, Error> {
+        self.write_u64(value.to_bits())
+    }
+
     fn write_string(&mut self, value: String) -> Result<usize, Error> {
         let mut size = self.write_var_int(value.len() as i32)?;
         size += self.write(value.as_bytes())?;
         Ok(size)
     }
+
+    /// A block position packed into a long: 26 bits of X, 26 of Z and 12 of Y.
+    fn write_position(&mut self, x: i64, y: i64, z: i64) -> Result<usize, Error> {
+        self.write_i64((x & 0x3FFFFFF) << 38 | (z & 0x3FFFFFF) << 12 | (y & 0xFFF))
+    }
+
+    /// Whether the slot has anything in it, then the item's id, how many there are and their tag
+    /// as an unnamed compound, or an end tag if there's none.
+    fn write_slot(&mut self, slot: Option<&ItemStack>) -> Result<usize, Error> {
+        let stack = match slot {
+            Some(stack) if stack.count > 0 => stack,
+            _ => return self.write_u8(0)
+        };
+        let mut size = self.write_u8(1)?;
+        size += self.write_var_int(stack.item)?;
+        size += self.write_i8(stack.count)?;
+        match &stack.nbt {
+            Some(nbt) => {
+                let blob = Blob { root: String::new(), elements: item::clone_compound(nbt) };
+                let bytes = blob.bytes()
+                    .map_err(|e| Error::IoError(std::io::Error::new(ErrorKind::InvalidData, format!("{}", e))))?;
+                size += self.write(&bytes)?;
+            }
+            None => size += self.write_u8(0)?
+        }
+        Ok(size)
+    }
 }
 
 pub trait Readable {
@@ -225,26 +264,26 @@ pub trait Readable {
         unsafe { Ok(transmute(a)) }
     }
 
+    /// Groups of seven bits, lowest first, in at most five bytes.
     fn read_var_int(&mut self) -> Result<i32, Error> {
-        let mut i = 0i32;
-        loop {
+        let mut i = 0u32;
+        for shift in (0..35).step_by(7) {
             let u = self.read_u8()?;
-            i <<= 7;
-            i |= (u & !0b10000000) as i32;
-            if u & 0b10000000 == 0 { break; }
+            i |= ((u & !0b10000000) as u32) << shift;
+            if u & 0b10000000 == 0 { return Ok(i as i32); }
         }
-        Ok(i)
+        Err(Error::IoError(std::io::Error::new(std::io::ErrorKind::InvalidData, "VarInt is too long")))
     }
 
+    /// Like `read_var_int`, in at most ten bytes.
     fn read_var_long(&mut self) -> Result<i64, Error> {
-        let mut i = 0i64;
-        loop {
+        let mut i = 0u64;
+        for shift in (0..70).step_by(7) {
             let u = self.read_u8()?;
-            i <<= 7;
-            i |= (u & !0b10000000) as i64;
-            if u & 0b10000000 == 0 { break; }
+            i |= ((u & !0b10000000) as u64) << shift;
+            if u & 0b10000000 == 0 { return Ok(i as i64); }
         }
-        Ok(i)
+        Err(Error::IoError(std::io::Error::new(std::io::ErrorKind::InvalidData, "VarLong is too long")))
     }
 
     fn read_string(&mut self, max_size: usize) -> Result<String, Error> {
@@ -257,4 +296,54 @@ pub trait Readable {
             Ok(String::from_utf8(chars).unwrap())
         }
     }
+
+    fn read_f32(&mut self) -> Result<f32, Error> {
+        Ok(f32::from_bits(self.read_u32()?))
+    }
+
+    fn read_f64(&mut self) -> Result<f64, Error> {
+        Ok(f64::from_bits(self.read_u64()?))
+    }
+
+    fn read_position(&mut self) -> Result<(i64, i64, i64), Error> {
+        let value = self.read_i64()?;
+        Ok((value >> 38, value << 52 >> 52, value << 26 >> 38))
+    }
+
+    /// Reads what `write_slot` writes. Slots of items the registry doesn't know, or of none of
+    /// them, come back empty like in vanilla.
+    fn read_slot(&mut self) -> Result<Option<ItemStack>, Error> {
+        if self.read_u8()? == 0 {
+            return Ok(None);
+        }
+        let item = self.read_var_int()?;
+        let count = self.read_i8()?;
+        let nbt = match self.read_u8()? {
+            0 => None,
+            ident => {
+                // nbt wants to read the tag type itself
+                let blob = Blob::read(&mut Cursor::new([ident]).chain(NbtReader(self)))
+                    .map_err(|e| Error::IoError(std::io::Error::new(ErrorKind::InvalidData, format!("{}", e))))?;
+                Some(blob.elements)
+            }
+        };
+        // air and empty stacks are empty slots too, however they were sent
+        if item == item::AIR || count <= 0 || item::get(item).is_none() {
+            return Ok(None);
+        }
+        Ok(Some(ItemStack { item, count, nbt }))
+    }
+}
+
+/// Lets nbt read from a `Readable`.
+struct NbtReader<'a, R: Readable + ?Sized>(&'a mut R);
+
+impl<R: Readable + ?Sized> Read for NbtReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.0.read(buf) {
+            Ok(read) => Ok(read),
+            Err(Error::IoError(e)) => Err(e),
+            Err(e) => Err(std::io::Error::new(ErrorKind::UnexpectedEof, format!("{}", e)))
+        }
+    }
 }
@@ -1,13 +1,283 @@
 use crate::Vector3I;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
 use ChunkLoadState::Loaded;
 use crate::world::ChunkLoadState::Unloaded;
 use std::ops::{DerefMut, Deref};
+use std::time::Instant;
+use std::fmt::{Display, Formatter};
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use sha2::{Sha256, Digest};
+use uuid::Uuid;
+use crate::generator::ChunkGenerator;
+use crate::biome::{self, BiomeId};
+use crate::dimension;
+use crate::light::{LightArray, LightKind, MAX_LIGHT};
+use crate::block_entity::BlockEntity;
+use crate::entity::{EntityChanges, EntityStore};
+use crate::item::ToolKind;
+use nbt::Tag;
+use crate::heightmap::{ChunkHeightmaps, ColumnHeightmaps, HeightmapKind};
+use std::ops::Range;
+#[cfg(feature = "world_syncers")] use crate::chunk_io::{ChunkIo, Completed};
 
-#[repr(u16)]
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
-pub enum Block {
-    Air
+macro_rules! blocks {
+    ($($variant:ident = $name:literal => $state:literal),* $(,)?) => {
+        /// Block ids are the enum discriminants and are written to world files, so new blocks
+        /// must only ever be appended.
+        #[repr(u16)]
+        #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+        pub enum Block {
+            $($variant),*
+        }
+
+        impl Block {
+            pub const ALL: &'static [Block] = &[$(Block::$variant),*];
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Block::$variant => $name),*
+                }
+            }
+
+            /// The vanilla 1.17 id of the block's default state, as sent to clients.
+            pub fn state_id(&self) -> u32 {
+                match self {
+                    $(Block::$variant => $state),*
+                }
+            }
+        }
+    }
+}
+
+blocks! {
+    Air = "minecraft:air" => 0,
+    Stone = "minecraft:stone" => 1,
+    Granite = "minecraft:granite" => 2,
+    PolishedGranite = "minecraft:polished_granite" => 3,
+    Diorite = "minecraft:diorite" => 4,
+    PolishedDiorite = "minecraft:polished_diorite" => 5,
+    Andesite = "minecraft:andesite" => 6,
+    PolishedAndesite = "minecraft:polished_andesite" => 7,
+    GrassBlock = "minecraft:grass_block" => 9,
+    Dirt = "minecraft:dirt" => 10,
+    CoarseDirt = "minecraft:coarse_dirt" => 11,
+    Podzol = "minecraft:podzol" => 13,
+    Cobblestone = "minecraft:cobblestone" => 14,
+    OakPlanks = "minecraft:oak_planks" => 15,
+    SprucePlanks = "minecraft:spruce_planks" => 16,
+    BirchPlanks = "minecraft:birch_planks" => 17,
+    JunglePlanks = "minecraft:jungle_planks" => 18,
+    AcaciaPlanks = "minecraft:acacia_planks" => 19,
+    DarkOakPlanks = "minecraft:dark_oak_planks" => 20,
+    Bedrock = "minecraft:bedrock" => 33,
+    Water = "minecraft:water" => 34,
+    Lava = "minecraft:lava" => 50,
+    Sand = "minecraft:sand" => 66,
+    RedSand = "minecraft:red_sand" => 67,
+    Gravel = "minecraft:gravel" => 68,
+    GoldOre = "minecraft:gold_ore" => 69,
+    DeepslateGoldOre = "minecraft:deepslate_gold_ore" => 70,
+    IronOre = "minecraft:iron_ore" => 71,
+    DeepslateIronOre = "minecraft:deepslate_iron_ore" => 72,
+    CoalOre = "minecraft:coal_ore" => 73,
+    DeepslateCoalOre = "minecraft:deepslate_coal_ore" => 74,
+    OakLog = "minecraft:oak_log" => 77,
+    SpruceLog = "minecraft:spruce_log" => 80,
+    BirchLog = "minecraft:birch_log" => 83,
+    JungleLog = "minecraft:jungle_log" => 86,
+    AcaciaLog = "minecraft:acacia_log" => 89,
+    DarkOakLog = "minecraft:dark_oak_log" => 92,
+    OakLeaves = "minecraft:oak_leaves" => 161,
+    SpruceLeaves = "minecraft:spruce_leaves" => 175,
+    BirchLeaves = "minecraft:birch_leaves" => 189,
+    JungleLeaves = "minecraft:jungle_leaves" => 203,
+    AcaciaLeaves = "minecraft:acacia_leaves" => 217,
+    DarkOakLeaves = "minecraft:dark_oak_leaves" => 231,
+    Glass = "minecraft:glass" => 262,
+    LapisOre = "minecraft:lapis_ore" => 263,
+    Sandstone = "minecraft:sandstone" => 278,
+    WhiteWool = "minecraft:white_wool" => 1440,
+    GoldBlock = "minecraft:gold_block" => 1483,
+    IronBlock = "minecraft:iron_block" => 1484,
+    Bricks = "minecraft:bricks" => 1485,
+    Tnt = "minecraft:tnt" => 1487,
+    Bookshelf = "minecraft:bookshelf" => 1488,
+    MossyCobblestone = "minecraft:mossy_cobblestone" => 1489,
+    Obsidian = "minecraft:obsidian" => 1490,
+    Torch = "minecraft:torch" => 1491,
+    Spawner = "minecraft:spawner" => 2009,
+    Chest = "minecraft:chest" => 2091,
+    DiamondOre = "minecraft:diamond_ore" => 3410,
+    DiamondBlock = "minecraft:diamond_block" => 3412,
+    CraftingTable = "minecraft:crafting_table" => 3413,
+    Furnace = "minecraft:furnace" => 3431,
+    OakSign = "minecraft:oak_sign" => 3439,
+    RedstoneOre = "minecraft:redstone_ore" => 3953,
+    Snow = "minecraft:snow" => 3990,
+    Ice = "minecraft:ice" => 3998,
+    SnowBlock = "minecraft:snow_block" => 3999,
+    Cactus = "minecraft:cactus" => 4000,
+    Clay = "minecraft:clay" => 4016,
+    Pumpkin = "minecraft:pumpkin" => 4067,
+    Netherrack = "minecraft:netherrack" => 4068,
+    SoulSand = "minecraft:soul_sand" => 4069,
+    Glowstone = "minecraft:glowstone" => 4082,
+    WhiteBanner = "minecraft:white_banner" => 8147,
+    Deepslate = "minecraft:deepslate" => 18684,
+    Tuff = "minecraft:tuff" => 17714,
+    EmeraldOre = "minecraft:emerald_ore" => 5455,
+    CopperOre = "minecraft:copper_ore" => 17818,
+    Grass = "minecraft:grass" => 1398,
+    TallGrass = "minecraft:tall_grass" => 8144,
+    Dandelion = "minecraft:dandelion" => 1468,
+    Poppy = "minecraft:poppy" => 1469,
+    SugarCane = "minecraft:sugar_cane" => 4017,
+    Terracotta = "minecraft:terracotta" => 8132,
+    PackedIce = "minecraft:packed_ice" => 8134,
+    Seagrass = "minecraft:seagrass" => 1401,
+    KelpPlant = "minecraft:kelp_plant" => 9746,
+    Calcite = "minecraft:calcite" => 17715,
+}
+
+/// A box from its low corner to its high corner.
+pub type BoundingBox = ((f64, f64, f64), (f64, f64, f64));
+
+impl Block {
+    pub fn from_id(id: u16) -> Option<Block> {
+        Block::ALL.get(id as usize).copied()
+    }
+
+    pub fn from_name(name: &str) -> Option<Block> {
+        Block::ALL.iter().copied().find(|b| b.name() == name)
+    }
+
+    /// How much light going through the block loses, like vanilla's `getLightBlock`: everything
+    /// for full solid blocks, nothing for see-through ones and one level for the rest.
+    pub fn light_opacity(&self) -> u8 {
+        match self {
+            Block::Air | Block::Glass | Block::Torch | Block::OakSign | Block::WhiteBanner | Block::Snow
+            | Block::Grass | Block::TallGrass | Block::Dandelion | Block::Poppy | Block::SugarCane
+            | Block::Chest | Block::Cactus => 0,
+            Block::Water | Block::Lava | Block::Ice | Block::Spawner | Block::Seagrass | Block::KelpPlant
+            | Block::OakLeaves | Block::SpruceLeaves | Block::BirchLeaves | Block::JungleLeaves
+            | Block::AcaciaLeaves | Block::DarkOakLeaves => 1,
+            _ => MAX_LIGHT
+        }
+    }
+
+    /// The light level the block gives off.
+    pub fn light_emission(&self) -> u8 {
+        match self {
+            Block::Glowstone | Block::Lava => 15,
+            Block::Torch => 14,
+            _ => 0
+        }
+    }
+
+    /// How long the block takes to dig, like vanilla's destroy time: 1.5 for stone and 0.5 for
+    /// dirt. `None` for blocks players can't dig at all.
+    pub fn hardness(&self) -> Option<f32> {
+        Some(match self {
+            Block::Air | Block::Bedrock | Block::Water | Block::Lava => return None,
+            Block::Tnt | Block::Torch | Block::Grass | Block::TallGrass | Block::Dandelion | Block::Poppy
+            | Block::SugarCane | Block::Seagrass | Block::KelpPlant => 0.0,
+            Block::Snow => 0.1,
+            Block::OakLeaves | Block::SpruceLeaves | Block::BirchLeaves | Block::JungleLeaves
+            | Block::AcaciaLeaves | Block::DarkOakLeaves | Block::SnowBlock => 0.2,
+            Block::Glass | Block::Glowstone => 0.3,
+            Block::Cactus | Block::Netherrack => 0.4,
+            Block::Dirt | Block::CoarseDirt | Block::Podzol | Block::Sand | Block::RedSand | Block::Ice
+            | Block::PackedIce | Block::SoulSand => 0.5,
+            Block::GrassBlock | Block::Gravel | Block::Clay => 0.6,
+            Block::Calcite => 0.75,
+            Block::Sandstone | Block::WhiteWool => 0.8,
+            Block::OakSign | Block::WhiteBanner | Block::Pumpkin => 1.0,
+            Block::Terracotta => 1.25,
+            Block::Stone | Block::Granite | Block::PolishedGranite | Block::Diorite | Block::PolishedDiorite
+            | Block::Andesite | Block::PolishedAndesite | Block::Bookshelf | Block::Tuff => 1.5,
+            Block::Cobblestone | Block::MossyCobblestone | Block::Bricks | Block::OakPlanks | Block::SprucePlanks
+            | Block::BirchPlanks | Block::JunglePlanks | Block::AcaciaPlanks | Block::DarkOakPlanks
+            | Block::OakLog | Block::SpruceLog | Block::BirchLog | Block::JungleLog | Block::AcaciaLog
+            | Block::DarkOakLog => 2.0,
+            Block::Chest | Block::CraftingTable => 2.5,
+            Block::GoldOre | Block::IronOre | Block::CoalOre | Block::LapisOre | Block::DiamondOre
+            | Block::RedstoneOre | Block::EmeraldOre | Block::CopperOre | Block::GoldBlock | Block::Deepslate => 3.0,
+            Block::Furnace => 3.5,
+            Block::DeepslateGoldOre | Block::DeepslateIronOre | Block::DeepslateCoalOre => 4.5,
+            Block::IronBlock | Block::DiamondBlock | Block::Spawner => 5.0,
+            Block::Obsidian => 50.0
+        })
+    }
+
+    /// The kind of tool that digs the block faster, if any. Blocks that want a pickaxe drop
+    /// nothing without one, so they're slower still by hand, like vanilla.
+    pub fn tool(&self) -> Option<ToolKind> {
+        match self {
+            Block::Stone | Block::Granite | Block::PolishedGranite | Block::Diorite | Block::PolishedDiorite
+            | Block::Andesite | Block::PolishedAndesite | Block::Cobblestone | Block::MossyCobblestone
+            | Block::GoldOre | Block::DeepslateGoldOre | Block::IronOre | Block::DeepslateIronOre | Block::CoalOre
+            | Block::DeepslateCoalOre | Block::LapisOre | Block::DiamondOre | Block::RedstoneOre
+            | Block::EmeraldOre | Block::CopperOre | Block::Sandstone | Block::GoldBlock | Block::IronBlock
+            | Block::DiamondBlock | Block::Bricks | Block::Obsidian | Block::Spawner | Block::Furnace
+            | Block::Ice | Block::PackedIce | Block::Netherrack | Block::Terracotta | Block::Deepslate
+            | Block::Tuff | Block::Calcite => Some(ToolKind::Pickaxe),
+            Block::GrassBlock | Block::Dirt | Block::CoarseDirt | Block::Podzol | Block::Sand | Block::RedSand
+            | Block::Gravel | Block::Snow | Block::SnowBlock | Block::Clay | Block::SoulSand => Some(ToolKind::Shovel),
+            Block::OakPlanks | Block::SprucePlanks | Block::BirchPlanks | Block::JunglePlanks | Block::AcaciaPlanks
+            | Block::DarkOakPlanks | Block::OakLog | Block::SpruceLog | Block::BirchLog | Block::JungleLog
+            | Block::AcaciaLog | Block::DarkOakLog | Block::Bookshelf | Block::Chest | Block::CraftingTable
+            | Block::OakSign | Block::Pumpkin | Block::WhiteBanner => Some(ToolKind::Axe),
+            Block::OakLeaves | Block::SpruceLeaves | Block::BirchLeaves | Block::JungleLeaves
+            | Block::AcaciaLeaves | Block::DarkOakLeaves => Some(ToolKind::Hoe),
+            _ => None
+        }
+    }
+
+    /// Whether the block breaks as soon as a player starts digging it.
+    pub fn breaks_instantly(&self) -> bool {
+        self.hardness() == Some(0.0)
+    }
+
+    /// Whether players can dig the block at all.
+    pub fn is_breakable(&self) -> bool {
+        self.hardness().is_some()
+    }
+
+    /// Whether placing a block where this one is replaces it, instead of going next to it.
+    pub fn is_replaceable(&self) -> bool {
+        matches!(self, Block::Air | Block::Water | Block::Lava | Block::Grass | Block::TallGrass
+            | Block::Seagrass | Block::Snow)
+    }
+
+    /// Whether entities collide with the block, like vanilla's `Material::blocksMotion`.
+    pub fn blocks_motion(&self) -> bool {
+        !matches!(self,
+            Block::Air | Block::Water | Block::Lava | Block::Torch | Block::OakSign | Block::WhiteBanner
+            | Block::Snow | Block::Grass | Block::TallGrass | Block::Dandelion | Block::Poppy
+            | Block::SugarCane | Block::Seagrass | Block::KelpPlant)
+    }
+
+    /// The part of its space entities collide with, as the low and high corners from 0 to 1
+    /// along each axis, or `None` if they go right through.
+    pub fn collision_box(&self) -> Option<BoundingBox> {
+        const PIXEL: f64 = 1.0 / 16.0;
+        if !self.blocks_motion() {
+            return None;
+        }
+        Some(match self {
+            Block::Chest => ((PIXEL, 0.0, PIXEL), (15.0 * PIXEL, 14.0 * PIXEL, 15.0 * PIXEL)),
+            Block::Cactus => ((PIXEL, 0.0, PIXEL), (15.0 * PIXEL, 15.0 * PIXEL, 15.0 * PIXEL)),
+            Block::SoulSand => ((0.0, 0.0, 0.0), (1.0, 14.0 * PIXEL, 1.0)),
+            _ => ((0.0, 0.0, 0.0), (1.0, 1.0, 1.0))
+        })
+    }
+
+    /// Whether the block is or contains a fluid, counting plants that only grow underwater.
+    pub fn holds_fluid(&self) -> bool {
+        matches!(self, Block::Water | Block::Lava | Block::Seagrass | Block::KelpPlant)
+    }
 }
 
 pub trait BlockContainer {
@@ -19,28 +289,108 @@ pub trait ChunkContainer {
     //noinspection RsSelfConvention
     fn get_chunk(&mut self, x: i32, y: i32, z: i32) -> &mut Chunk;
     fn put_chunk(&mut self, x: i32, y: i32, z: i32, chunk: Chunk);
-    fn get(&mut self, x: i64, y: i64, z: i64) -> Block;
-    fn put(&mut self, x: i64, y: i64, z: i64, block: Block);
+    fn get(&mut self, x: i64, y: i64, z: i64) -> Result<Block, WorldError>;
+    fn put(&mut self, x: i64, y: i64, z: i64, block: Block) -> Result<(), WorldError>;
 }
 
 #[cfg(feature = "world_syncers")]
-pub trait WorldSyncer {
-    fn new(path: &str) -> Self where Self: Sized;
-    fn save(&mut self, chunk: &mut Chunk);
-    fn find_all(&mut self) -> Vec<Vector3I>;
-    fn load(&mut self, x: i32, y: i32, z: i32) -> Chunk;
+pub trait WorldSyncer: Send {
+    fn new(path: &str) -> Result<Self, WorldError> where Self: Sized;
+    /// Opens the world at `path` without ever writing to it: nothing is created, upgraded or
+    /// repaired, and saving is `WorldError::ReadOnly`.
+    fn open_read_only(path: &str) -> Result<Self, WorldError> where Self: Sized;
+    fn save(&mut self, chunk: &mut Chunk) -> Result<(), WorldError>;
+    fn find_all(&mut self) -> Result<Vec<Vector3I>, WorldError>;
+    /// Reads a stored chunk; chunks that were never saved are `WorldError::MissingChunk`.
+    fn load(&mut self, x: i32, y: i32, z: i32) -> Result<Chunk, WorldError>;
+    /// Reads the world-wide data; worlds that never had any saved give the defaults.
+    fn load_meta(&mut self) -> Result<WorldMeta, WorldError>;
+    /// Stores the world-wide data. Like chunks, it's only durable after the next `flush`.
+    fn save_meta(&mut self, meta: &WorldMeta) -> Result<(), WorldError>;
+    /// Makes everything saved so far durable; syncers that write through can leave this alone.
+    fn flush(&mut self) -> Result<(), WorldError> { Ok(()) }
+    /// Tells the syncer which sections the world's dimension has, before anything is read. Only
+    /// formats that lay out whole columns need it.
+    fn set_height(&mut self, _min_section: i32, _sections: i32) {}
+    /// Throws away everything saved since the syncer was opened, if it keeps that apart from
+    /// what it was opened with. Gives back whether it did.
+    fn reset(&mut self) -> bool { false }
 }
 
+/// Data about a whole world that syncers keep next to its chunks.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WorldMeta {
+    pub seed: Option<i64>
+}
+
+#[derive(Debug)]
+pub enum WorldError {
+    Io(std::io::Error),
+    Corrupt(String),
+    VersionMismatch { found: u32, expected: u32 },
+    MissingChunk(Vector3I),
+    /// A block above or below what the dimension has room for.
+    OutOfHeight(i64),
+    /// A write to a world that was opened read-only.
+    ReadOnly
+}
+
+impl Display for WorldError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorldError::Io(e) => write!(f, "I/O error: {}", e),
+            WorldError::Corrupt(why) => write!(f, "corrupt world data: {}", why),
+            WorldError::VersionMismatch { found, expected } =>
+                write!(f, "world format version {} (expected {})", found, expected),
+            WorldError::MissingChunk(Vector3I(x, y, z)) => write!(f, "chunk [{},{},{}] is not stored", x, y, z),
+            WorldError::OutOfHeight(y) => write!(f, "Y {} is outside the world", y),
+            WorldError::ReadOnly => write!(f, "the world was opened read-only")
+        }
+    }
+}
+
+impl From<std::io::Error> for WorldError {
+    fn from(e: std::io::Error) -> Self {
+        // Running out of file mid-structure means the data is bad, not the disk.
+        if e.kind() == ErrorKind::UnexpectedEof {
+            WorldError::Corrupt("unexpected end of file".to_string())
+        } else {
+            WorldError::Io(e)
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Chunk {
     pub x: i32,
     pub y: i32,
     pub z: i32,
-    pub(crate) blocks: [Block; 16*16*16]
+    pub(crate) blocks: [Block; 16*16*16],
+    /// One biome per 4x4x4 cell, indexed `(y * 4 + z) * 4 + x` like vanilla.
+    pub(crate) biomes: [BiomeId; 4*4*4],
+    /// Kept up to date by `put`; anything writing `blocks` directly has to recompute it.
+    pub(crate) heightmaps: ChunkHeightmaps,
+    pub(crate) sky_light: LightArray,
+    pub(crate) block_light: LightArray,
+    /// Whether the light arrays have been worked out. The world lights chunks as their columns
+    /// load and keeps them up to date from then on.
+    pub(crate) lit: bool,
+    /// Block entities by chunk-relative `x`, `y`, `z`. `put` adds and removes them as blocks
+    /// change, so each one always fits the block it's at.
+    pub(crate) block_entities: HashMap<(u8, u8, u8), BlockEntity>,
+    /// Set whenever the chunk differs from what its syncer last saved.
+    pub(crate) dirty: bool
 }
 
 impl Chunk {
     pub fn generate_new(x: i32, y: i32, z: i32) -> Chunk {
-        Chunk::new_empty(x, y, z)
+        let mut chunk = Chunk::new_empty(x, y, z);
+        chunk.dirty = true; // never been written out
+        chunk
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
     }
 }
 
@@ -49,27 +399,178 @@ pub(crate) enum ChunkLoadState {
     Loaded { chunk: Chunk }
 }
 
+/// Where players spawn in a column with no blocks.
+const SPAWN_Y_FALLBACK: i64 = 64;
+
+/// Blocks changed in a section, by chunk-relative position.
+pub type SectionChanges = Vec<((u8, u8, u8), Block)>;
+
+/// Called on the world's thread once a requested chunk is loaded, or with why it couldn't be.
+pub type ChunkCallback = Box<dyn FnOnce(&mut World, Result<(), &WorldError>) + Send>;
+
 pub struct World {
+    /// What the world is called, like `minecraft:overworld`; sent to clients as the world name.
+    name: String,
+    /// Registered name of the dimension type clients are told the world has.
+    dimension_type: String,
+    /// Players currently in this world.
+    players: Vec<Uuid>,
+    /// Section Y of the bottom of the world, from the dimension type's `min_y`.
+    min_section: i32,
+    /// How many sections tall the world is.
+    sections: i32,
     pub(crate) chunks: HashMap<Vector3I, ChunkLoadState>,
-    pub sync: Box<dyn WorldSyncer>
+    seed: i64,
+    io: ChunkIo,
+    /// Chunks being loaded in the background and who's waiting on them.
+    waiting: HashMap<Vector3I, Vec<ChunkCallback>>,
+    /// Heightmaps of columns that have been loaded whole, kept up to date as blocks change.
+    heightmaps: HashMap<(i32, i32), ColumnHeightmaps>,
+    /// Block entities changed since the last `take_block_entity_updates`, for players to be told.
+    block_entity_updates: Vec<(i64, i64, i64)>,
+    /// Blocks changed since the last `take_block_changes`, by section and index in it.
+    block_changes: HashMap<Vector3I, HashSet<u16>>,
+    /// The columns each player has been sent.
+    tracked: HashMap<Uuid, HashSet<(i32, i32)>>,
+    /// Who has each container open, and as which of their windows.
+    viewers: HashMap<(i64, i64, i64), Vec<(Uuid, u8)>>,
+    entities: EntityStore
 }
 
 impl Chunk {
     pub fn new_empty(x: i32, y: i32, z: i32) -> Chunk {
         Chunk {
             x, y, z,
-            blocks: [Block::Air; 16*16*16]
+            blocks: [Block::Air; 16*16*16],
+            biomes: [biome::default_id(); 4*4*4],
+            heightmaps: ChunkHeightmaps::empty(),
+            sky_light: LightArray::empty(),
+            block_light: LightArray::empty(),
+            lit: false,
+            block_entities: HashMap::new(),
+            dirty: false
         }
     }
 
     pub fn new_from_block(x: i32, y: i32, z: i32, block: Block) -> Chunk {
         Chunk {
             x, y, z,
-            blocks: [block; 16*16*16]
+            blocks: [block; 16*16*16],
+            biomes: [biome::default_id(); 4*4*4],
+            heightmaps: ChunkHeightmaps::compute(&[block; 16*16*16]),
+            sky_light: LightArray::empty(),
+            block_light: LightArray::empty(),
+            lit: false,
+            block_entities: HashMap::new(),
+            dirty: false
+        }
+    }
+
+    /// Biome of the cell holding the block at `x`, `y`, `z` inside the chunk.
+    pub fn get_biome(&self, x: i64, y: i64, z: i64) -> BiomeId {
+        self.biomes[biome_index(x, y, z)]
+    }
+
+    pub fn set_biome(&mut self, x: i64, y: i64, z: i64, biome: BiomeId) {
+        self.biomes[biome_index(x, y, z)] = biome;
+        self.dirty = true;
+    }
+
+    pub fn fill_biome(&mut self, biome: BiomeId) {
+        self.biomes = [biome; 4*4*4];
+        self.dirty = true;
+    }
+
+    pub fn heightmaps(&self) -> &ChunkHeightmaps {
+        &self.heightmaps
+    }
+
+    pub fn light(&self, kind: LightKind) -> &LightArray {
+        match kind {
+            LightKind::Sky => &self.sky_light,
+            LightKind::Block => &self.block_light
+        }
+    }
+
+    pub(crate) fn light_mut(&mut self, kind: LightKind) -> &mut LightArray {
+        match kind {
+            LightKind::Sky => &mut self.sky_light,
+            LightKind::Block => &mut self.block_light
+        }
+    }
+
+    pub fn is_lit(&self) -> bool {
+        self.lit
+    }
+
+    /// The block entity at chunk-relative `x`, `y`, `z`.
+    pub fn block_entity(&self, x: u8, y: u8, z: u8) -> Option<&BlockEntity> {
+        self.block_entities.get(&(x, y, z))
+    }
+
+    /// Replaces the block entity at chunk-relative `x`, `y`, `z`, unless it doesn't fit the block
+    /// there; gives back whether it did.
+    pub fn set_block_entity(&mut self, x: u8, y: u8, z: u8, entity: BlockEntity) -> bool {
+        let block = self.blocks[(y as usize) << 8 | (z as usize) << 4 | x as usize];
+        if !entity.fits(block) {
+            return false;
+        }
+        self.block_entities.insert((x, y, z), entity);
+        self.dirty = true;
+        true
+    }
+
+    pub fn block_entities(&self) -> impl Iterator<Item = (&(u8, u8, u8), &BlockEntity)> {
+        self.block_entities.iter()
+    }
+
+    /// World position of the chunk-relative `x`, `y`, `z`.
+    pub fn world_pos(&self, x: u8, y: u8, z: u8) -> (i64, i64, i64) {
+        (self.x as i64 * 16 + x as i64, self.y as i64 * 16 + y as i64, self.z as i64 * 16 + z as i64)
+    }
+
+    /// Adds a stored block entity, warning about and skipping ones that can't be read or aren't in
+    /// this chunk.
+    pub(crate) fn load_block_entity(&mut self, nbt: HashMap<String, Tag>) {
+        let ((x, y, z), entity) = match BlockEntity::from_nbt(nbt) {
+            Ok(entity) => entity,
+            Err(e) => {
+                log::warn!("Skipping a block entity in chunk [{},{},{}]: {}", self.x, self.y, self.z, e);
+                return;
+            }
+        };
+        if (x.div_euclid(16), y.div_euclid(16), z.div_euclid(16)) != (self.x as i64, self.y as i64, self.z as i64) {
+            log::warn!("Skipping {} at [{},{},{}], it's outside chunk [{},{},{}]", entity.id(), x, y, z, self.x, self.y, self.z);
+            return;
         }
+        self.block_entities.insert((x.rem_euclid(16) as u8, y.rem_euclid(16) as u8, z.rem_euclid(16) as u8), entity);
+    }
+
+    /// Makes the block entities match the blocks after both were read separately: ones that
+    /// don't fit are dropped and missing ones are made fresh.
+    pub(crate) fn repair_block_entities(&mut self) {
+        let blocks = &self.blocks;
+        self.block_entities.retain(|(x, y, z), entity| entity.fits(blocks[(*y as usize) << 8 | (*z as usize) << 4 | *x as usize]));
+        for (i, block) in self.blocks.iter().enumerate() {
+            let pos = ((i & 15) as u8, (i >> 8) as u8, ((i >> 4) & 15) as u8);
+            if let (Entry::Vacant(slot), Some(entity)) = (self.block_entities.entry(pos), BlockEntity::for_block(*block)) {
+                slot.insert(entity);
+            }
+        }
+    }
+
+    pub(crate) fn recompute_heightmaps(&mut self) {
+        self.heightmaps = ChunkHeightmaps::compute(&self.blocks);
     }
 }
 
+fn biome_index(x: i64, y: i64, z: i64) -> usize {
+    if !(0..16).contains(&x) || !(0..16).contains(&y) || !(0..16).contains(&z) {
+        panic!("Invalid position in chunk [{},{},{}]", x, y, z);
+    }
+    (((y / 4) * 4 + z / 4) * 4 + x / 4) as usize
+}
+
 impl BlockContainer for Chunk {
     fn get(&mut self, x: i64, y: i64, z: i64) -> Block {
         if x < 0 || x >= 16 || y < 0 || y >= 16 || z < 0 || z >= 16 {
@@ -105,36 +606,524 @@ impl BlockContainer for Chunk {
         log::debug!("{},{},{} = idx {}; now {:?}", x, y, z, i, block);
 
         self.blocks[i] = block;
+        self.heightmaps.update(&self.blocks, x as usize, y as usize, z as usize);
+        let pos = (x as u8, y as u8, z as u8);
+        match self.block_entities.get(&pos) {
+            Some(entity) if entity.fits(block) => {}
+            _ => match BlockEntity::for_block(block) {
+                Some(entity) => { self.block_entities.insert(pos, entity); }
+                None => { self.block_entities.remove(&pos); }
+            }
+        }
+        self.dirty = true;
     }
 }
 
 impl World {
-    pub fn new(mut sync: Box<dyn WorldSyncer>) -> World {
+    pub fn new(name: &str, dimension_type: &str, mut sync: Box<dyn WorldSyncer>, generator: Arc<dyn ChunkGenerator>,
+               io_threads: usize) -> Result<World, WorldError> {
+        let (min_y, height) = dimension::get(dimension_type).map_or((0, 256), |d| (d.min_y, d.height));
+        let (min_section, sections) = (min_y.div_euclid(16), height / 16);
+        sync.set_height(min_section, sections);
+
         let mut chunks = HashMap::new();
-        for p in sync.find_all() {
+        for p in sync.find_all()? {
             chunks.insert(p, Unloaded);
         }
-        World { chunks, sync }
+        let seed = sync.load_meta()?.seed.unwrap_or(0);
+        Ok(World {
+            name: name.to_string(),
+            dimension_type: dimension_type.to_string(),
+            players: vec![],
+            min_section,
+            sections,
+            chunks,
+            seed,
+            io: ChunkIo::new(sync, generator, min_section as i64 * 16, sections as i64 * 16, io_threads),
+            waiting: HashMap::new(),
+            heightmaps: HashMap::new(),
+            block_entity_updates: vec![],
+            block_changes: HashMap::new(),
+            tracked: HashMap::new(),
+            viewers: HashMap::new(),
+            entities: EntityStore::new()
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn dimension_type(&self) -> &str {
+        &self.dimension_type
+    }
+
+    pub fn players(&self) -> &[Uuid] {
+        &self.players
+    }
+
+    pub fn add_player(&mut self, uuid: Uuid) {
+        if !self.players.contains(&uuid) {
+            self.players.push(uuid);
+        }
+    }
+
+    /// Takes the player off the player list, along with their entity.
+    pub fn remove_player(&mut self, uuid: Uuid) {
+        self.players.retain(|p| *p != uuid);
+        self.tracked.remove(&uuid);
+        if let Some(id) = self.entities.by_uuid(uuid).map(|e| e.id) {
+            self.entities.remove(id);
+        }
+        self.entities.remove_viewer(uuid);
+        self.close_containers(uuid);
+    }
+
+    /// Notes that `player` has the container at `x`, `y`, `z` open as their window `window_id`,
+    /// so they see what others do with it.
+    pub fn open_container(&mut self, player: Uuid, window_id: u8, x: i64, y: i64, z: i64) {
+        self.close_containers(player);
+        self.viewers.entry((x, y, z)).or_default().push((player, window_id));
+    }
+
+    /// Forgets about whatever container `player` had open.
+    pub fn close_containers(&mut self, player: Uuid) {
+        self.viewers.retain(|_, viewers| {
+            viewers.retain(|(p, _)| *p != player);
+            !viewers.is_empty()
+        });
+    }
+
+    /// The players with the container at `x`, `y`, `z` open, with the window each has it as.
+    pub fn viewers(&self, x: i64, y: i64, z: i64) -> &[(Uuid, u8)] {
+        self.viewers.get(&(x, y, z)).map_or(&[], |v| v.as_slice())
+    }
+
+    /// Forgets who had containers that are gone open, giving back whose windows they were.
+    pub fn take_closed_containers(&mut self) -> Vec<(Uuid, u8)> {
+        let chunks = &self.chunks;
+        let mut closed = vec![];
+        self.viewers.retain(|(x, y, z), viewers| {
+            let pos = Vector3I(x.div_euclid(16), y.div_euclid(16), z.div_euclid(16));
+            let open = match chunks.get(&pos) {
+                Some(Loaded { chunk }) => chunk.block_entity(x.rem_euclid(16) as u8, y.rem_euclid(16) as u8, z.rem_euclid(16) as u8)
+                    .is_some_and(|entity| entity.window_type().is_some()),
+                _ => false
+            };
+            if !open {
+                closed.append(viewers);
+            }
+            open
+        });
+        closed
+    }
+
+    /// Runs a tick of every furnace in the loaded chunks, giving back where the ones that changed
+    /// are.
+    pub fn tick_furnaces(&mut self) -> Vec<(i64, i64, i64)> {
+        let mut changed = vec![];
+        for state in self.chunks.values_mut() {
+            let chunk = match state {
+                Loaded { chunk } => chunk,
+                Unloaded => continue
+            };
+            let mut ticked = vec![];
+            for (pos, entity) in chunk.block_entities.iter_mut() {
+                if let BlockEntity::Furnace(furnace) = entity {
+                    if furnace.tick() {
+                        ticked.push(*pos);
+                    }
+                }
+            }
+            if !ticked.is_empty() {
+                chunk.dirty = true;
+                changed.extend(ticked.into_iter().map(|(x, y, z)| chunk.world_pos(x, y, z)));
+            }
+        }
+        changed
+    }
+
+    pub fn entities(&self) -> &EntityStore {
+        &self.entities
+    }
+
+    pub fn entities_mut(&mut self) -> &mut EntityStore {
+        &mut self.entities
+    }
+
+    /// What players need to be told about the entities around them since the last call.
+    pub fn take_entity_changes(&mut self) -> EntityChanges {
+        self.entities.take_changes(&self.tracked)
+    }
+
+    /// Notes that `player` has the column at `x`, `z`, so they get told about changes to it.
+    pub fn track_column(&mut self, player: Uuid, x: i32, z: i32) {
+        self.tracked.entry(player).or_default().insert((x, z));
+    }
+
+    pub fn untrack_column(&mut self, player: Uuid, x: i32, z: i32) {
+        if let Some(columns) = self.tracked.get_mut(&player) {
+            columns.remove(&(x, z));
+        }
+    }
+
+    /// The columns `player` has.
+    pub fn tracked_columns(&self, player: Uuid) -> Vec<(i32, i32)> {
+        self.tracked.get(&player).map(|columns| columns.iter().copied().collect()).unwrap_or_default()
+    }
+
+    /// The players who have the column at `x`, `z`.
+    pub fn trackers(&self, x: i32, z: i32) -> Vec<Uuid> {
+        self.tracked.iter()
+            .filter(|(_, columns)| columns.contains(&(x, z)))
+            .map(|(player, _)| *player)
+            .collect()
+    }
+
+    /// The blocks changed since the last call, by section, as chunk-relative positions and what's
+    /// there now. Several changes to one block only show up once.
+    pub fn take_block_changes(&mut self) -> Vec<(Vector3I, SectionChanges)> {
+        let mut changes = vec![];
+        for (pos, indices) in std::mem::take(&mut self.block_changes) {
+            let chunk = match self.chunks.get(&pos) {
+                Some(Loaded { chunk }) => chunk,
+                _ => continue
+            };
+            let mut indices: Vec<_> = indices.into_iter().collect();
+            indices.sort_unstable();
+            let blocks = indices.into_iter()
+                .map(|i| (((i & 15) as u8, (i >> 8) as u8, ((i >> 4) & 15) as u8), chunk.blocks[i as usize]))
+                .collect();
+            changes.push((pos, blocks));
+        }
+        changes
+    }
+
+    /// The lowest block Y in the world.
+    pub fn min_y(&self) -> i64 {
+        self.min_section as i64 * 16
+    }
+
+    /// How many blocks tall the world is.
+    pub fn height(&self) -> i64 {
+        self.sections as i64 * 16
+    }
+
+    /// Section Ys of every section in a column, from the bottom up.
+    pub fn section_range(&self) -> Range<i32> {
+        self.min_section..self.min_section + self.sections
+    }
+
+    pub fn contains_y(&self, y: i64) -> bool {
+        (self.min_y()..self.min_y() + self.height()).contains(&y)
+    }
+
+    /// Loads every section of the column at `x`, `z` and gives them from the bottom up.
+    pub fn load_column(&mut self, x: i32, z: i32) -> Result<Vec<&Chunk>, WorldError> {
+        for y in self.section_range() {
+            self.load_chunk(x, y, z)?;
+        }
+        self.light_column(x, z);
+        if !self.heightmaps.contains_key(&(x, z)) {
+            let maps = self.loaded_column(x, z).map(|sections| {
+                let sections: Vec<_> = sections.iter().map(|c| c.heightmaps()).collect();
+                ColumnHeightmaps::combine(&sections, self.height())
+            });
+            if let Some(maps) = maps {
+                self.heightmaps.insert((x, z), maps);
+            }
+        }
+        Ok(self.loaded_column(x, z).expect("column was just loaded"))
+    }
+
+    /// The sections of the column at `x`, `z` from the bottom up, if they're all loaded.
+    fn loaded_column(&self, x: i32, z: i32) -> Option<Vec<&Chunk>> {
+        column_sections(&self.chunks, self.section_range(), x, z)
+    }
+
+    /// Whether every section of the column at `x`, `z` is loaded, so reading it won't wait.
+    pub fn is_column_loaded(&self, x: i32, z: i32) -> bool {
+        self.loaded_column(x, z).is_some()
+    }
+
+    /// Loads every section of the column at `x`, `z` in the background and calls `callback` once
+    /// they're all in, or with the first section that couldn't be loaded. `load_column` doesn't
+    /// wait after that, unless something unloads part of the column in the meantime.
+    pub fn request_column(&mut self, x: i32, z: i32, callback: ChunkCallback) {
+        let callback = Arc::new(Mutex::new(Some(callback)));
+        for y in self.section_range() {
+            let callback = callback.clone();
+            self.request_chunk(x, y, z, Box::new(move |world, result| {
+                if result.is_ok() && !world.is_column_loaded(x, z) {
+                    return;
+                }
+                let callback = callback.lock().unwrap_or_else(|e| e.into_inner()).take();
+                if let Some(callback) = callback {
+                    callback(world, result);
+                }
+            }));
+        }
+    }
+
+    /// Heightmaps of the column at `x`, `z`, loading it if needed.
+    pub fn column_heightmaps(&mut self, x: i32, z: i32) -> Result<&ColumnHeightmaps, WorldError> {
+        self.load_column(x, z)?;
+        Ok(&self.heightmaps[&(x, z)])
+    }
+
+    /// The block entity at `x`, `y`, `z`, loading its chunk if needed.
+    pub fn block_entity(&mut self, x: i64, y: i64, z: i64) -> Result<Option<&BlockEntity>, WorldError> {
+        if !self.contains_y(y) {
+            return Ok(None);
+        }
+        let chunk = self.load_chunk(x.div_euclid(16) as i32, y.div_euclid(16) as i32, z.div_euclid(16) as i32)?;
+        Ok(chunk.block_entity(x.rem_euclid(16) as u8, y.rem_euclid(16) as u8, z.rem_euclid(16) as u8))
+    }
+
+    /// Replaces the block entity at `x`, `y`, `z` and lets players know, unless it doesn't fit the
+    /// block there; gives back whether it did.
+    pub fn set_block_entity(&mut self, x: i64, y: i64, z: i64, entity: BlockEntity) -> Result<bool, WorldError> {
+        if !self.contains_y(y) {
+            return Err(WorldError::OutOfHeight(y));
+        }
+        let chunk = self.load_chunk(x.div_euclid(16) as i32, y.div_euclid(16) as i32, z.div_euclid(16) as i32)?;
+        let set = chunk.set_block_entity(x.rem_euclid(16) as u8, y.rem_euclid(16) as u8, z.rem_euclid(16) as u8, entity);
+        if set && !self.block_entity_updates.contains(&(x, y, z)) {
+            self.block_entity_updates.push((x, y, z));
+        }
+        Ok(set)
+    }
+
+    /// Positions of the block entities changed since the last call, in the order they changed.
+    pub fn take_block_entity_updates(&mut self) -> Vec<(i64, i64, i64)> {
+        std::mem::take(&mut self.block_entity_updates)
+    }
+
+    /// Where players appear: on top of the highest block at the middle of column 0, 0.
+    pub fn spawn_point(&mut self) -> Result<(f64, f64, f64), WorldError> {
+        let y = match self.top_y(0, 0, HeightmapKind::MotionBlocking)? {
+            Some(top) => top + 1,
+            None => self.min_y().max(SPAWN_Y_FALLBACK)
+        };
+        Ok((0.5, y as f64, 0.5))
+    }
+
+    /// Y of the highest block of `kind` at block `x`, `z`, like where to put a player down, or
+    /// `None` if the column has none.
+    pub fn top_y(&mut self, x: i64, z: i64, kind: HeightmapKind) -> Result<Option<i64>, WorldError> {
+        let min_y = self.min_y();
+        let maps = self.column_heightmaps(x.div_euclid(16) as i32, z.div_euclid(16) as i32)?;
+        let height = maps.get(kind).get(x.rem_euclid(16) as usize, z.rem_euclid(16) as usize);
+        Ok(height.checked_sub(1).map(|h| min_y + h as i64))
+    }
+
+    /// Brings the column heightmaps up to date after the block at `x`, `y`, `z` changed.
+    fn refresh_heightmaps(&mut self, x: i64, z: i64) {
+        let (cx, cz) = (x.div_euclid(16) as i32, z.div_euclid(16) as i32);
+        if !self.heightmaps.contains_key(&(cx, cz)) {
+            return;
+        }
+        match column_sections(&self.chunks, self.section_range(), cx, cz) {
+            Some(sections) => {
+                let sections: Vec<_> = sections.iter().map(|c| c.heightmaps()).collect();
+                let maps = self.heightmaps.get_mut(&(cx, cz)).unwrap();
+                maps.refresh(&sections, x.rem_euclid(16) as usize, z.rem_euclid(16) as usize);
+            }
+            // part of the column was unloaded; it gets worked out again when it's loaded
+            None => { self.heightmaps.remove(&(cx, cz)); }
+        }
     }
 
-    pub fn load_chunk(&mut self, x: i32, y: i32, z: i32) -> &mut Chunk {
+    pub fn seed(&self) -> i64 {
+        self.seed
+    }
+
+    /// What clients are told the seed is: the first 8 bytes of its SHA-256, like vanilla, so
+    /// they can use it for biome blending without learning the seed itself.
+    pub fn hashed_seed(&self) -> i64 {
+        let digest = Sha256::digest(self.seed.to_le_bytes());
+        let mut first = [0u8; 8];
+        first.copy_from_slice(&digest[..8]);
+        i64::from_le_bytes(first)
+    }
+
+    /// Loads a chunk in the background and calls `callback` when it's ready. Loaded chunks call
+    /// back straight away, and asking for a chunk that's already on its way doesn't read it twice.
+    pub fn request_chunk(&mut self, x: i32, y: i32, z: i32, callback: ChunkCallback) {
         let pos = Vector3I(x as i64, y as i64, z as i64);
-        if !self.chunks.contains_key(&pos) {
-            let chunk = self.sync.load(x, y, z);
-            self.chunks.insert(pos, Loaded { chunk });
-        } else if let Unloaded = self.chunks.get(&pos).unwrap() {
-            let chunk = self.sync.load(x, y, z);
-            self.chunks.insert(pos, Loaded { chunk });
+        if let Some(Loaded { .. }) = self.chunks.get(&pos) {
+            callback(self, Ok(()));
+            return;
+        }
+
+        match self.waiting.get_mut(&pos) {
+            Some(callbacks) => callbacks.push(callback),
+            None => {
+                self.waiting.insert(pos, vec![callback]);
+                self.io.load(pos, false);
+            }
         }
-        self.get_chunk(x, y, z)
     }
 
-    pub fn unload_chunk(&mut self, x: i32, y: i32, z: i32) {
+    /// Loads a chunk, waiting for it if it isn't loaded yet. It jumps the queue, but prefer
+    /// `request_chunk` anywhere that mustn't stall.
+    pub fn load_chunk(&mut self, x: i32, y: i32, z: i32) -> Result<&mut Chunk, WorldError> {
         let pos = Vector3I(x as i64, y as i64, z as i64);
-        if self.chunks.contains_key(&pos) {
-            self.chunks.insert(pos, Unloaded);
+        if let Some(Loaded { .. }) = self.chunks.get(&pos) {
+            return Ok(self.get_chunk(x, y, z));
+        }
+
+        self.waiting.entry(pos).or_default();
+        self.io.load(pos, true);
+        loop {
+            let done = self.io.recv();
+            if let Some((failed, e)) = self.complete(done) {
+                if failed == pos { return Err(e); }
+            }
+            if let Some(Loaded { .. }) = self.chunks.get(&pos) {
+                return Ok(self.get_chunk(x, y, z));
+            }
         }
     }
+
+    /// Takes in every chunk the background threads have finished with and runs their callbacks.
+    /// Called once per tick.
+    pub fn poll_chunks(&mut self) {
+        while let Some(done) = self.io.try_recv() {
+            self.complete(done);
+        }
+    }
+
+    /// Tells the background threads where the players are, so the chunks nearest them load first.
+    pub fn set_player_chunks(&mut self, players: Vec<Vector3I>) {
+        self.io.set_players(players);
+    }
+
+    /// Handles a finished background request, giving back the position and error of a failed load.
+    fn complete(&mut self, done: Completed) -> Option<(Vector3I, WorldError)> {
+        match done {
+            Completed::Loaded(pos, Ok(chunk)) => {
+                // something may have put a chunk here in the meantime; that one is newer
+                if !matches!(self.chunks.get(&pos), Some(Loaded { .. })) {
+                    self.chunks.insert(pos, Loaded { chunk });
+                }
+                for callback in self.waiting.remove(&pos).unwrap_or_default() {
+                    callback(self, Ok(()));
+                }
+                None
+            }
+            Completed::Loaded(pos, Err(e)) => {
+                for callback in self.waiting.remove(&pos).unwrap_or_default() {
+                    callback(self, Err(&e));
+                }
+                Some((pos, e))
+            }
+            Completed::Saved(_, Ok(())) => None,
+            Completed::Saved(pos, Err((e, chunk))) => {
+                log::error!("Could not save chunk [{},{},{}]: {}", chunk.x, chunk.y, chunk.z, e);
+                // keep the chunk in memory; dropping it would lose the changes
+                match self.chunks.get_mut(&pos) {
+                    Some(Loaded { chunk }) => chunk.dirty = true,
+                    _ => {
+                        let mut chunk = chunk;
+                        chunk.dirty = true;
+                        self.chunks.insert(pos, Loaded { chunk });
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// Drops a chunk from memory, saving it in the background first if it was changed. A failed
+    /// save brings the chunk back as loaded.
+    pub fn unload_chunk(&mut self, x: i32, y: i32, z: i32) -> Result<(), WorldError> {
+        let pos = Vector3I(x as i64, y as i64, z as i64);
+        if let Some(Loaded { .. }) = self.chunks.get(&pos) {
+            self.heightmaps.remove(&(x, z));
+            if let Some(Loaded { chunk }) = self.chunks.insert(pos, Unloaded) {
+                if chunk.dirty {
+                    self.io.save(chunk);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes every dirty loaded chunk to the syncer, returning how many were written. Saves of
+    /// them still waiting in the background are dropped, since these copies are newer.
+    pub fn save_all(&mut self) -> usize {
+        let start = Instant::now();
+        let mut saved = vec![];
+        let dirty = self.chunks.iter()
+            .filter(|(_, state)| matches!(state, Loaded { chunk } if chunk.dirty))
+            .map(|(pos, _)| *pos)
+            .collect();
+        let mut sync = self.io.syncer_for(&dirty);
+
+        for (pos, state) in self.chunks.iter_mut() {
+            if let Loaded { chunk } = state {
+                if chunk.dirty {
+                    match sync.save(chunk) {
+                        Ok(()) => {
+                            chunk.dirty = false;
+                            saved.push(*pos);
+                        }
+                        Err(e) => log::error!("Could not save chunk [{},{},{}]: {}", chunk.x, chunk.y, chunk.z, e)
+                    }
+                }
+            }
+        }
+
+        if let Err(e) = sync.flush() {
+            log::error!("Could not flush world: {}", e);
+            // nothing written this pass is durable, so try all of it again next time
+            for pos in &saved {
+                if let Some(Loaded { chunk }) = self.chunks.get_mut(pos) {
+                    chunk.dirty = true;
+                }
+            }
+            return 0;
+        }
+
+        log::info!("Saved {} chunks in {:?}", saved.len(), start.elapsed());
+        saved.len()
+    }
+
+    /// Puts the world back the way its syncer was opened, like a minigame map made from a
+    /// read-only template, throwing away every chunk changed since along with the entities.
+    /// Gives back false, changing nothing, if the syncer can't. Players should be moved out
+    /// first; they aren't sent the old chunks again.
+    pub fn reset(&mut self) -> Result<bool, WorldError> {
+        // let loads already on their way land first, so none of them brings back a changed chunk
+        while !self.waiting.is_empty() {
+            let done = self.io.recv();
+            self.complete(done);
+        }
+        if !self.io.reset() {
+            return Ok(false);
+        }
+        // whatever the saves still had to say is about chunks that are gone now
+        while self.io.try_recv().is_some() {}
+
+        let poses = self.io.syncer().find_all()?;
+        self.chunks = poses.into_iter().map(|p| (p, Unloaded)).collect();
+        self.heightmaps.clear();
+        self.block_entity_updates.clear();
+        self.block_changes.clear();
+        self.viewers.clear();
+        self.entities = EntityStore::new();
+        Ok(true)
+    }
+}
+
+fn column_sections(chunks: &HashMap<Vector3I, ChunkLoadState>, sections: Range<i32>, x: i32, z: i32) -> Option<Vec<&Chunk>> {
+    sections.map(|y| {
+        match chunks.get(&Vector3I(x as i64, y as i64, z as i64)) {
+            Some(Loaded { chunk }) => Some(chunk),
+            _ => None
+        }
+    }).collect()
 }
 
 impl ChunkContainer for World {
@@ -162,15 +1151,29 @@ impl ChunkContainer for World {
 
     fn put_chunk(&mut self, x: i32, y: i32, z: i32, chunk: Chunk) {
         let pos = Vector3I(x as i64, y as i64, z as i64);
+        self.heightmaps.remove(&(x, z));
 
         self.chunks.insert(pos, Loaded { chunk });
     }
 
-    fn get(&mut self, x: i64, y: i64, z: i64) -> Block {
-        todo!()
+    fn get(&mut self, x: i64, y: i64, z: i64) -> Result<Block, WorldError> {
+        if !self.contains_y(y) {
+            return Ok(Block::Air);
+        }
+        Ok(self.load_chunk(x.div_euclid(16) as i32, y.div_euclid(16) as i32, z.div_euclid(16) as i32)?
+            .get(x.rem_euclid(16), y.rem_euclid(16), z.rem_euclid(16)))
     }
 
-    fn put(&mut self, x: i64, y: i64, z: i64, block: Block) {
-        todo!()
+    fn put(&mut self, x: i64, y: i64, z: i64, block: Block) -> Result<(), WorldError> {
+        if !self.contains_y(y) {
+            return Err(WorldError::OutOfHeight(y));
+        }
+        self.load_chunk(x.div_euclid(16) as i32, y.div_euclid(16) as i32, z.div_euclid(16) as i32)?
+            .put(x.rem_euclid(16), y.rem_euclid(16), z.rem_euclid(16), block);
+        self.refresh_heightmaps(x, z);
+        self.relight(x, y, z);
+        let index = (y.rem_euclid(16) << 8 | z.rem_euclid(16) << 4 | x.rem_euclid(16)) as u16;
+        self.block_changes.entry(Vector3I(x.div_euclid(16), y.div_euclid(16), z.div_euclid(16))).or_default().insert(index);
+        Ok(())
     }
 }
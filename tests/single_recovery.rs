@@ -0,0 +1,260 @@
+#![cfg(feature = "single")]
+
+use byteorder::{BigEndian, ByteOrder};
+use grimstone::memory::OverlayWorldSyncer;
+use grimstone::single::SingleWorldFile;
+use grimstone::world::{WorldSyncer, WorldMeta, Chunk, Block, BlockContainer};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Bytes covered by the magic, version and both header slots.
+const HEADER_LEN: usize = 72;
+
+type State = HashMap<(i32, i32, i32), Vec<Block>>;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("grimstone-{}-{}.sng", name, std::process::id()))
+}
+
+fn chunk(x: i32, y: i32, z: i32, seed: usize) -> Chunk {
+    let mut chunk = Chunk::new_empty(x, y, z);
+    for i in 0..(16 * 16 * 16) {
+        // vary the amount of data so payloads shrink and grow between saves
+        if (i * 7 + seed).is_multiple_of(seed + 2) {
+            let block = Block::ALL[(i + seed) % Block::ALL.len()];
+            chunk.put((i % 16) as i64, (i / 256) as i64, ((i / 16) % 16) as i64, block);
+        }
+    }
+    chunk
+}
+
+fn blocks_of(chunk: &mut Chunk) -> Vec<Block> {
+    let mut blocks = vec![];
+    for y in 0..16 { for z in 0..16 { for x in 0..16 {
+        blocks.push(chunk.get(x, y, z));
+    } } }
+    blocks
+}
+
+fn save(sync: &mut SingleWorldFile, state: &mut State, mut chunk: Chunk) {
+    state.insert((chunk.x, chunk.y, chunk.z), blocks_of(&mut chunk));
+    sync.save(&mut chunk).unwrap();
+}
+
+fn read_state(sync: &mut SingleWorldFile) -> State {
+    let mut state = State::new();
+    for pos in sync.find_all().unwrap() {
+        let (x, y, z) = (pos.0 as i32, pos.1 as i32, pos.2 as i32);
+        state.insert((x, y, z), blocks_of(&mut sync.load(x, y, z).unwrap()));
+    }
+    state
+}
+
+/// Saves two generations and returns the file after each along with what it should contain.
+fn two_generations(path: &PathBuf) -> (Vec<u8>, State, Vec<u8>, State) {
+    let _ = std::fs::remove_file(path);
+    let mut state = State::new();
+
+    let mut sync = SingleWorldFile::new(path.to_str().unwrap()).unwrap();
+    save(&mut sync, &mut state, chunk(0, 0, 0, 1));
+    save(&mut sync, &mut state, chunk(1, 0, 0, 9));
+    save(&mut sync, &mut state, chunk(0, 1, -1, 3));
+    sync.flush().unwrap();
+    let before = std::fs::read(path).unwrap();
+    let before_state = state.clone();
+
+    save(&mut sync, &mut state, chunk(0, 0, 0, 14)); // shrinks
+    save(&mut sync, &mut state, chunk(1, 0, 0, 0)); // grows
+    save(&mut sync, &mut state, chunk(-4, 2, 7, 5)); // new
+    sync.flush().unwrap();
+    drop(sync);
+    let after = std::fs::read(path).unwrap();
+
+    (before, before_state, after, state)
+}
+
+/// Crashes the second save of `two_generations` after each of `points` of its writes and checks
+/// the file reopens as one generation or the other.
+fn check_interrupted_save(name: &str, points: impl Fn(&[usize]) -> Vec<usize>) {
+    let path = temp_path(name);
+    let (before, before_state, after, after_state) = two_generations(&path);
+
+    // A save writes data into free space first and the header slot last, so a crash leaves
+    // some prefix of those writes on disk.
+    let mut writes = vec![];
+    let mut header = vec![];
+    for (i, byte) in after.iter().enumerate() {
+        if before.get(i) != Some(byte) {
+            if i < HEADER_LEN { header.push(i) } else { writes.push(i) }
+        }
+    }
+    assert!(!header.is_empty());
+    writes.extend(header);
+
+    for done in points(&writes) {
+        let mut image = before.clone();
+        for &i in &writes[..done] {
+            if i >= image.len() { image.resize(i + 1, 0); }
+            image[i] = after[i];
+        }
+        std::fs::write(&path, &image).unwrap();
+
+        let mut sync = SingleWorldFile::new(path.to_str().unwrap())
+            .unwrap_or_else(|e| panic!("crash after {} of {} bytes: {}", done, writes.len(), e));
+        let state = read_state(&mut sync);
+        if done == writes.len() {
+            assert!(state == after_state, "complete save wasn't kept");
+        } else {
+            assert!(state == before_state, "crash after {} of {} bytes didn't roll back", done, writes.len());
+        }
+    }
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn interrupted_save_rolls_back() {
+    check_interrupted_save("interrupted", |writes| {
+        // every byte of the header slot, both sides of each jump between the payloads, the
+        // index and the header, and a fixed sample of everything in between
+        let mut points = vec![0, writes.len()];
+        for k in 1..writes.len() {
+            if writes[k] != writes[k - 1] + 1 || writes[k] < HEADER_LEN {
+                points.extend(&[k - 1, k, k + 1]);
+            }
+        }
+        let mut seed = 0x2545_f491_u64;
+        for _ in 0..64 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            points.push((seed >> 33) as usize % writes.len());
+        }
+        points.sort_unstable();
+        points.dedup();
+        points
+    });
+}
+
+#[test]
+#[ignore = "reopens the file once per byte written; takes minutes"]
+fn interrupted_save_rolls_back_at_every_byte() {
+    check_interrupted_save("interrupted-every-byte", |writes| (0..=writes.len()).collect());
+}
+
+/// Reads the live slot and its index straight from the file and returns every extent in use:
+/// the index table itself followed by each chunk's payload.
+fn live_extents(file: &[u8]) -> Vec<(u64, u64)> {
+    let u32_at = |i: usize| BigEndian::read_u32(&file[i..]);
+    let u64_at = |i: usize| BigEndian::read_u64(&file[i..]);
+
+    let slot = [8, 40].iter().copied()
+        .filter(|&s| crc32fast::hash(&file[s..s + 24]) == u32_at(s + 24))
+        .max_by_key(|&s| u64_at(s))
+        .expect("no valid slot");
+    let (index, index_len) = (u64_at(slot + 8) as usize, u32_at(slot + 16) as u64);
+
+    let mut extents = vec![(index as u64, index_len)];
+    for entry in 0..u32_at(index) as usize {
+        let at = index + 4 + entry * 28;
+        extents.push((u64_at(at + 12), u32_at(at + 20) as u64));
+    }
+    extents
+}
+
+#[test]
+fn failed_flush_can_be_retried_without_reusing_space() {
+    let path = temp_path("retry");
+    let _ = std::fs::remove_file(&path);
+    let mut state = State::new();
+
+    let mut sync = SingleWorldFile::new(path.to_str().unwrap()).unwrap();
+    save(&mut sync, &mut state, chunk(0, 0, 0, 1));
+    save(&mut sync, &mut state, chunk(1, 0, 0, 9));
+    sync.flush().unwrap();
+
+    save(&mut sync, &mut state, chunk(0, 0, 0, 14));
+    for _ in 0..3 {
+        sync.fail_next_flush();
+        assert!(sync.flush().is_err());
+    }
+    sync.flush().unwrap();
+
+    // anything freed twice would now be handed out to more than one of these
+    for seed in 0..6 {
+        save(&mut sync, &mut state, chunk(seed as i32, 2, 0, seed));
+        sync.flush().unwrap();
+    }
+    drop(sync);
+
+    let mut extents = live_extents(&std::fs::read(&path).unwrap());
+    extents.sort();
+    assert!(extents[0].0 >= HEADER_LEN as u64);
+    for pair in extents.windows(2) {
+        assert!(pair[0].0 + pair[0].1 <= pair[1].0, "{:?} overlaps {:?}", pair[0], pair[1]);
+    }
+
+    let mut sync = SingleWorldFile::new(path.to_str().unwrap()).unwrap();
+    assert!(read_state(&mut sync) == state);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn truncated_file_never_mixes_generations() {
+    let path = temp_path("truncated");
+    let (_, before_state, after, after_state) = two_generations(&path);
+
+    for len in 0..after.len() {
+        std::fs::write(&path, &after[..len]).unwrap();
+
+        // losing committed data can't always be recovered from, but it must never panic or
+        // hand back a world that's half one generation and half another
+        if let Ok(mut sync) = SingleWorldFile::new(path.to_str().unwrap()) {
+            let state = read_state(&mut sync);
+            assert!(state == before_state || state == after_state,
+                "truncating to {} bytes gave a mixed world", len);
+        }
+    }
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn reopening_after_a_clean_save_keeps_everything() {
+    let path = temp_path("clean");
+    let (_, _, _, after_state) = two_generations(&path);
+
+    let mut sync = SingleWorldFile::new(path.to_str().unwrap()).unwrap();
+    assert!(read_state(&mut sync) == after_state);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn read_only_overlay_never_touches_the_file() {
+    let path = temp_path("readonly");
+    let (before, before_state, after, _) = two_generations(&path);
+
+    // half of the second save made it, along with junk past the end that opening writable cuts off
+    let mut image = after[..before.len().max(after.len() / 2)].to_vec();
+    image[..HEADER_LEN].copy_from_slice(&before[..HEADER_LEN]);
+    image.extend_from_slice(&[0xAB; 100]);
+    std::fs::write(&path, &image).unwrap();
+
+    let mut sync = OverlayWorldSyncer::<SingleWorldFile>::new(path.to_str().unwrap()).unwrap();
+    let mut changed = chunk(0, 0, 0, 6);
+    sync.save(&mut changed).unwrap();
+    sync.save_meta(&WorldMeta { seed: Some(7) }).unwrap();
+    sync.flush().unwrap();
+    assert!(sync.template().save(&mut chunk(5, 5, 5, 1)).is_err());
+    assert!(sync.reset());
+    let mut state = State::new();
+    for pos in sync.find_all().unwrap() {
+        let (x, y, z) = (pos.0 as i32, pos.1 as i32, pos.2 as i32);
+        state.insert((x, y, z), blocks_of(&mut sync.load(x, y, z).unwrap()));
+    }
+    assert!(state == before_state, "reset didn't go back to the template");
+    drop(sync);
+
+    assert!(std::fs::read(&path).unwrap() == image, "the template was written to");
+    let _ = std::fs::remove_file(&path);
+}